@@ -0,0 +1,12 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        // no protoc has to be installed, the vendored one is used
+        std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+        // clients generate their own stubs from the proto file
+        tonic_prost_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/btree.proto"], &["proto"])
+            .unwrap();
+    }
+}
@@ -1,7 +1,7 @@
 use btree::BTree;
 
 fn main() {
-    let mut btree = BTree::<u64, f64>::new("./testfloat.btree");
+    let btree = BTree::<u64, f64>::new("./testfloat.btree");
     // btree.set(&1, &10.23f64).unwrap();
     // btree.set(&2, &99.9f64).unwrap();
     //
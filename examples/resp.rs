@@ -0,0 +1,162 @@
+//! serves a tree file over a small subset of the redis protocol: PING, GET, SET, DEL and SCAN.
+//! connections are handled one after another, which is plenty for prototyping.
+//!
+//!     cargo run --example resp -- ./resp.btree 127.0.0.1:6380
+//!     redis-cli -p 6380 set hello world
+
+use btree::*;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+
+define_fixed_len_str!(Key, 64);
+// the overlay marks deleted keys in a byte of its own, so values stay a byte short of MAX_VALUE_SIZE
+define_fixed_len_str!(Value, 1023);
+
+// keys handed out per SCAN call unless the client asks for a COUNT
+const SCAN_COUNT: usize = 10;
+
+enum Reply {
+    Simple(&'static str),
+    Error(String),
+    Int(usize),
+    Bulk(Option<String>),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        match self {
+            Reply::Simple(s) => write!(out, "+{}\r\n", s),
+            Reply::Error(e) => write!(out, "-ERR {}\r\n", e),
+            Reply::Int(n) => write!(out, ":{}\r\n", n),
+            Reply::Bulk(None) => write!(out, "$-1\r\n"),
+            Reply::Bulk(Some(s)) => write!(out, "${}\r\n{}\r\n", s.len(), s),
+            Reply::Array(items) => {
+                write!(out, "*{}\r\n", items.len())?;
+                items.iter().try_for_each(|item| item.write_to(out))
+            }
+        }
+    }
+}
+
+/// reads one command, either a resp array of bulk strings or an inline line of words
+fn read_command(reader: &mut impl BufRead) -> io::Result<Option<Vec<String>>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let line = line.trim_end();
+    if !line.starts_with('*') {
+        return Ok(Some(line.split_whitespace().map(str::to_owned).collect()));
+    }
+    let n: usize = line[1..].parse().map_err(|_| invalid("bad array length"))?;
+    let mut args = Vec::with_capacity(n);
+    for _ in 0..n {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let len: usize = header.trim_end().strip_prefix('$')
+            .and_then(|l| l.parse().ok())
+            .ok_or_else(|| invalid("expected a bulk string"))?;
+        let mut buf = vec![0; len + 2];
+        reader.read_exact(&mut buf)?;
+        buf.truncate(len);
+        args.push(String::from_utf8(buf).map_err(|_| invalid("arguments must be utf-8"))?);
+    }
+    Ok(Some(args))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn key(s: &str) -> Result<Key, String> {
+    if s.len() > Key::bin_size() {
+        return Err(format!("keys are limited to {} bytes", Key::bin_size()));
+    }
+    Ok(Key::new(s))
+}
+
+fn execute(tree: &mut Overlay<Key, Value>, args: &[String]) -> Reply {
+    let result = match args[0].to_ascii_uppercase().as_str() {
+        "PING" => Ok(Reply::Simple("PONG")),
+        // redis-cli asks for the command table when it starts
+        "COMMAND" => Ok(Reply::Array(Vec::new())),
+        "GET" if args.len() == 2 => {
+            key(&args[1]).map(|k| Reply::Bulk(tree.get(&k).map(|v| v.0)))
+        }
+        "SET" if args.len() == 3 => {
+            if args[2].len() > Value::bin_size() {
+                Err(format!("values are limited to {} bytes", Value::bin_size()))
+            } else {
+                key(&args[1]).and_then(|k| {
+                    tree.set(&k, &Value::new(&args[2])).map_err(|e| e.to_string())
+                }).map(|_| Reply::Simple("OK"))
+            }
+        }
+        "DEL" if args.len() >= 2 => {
+            let mut removed = 0;
+            args[1..].iter().try_for_each(|a| {
+                let k = key(a)?;
+                if tree.get(&k).is_some() {
+                    tree.remove(&k).map_err(|e| e.to_string())?;
+                    removed += 1;
+                }
+                Ok(())
+            }).map(|_| Reply::Int(removed))
+        }
+        "SCAN" if args.len() >= 2 => scan(tree, args),
+        _ => Err(format!("unknown command or wrong number of arguments for '{}'", args[0]))
+    };
+    result.unwrap_or_else(Reply::Error)
+}
+
+/// the cursor is the number of keys already handed out, it comes back as 0 once the scan is done
+fn scan(tree: &Overlay<Key, Value>, args: &[String]) -> Result<Reply, String> {
+    let cursor: usize = args[1].parse().map_err(|_| "invalid cursor".to_owned())?;
+    let mut count = SCAN_COUNT;
+    for option in args[2..].chunks(2) {
+        match (option[0].to_ascii_uppercase().as_str(), option.get(1)) {
+            ("COUNT", Some(n)) => count = n.parse().map_err(|_| "invalid COUNT".to_owned())?,
+            _ => return Err("only COUNT is supported".to_owned())
+        }
+    }
+    let mut keys: Vec<Reply> = tree.iter()
+        .skip(cursor)
+        .take(count + 1)
+        .map(|(k, _)| Reply::Bulk(Some(k.0)))
+        .collect();
+    // one key past the page tells whether there is anything left
+    let next = if keys.len() > count { cursor + count } else { 0 };
+    keys.truncate(count);
+    Ok(Reply::Array(vec![Reply::Bulk(Some(next.to_string())), Reply::Array(keys)]))
+}
+
+fn serve(tree: &mut Overlay<Key, Value>, stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = io::BufWriter::new(stream);
+    while let Some(args) = read_command(&mut reader)? {
+        if args.is_empty() {
+            continue;
+        }
+        execute(tree, &args).write_to(&mut writer)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let path = args.next().unwrap_or_else(|| "./resp.btree".to_owned());
+    let addr = args.next().unwrap_or_else(|| "127.0.0.1:6380".to_owned());
+    // an overlay without bases is just the tree, plus tombstones so DEL works
+    let mut tree = Overlay::<Key, Value>::new(path, &[] as &[PathBuf])?;
+    let listener = TcpListener::bind(&addr)?;
+    println!("listening on {}", addr);
+    for stream in listener.incoming() {
+        if let Err(e) = serve(&mut tree, stream?) {
+            println!("connection closed: {}", e);
+        }
+    }
+    Ok(())
+}
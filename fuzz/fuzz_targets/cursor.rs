@@ -0,0 +1,96 @@
+//! flips bytes of a tree file whose values spill to overflow pages, sealing the pages again so
+//! the damage gets past their checksums, then walks it with every kind of iterator
+#![no_main]
+use btree::*;
+use libfuzzer_sys::fuzz_target;
+use std::fs;
+use std::sync::OnceLock;
+
+const PAGE_SIZE: usize = 512;
+
+define_fixed_len_str!(Name, 12);
+
+// a value too large for two of them to share a leaf, so every one of them spills
+type Blob = FixedBytes<300>;
+
+fn name(i: u32) -> Name {
+    Name::new(&format!("k{:05}", i))
+}
+
+// a tree a few levels deep with an overflow chain per entry, built once
+fn template() -> &'static [u8] {
+    static TEMPLATE: OnceLock<Vec<u8>> = OnceLock::new();
+    TEMPLATE.get_or_init(|| {
+        let path = std::env::temp_dir().join(format!("btree-fuzz-cursor-template-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        {
+            let mut tree = BTree::<Name, Blob>::with_page_size(&path, PAGE_SIZE);
+            for i in 0..300u32 {
+                tree.set(&name(i.wrapping_mul(2654435761) % 1009), &Blob::new(&i.to_be_bytes())).unwrap();
+            }
+        }
+        let bytes = fs::read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        bytes
+    })
+}
+
+// the checksum the tree keeps in the first four bytes of each page, as it computes it
+fn seal(index: u32, page: &mut [u8]) {
+    if page[0] & 0x10 == 0 {
+        return;
+    }
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&index.to_be_bytes());
+    hasher.update(&page[..1]);
+    hasher.update(&page[4..]);
+    let crc = (hasher.finalize() & 0x00ff_ffff).to_be_bytes();
+    page[1..4].copy_from_slice(&crc[1..]);
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut file = template().to_vec();
+    // each 4 bytes of input write their last byte at the offset the first three give
+    for patch in data.chunks_exact(4) {
+        let offset = u32::from_be_bytes([0, patch[0], patch[1], patch[2]]) as usize % file.len();
+        file[offset] = patch[3];
+        let index = offset / PAGE_SIZE;
+        seal(index as u32, &mut file[index * PAGE_SIZE..(index + 1) * PAGE_SIZE]);
+    }
+
+    let path = std::env::temp_dir().join(format!("btree-fuzz-cursor-{}", std::process::id()));
+    fs::write(&path, &file).unwrap();
+    if let Ok(tree) = BTree::<Name, Blob>::open_read_only(&path) {
+        let mut iter = tree.iter();
+        while iter.next().is_some() {}
+        let _ = iter.error();
+
+        let mut iter = tree.range(name(100)..=name(900));
+        // both ends at once, meeting in the middle
+        loop {
+            let front = iter.next();
+            let back = iter.next_back();
+            if front.is_none() && back.is_none() {
+                break;
+            }
+        }
+        let _ = iter.error();
+        let _ = tree.range(..name(500)).rev().count();
+
+        let mut scan = tree.scan(name(200)..);
+        while scan.next().is_some() {}
+        let _ = scan.error();
+
+        let mut filtered = tree.scan_filtered(.., |key, _| key.len() > 4 && key[4] % 2 == 0);
+        while filtered.next().is_some() {}
+        let _ = filtered.error();
+
+        let mut streaming = tree.streaming_iter(name(10)..name(1000));
+        while streaming.next().is_some() {}
+        let _ = streaming.error();
+
+        let _ = tree.first();
+        let _ = tree.last();
+    }
+    let _ = fs::remove_file(&path);
+});
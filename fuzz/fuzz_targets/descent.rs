@@ -0,0 +1,65 @@
+//! flips bytes of a real multi level tree file, then reads and writes through it
+#![no_main]
+use btree::*;
+use libfuzzer_sys::fuzz_target;
+use std::fs;
+use std::sync::OnceLock;
+
+const PAGE_SIZE: usize = 512;
+
+// a tree a few levels deep, built once
+fn template() -> &'static [u8] {
+    static TEMPLATE: OnceLock<Vec<u8>> = OnceLock::new();
+    TEMPLATE.get_or_init(|| {
+        let path = std::env::temp_dir().join(format!("btree-fuzz-template-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        {
+            let mut tree = BTree::<u32, u64>::with_page_size(&path, PAGE_SIZE);
+            for i in 0..5000u32 {
+                tree.set(&(i.wrapping_mul(2654435761) % 10007), &(i as u64)).unwrap();
+            }
+        }
+        let bytes = fs::read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        bytes
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut file = template().to_vec();
+    // each 4 bytes of input write their last byte at the offset the first three give
+    for patch in data.chunks_exact(4) {
+        let offset = u32::from_be_bytes([0, patch[0], patch[1], patch[2]]) as usize % file.len();
+        file[offset] = patch[3];
+    }
+    // inputs not a multiple of 4 also cut the file short
+    if !data.len().is_multiple_of(4) {
+        let cut = data.len() * PAGE_SIZE % file.len();
+        file.truncate(cut);
+    }
+
+    let path = std::env::temp_dir().join(format!("btree-fuzz-descent-{}", std::process::id()));
+    fs::write(&path, &file).unwrap();
+    if let Ok(tree) = BTree::<u32, u64>::open_read_only(&path) {
+        let _ = tree.range(..).count();
+        for k in (0..10007u32).step_by(97) {
+            let _ = tree.try_get(&k);
+        }
+        let _ = tree.key_histogram(8);
+    }
+    if let Ok(mut tree) = BTree::<u32, u64>::try_new(&path) {
+        for k in (0..2000u32).step_by(7) {
+            if tree.set(&k, &0).is_err() {
+                break;
+            }
+        }
+        for k in (0..10007u32).step_by(3) {
+            if tree.remove(&k).is_err() {
+                break;
+            }
+        }
+        let _ = tree.write_value_at(&14, 0, &[1]);
+        let _ = tree.reclaim_orphans();
+    }
+    let _ = fs::remove_file(&path);
+});
@@ -0,0 +1,33 @@
+//! treats the input as the root page of a one page tree and reads it every way the tree can
+#![no_main]
+use btree::*;
+use libfuzzer_sys::fuzz_target;
+use std::fs;
+
+const PAGE_SIZE: usize = 512;
+
+define_fixed_len_str!(Name, 12);
+
+fuzz_target!(|data: &[u8]| {
+    let mut file = vec![0u8; 2 * PAGE_SIZE];
+    // a meta page with the root at page 1, of a file recording no key and value sizes
+    file[0] = 0x01;
+    file[4..8].copy_from_slice(&1u32.to_be_bytes());
+    file[8..12].copy_from_slice(&2u32.to_be_bytes());
+    file[64..68].copy_from_slice(&(PAGE_SIZE as u32).to_be_bytes());
+    let len = data.len().min(PAGE_SIZE);
+    file[PAGE_SIZE..PAGE_SIZE + len].copy_from_slice(&data[..len]);
+
+    let path = std::env::temp_dir().join(format!("btree-fuzz-page-{}", std::process::id()));
+    fs::write(&path, &file).unwrap();
+    if let Ok(tree) = BTree::<Name, u32>::open_read_only(&path) {
+        let keys: Vec<Name> = tree.range(..).map(|(k, _)| k).collect();
+        for k in keys.iter() {
+            let _ = tree.try_get(k);
+        }
+        let _ = tree.try_get(&Name::new("fuzz"));
+        let _ = tree.scan(Name::new("a")..Name::new("z")).count();
+        let _ = tree.key_histogram(4);
+    }
+    let _ = fs::remove_file(&path);
+});
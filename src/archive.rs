@@ -0,0 +1,196 @@
+use crate::build::Builder;
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::page::PAGE_SIZE;
+use crate::BTree;
+use anyhow::{anyhow, Result};
+use std::fmt::Debug;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const ARCHIVE_MAGIC: &[u8; 8] = b"BTREEARC";
+/// the archive layout `export_archive` writes, older ones stay readable
+pub const ARCHIVE_VERSION: u8 = 1;
+const FLAG_COMPRESSED: u8 = 0x01;
+// every entry is preceded by ENTRY, the trailer by END
+const ENTRY: u8 = 1;
+const END: u8 = 0;
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    /// streams every entry into `writer` as an archive that knows nothing of pages: a header
+    /// recording the format version and the encoded key and value sizes, the encoded entries in
+    /// key order, and a trailer with their count and crc. returns how many entries were written
+    pub fn export_archive<W: Write>(&self, writer: W) -> Result<usize, BTreeError> {
+        let mut writer = BufWriter::new(writer);
+        write_header::<K, V, _>(&mut writer, 0)?;
+        let count = self.write_entries(&mut writer)?;
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// like `export_archive`, with everything after the header zlib compressed
+    #[cfg(feature = "compression")]
+    pub fn export_archive_compressed<W: Write>(&self, writer: W) -> Result<usize, BTreeError> {
+        let mut writer = BufWriter::new(writer);
+        write_header::<K, V, _>(&mut writer, FLAG_COMPRESSED)?;
+        let mut encoder = flate2::write::ZlibEncoder::new(writer, flate2::Compression::default());
+        let count = self.write_entries(&mut encoder)?;
+        encoder.finish()?.flush()?;
+        Ok(count)
+    }
+
+    fn write_entries<W: Write>(&self, writer: &mut W) -> Result<usize> {
+        let mut hasher = crc32fast::Hasher::new();
+        let mut count = 0u64;
+        let mut entries = self.streaming_iter(..);
+        while let Some((k, v)) = entries.next() {
+            writer.write_all(&[ENTRY])?;
+            writer.write_all(k)?;
+            writer.write_all(v)?;
+            hasher.update(k);
+            hasher.update(v);
+            count += 1;
+        }
+        writer.write_all(&[END])?;
+        writer.write_all(&count.to_be_bytes())?;
+        writer.write_all(&hasher.finalize().to_be_bytes())?;
+        Ok(count as usize)
+    }
+
+    /// bulk builds a new tree file at `path` from an archive `export_archive` wrote, with the
+    /// default page size whatever the exporting tree used. returns the tree and its entry count.
+    /// an archive that is cut short or fails its trailer leaves no file at `path`
+    pub fn import_archive<P: AsRef<Path>, R: Read>(path: P, reader: R) -> Result<(Self, usize), BTreeError> {
+        let mut reader = BufReader::new(reader);
+        let flags = read_header::<K, V, _>(&mut reader)?;
+        let count = if flags & FLAG_COMPRESSED != 0 {
+            Self::read_entries(path.as_ref(), &mut decompress(reader)?)?
+        } else {
+            Self::read_entries(path.as_ref(), &mut reader)?
+        };
+        Ok((BTree::open(path)?, count))
+    }
+
+    fn read_entries<R: Read>(path: &Path, reader: &mut R) -> Result<usize> {
+        let mut builder = Builder::<K, V>::create(path, PAGE_SIZE)?;
+        let mut hasher = crc32fast::Hasher::new();
+        let mut key = vec![0u8; K::bin_size()];
+        let mut value = vec![0u8; V::bin_size()];
+        loop {
+            let mut tag = [0u8];
+            read_exact(reader, &mut tag)?;
+            match tag[0] {
+                ENTRY => {
+                    read_exact(reader, &mut key)?;
+                    read_exact(reader, &mut value)?;
+                    hasher.update(&key);
+                    hasher.update(&value);
+                    builder.push(&K::decode(&key)?.0, &V::decode(&value)?.0)?;
+                }
+                END => break,
+                tag => return Err(anyhow!("archive is corrupted: unknown entry tag {:#04x}", tag))
+            }
+        }
+        let mut trailer = [0u8; 12];
+        read_exact(reader, &mut trailer)?;
+        let count = u64::decode(&trailer)?.0 as usize;
+        let crc = u32::decode(&trailer[8..])?.0;
+        // checked before the tree is finished, dropping the builder takes the file away
+        if builder.count() != count {
+            return Err(anyhow!("archive is corrupted: {} entries read, the trailer records {}", builder.count(), count));
+        }
+        if hasher.finalize() != crc {
+            return Err(anyhow!("archive is corrupted: entries do not match the trailer crc"));
+        }
+        builder.finish()
+    }
+}
+
+fn write_header<K: BinSizer, V: BinSizer, W: Write>(writer: &mut W, flags: u8) -> Result<()> {
+    writer.write_all(ARCHIVE_MAGIC)?;
+    writer.write_all(&[ARCHIVE_VERSION, flags])?;
+    writer.write_all(&(K::bin_size() as u32).to_be_bytes())?;
+    writer.write_all(&(V::bin_size() as u32).to_be_bytes())?;
+    Ok(())
+}
+
+/// checks the archive holds entries of the tree's types and returns its flags
+fn read_header<K: BinSizer, V: BinSizer, R: Read>(reader: &mut R) -> Result<u8> {
+    let mut header = [0u8; 18];
+    read_exact(reader, &mut header)?;
+    if &header[..8] != ARCHIVE_MAGIC {
+        return Err(anyhow!("not a btree archive"));
+    }
+    let (version, flags) = (header[8], header[9]);
+    if version == 0 || version > ARCHIVE_VERSION {
+        return Err(anyhow!("archive version {} is not one this crate reads, it reads 1 to {}", version, ARCHIVE_VERSION));
+    }
+    if flags & !FLAG_COMPRESSED != 0 {
+        return Err(anyhow!("archive uses flags {:#04x} this crate does not know", flags));
+    }
+    let key_size = u32::decode(&header[10..])?.0 as usize;
+    let value_size = u32::decode(&header[14..])?.0 as usize;
+    if key_size != K::bin_size() || value_size != V::bin_size() {
+        return Err(anyhow!("the archive holds {} byte keys and {} byte values, the tree types have {} and {}",
+                           key_size, value_size, K::bin_size(), V::bin_size()));
+    }
+    Ok(flags)
+}
+
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<()> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => anyhow!("archive is truncated"),
+        _ => e.into()
+    })
+}
+
+#[cfg(feature = "compression")]
+fn decompress<R: Read>(reader: R) -> Result<impl Read> {
+    Ok(flate2::read::ZlibDecoder::new(reader))
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompress<R: Read>(_reader: R) -> Result<R> {
+    Err(anyhow!("the archive is compressed and btree was built without the compression feature"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BTree;
+    use std::fs;
+
+    #[test]
+    fn a_corrupt_archive_leaves_no_tree_and_a_retry_imports_it() {
+        let path = std::env::temp_dir().join(format!("btree-archive-test-{}", std::process::id()));
+        let imported = std::env::temp_dir().join(format!("btree-archive-test-{}-imported", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(&imported);
+        let mut archive = Vec::new();
+        {
+            let mut tree = BTree::<u32, u64>::open_or_create(&path).unwrap();
+            for i in 0..1000u32 {
+                tree.set(&i, &(i as u64)).unwrap();
+            }
+            assert_eq!(tree.export_archive(&mut archive).unwrap(), 1000);
+        }
+        // the last byte of the first value, past the 18 byte header, the tag and the key
+        let mut flipped = archive.clone();
+        flipped[18 + 1 + 4 + 7] ^= 0xff;
+        assert!(BTree::<u32, u64>::import_archive(&imported, &flipped[..]).is_err());
+        assert!(!imported.exists());
+        let cut = &archive[..archive.len() / 2];
+        assert!(BTree::<u32, u64>::import_archive(&imported, cut).is_err());
+        assert!(!imported.exists());
+        let (tree, count) = BTree::<u32, u64>::import_archive(&imported, &archive[..]).unwrap();
+        assert_eq!(count, 1000);
+        assert_eq!(tree.get(&0), Some(0));
+        assert!(tree.verify().unwrap().is_empty());
+        drop(tree);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&imported).unwrap();
+    }
+}
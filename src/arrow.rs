@@ -0,0 +1,109 @@
+use crate::byte::{Encodable, Decodable, BinSizer, FixedBytes};
+use crate::BTree;
+use crate::error::BTreeError;
+use anyhow::Result;
+use arrow_array::types::*;
+use arrow_array::{ArrayRef, FixedSizeBinaryArray, PrimitiveArray, RecordBatch};
+use arrow_schema::{DataType, Field, Schema as ArrowSchema, SchemaRef};
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// a key or value type with a matching arrow column type. `define_fixed_len_str!` types
+/// come with one, a utf8 column
+pub trait ArrowField: Sized {
+    fn data_type() -> DataType;
+
+    fn to_array(values: Vec<Self>) -> ArrayRef;
+}
+
+macro_rules! arrow_field_impl {
+    ($ty: ty, $arrow: ty, $data_type: expr) => {
+        impl ArrowField for $ty {
+            fn data_type() -> DataType {
+                $data_type
+            }
+
+            fn to_array(values: Vec<Self>) -> ArrayRef {
+                Arc::new(PrimitiveArray::<$arrow>::from_iter_values(values))
+            }
+        }
+    }
+}
+
+arrow_field_impl!(u8, UInt8Type, DataType::UInt8);
+arrow_field_impl!(u16, UInt16Type, DataType::UInt16);
+arrow_field_impl!(u32, UInt32Type, DataType::UInt32);
+arrow_field_impl!(u64, UInt64Type, DataType::UInt64);
+arrow_field_impl!(i8, Int8Type, DataType::Int8);
+arrow_field_impl!(i16, Int16Type, DataType::Int16);
+arrow_field_impl!(i32, Int32Type, DataType::Int32);
+arrow_field_impl!(i64, Int64Type, DataType::Int64);
+arrow_field_impl!(f32, Float32Type, DataType::Float32);
+arrow_field_impl!(f64, Float64Type, DataType::Float64);
+
+// a column of `N` bytes a row, the shorter values padded with zeros as on the page
+impl<const N: usize> ArrowField for FixedBytes<N> {
+    fn data_type() -> DataType {
+        DataType::FixedSizeBinary(N as i32)
+    }
+
+    fn to_array(values: Vec<Self>) -> ArrayRef {
+        let padded = values.into_iter().map(|v| {
+            let mut bytes = v.into_vec();
+            bytes.resize(N, 0);
+            Some(bytes)
+        });
+        Arc::new(FixedSizeBinaryArray::try_from_sparse_iter_with_size(padded, N as i32).unwrap())
+    }
+}
+
+// rows per record batch when streaming a tree out to parquet
+#[cfg(feature = "parquet")]
+const PARQUET_BATCH_ROWS: usize = 64 * 1024;
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone + ArrowField,
+        V: Encodable + Decodable + BinSizer + Debug + Clone + ArrowField
+{
+    fn arrow_schema() -> SchemaRef {
+        Arc::new(ArrowSchema::new(vec![
+            Field::new("key", K::data_type(), false),
+            Field::new("value", V::data_type(), false),
+        ]))
+    }
+
+    fn record_batch(schema: SchemaRef, entries: Vec<(K, V)>) -> Result<RecordBatch> {
+        let (keys, values): (Vec<K>, Vec<V>) = entries.into_iter().unzip();
+        Ok(RecordBatch::try_new(schema, vec![K::to_array(keys), V::to_array(values)])?)
+    }
+
+    /// every entry as one record batch with a `key` and a `value` column
+    pub fn export_arrow(&self) -> Result<RecordBatch, BTreeError> {
+        Ok(Self::record_batch(Self::arrow_schema(), self.iter().collect())?)
+    }
+
+    /// writes every entry to a new parquet file at `path` with a `key` and a `value` column,
+    /// returning how many rows were written
+    #[cfg(feature = "parquet")]
+    pub fn export_parquet<P: AsRef<std::path::Path>>(&self, path: P) -> Result<usize, BTreeError> {
+        let file = std::fs::OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(path.as_ref())?;
+        let schema = Self::arrow_schema();
+        let mut writer = parquet::arrow::ArrowWriter::try_new(file, schema.clone(), None)?;
+        let mut iter = self.iter();
+        let mut rows = 0;
+        loop {
+            let entries: Vec<(K, V)> = iter.by_ref().take(PARQUET_BATCH_ROWS).collect();
+            if entries.is_empty() {
+                break;
+            }
+            rows += entries.len();
+            writer.write(&Self::record_batch(schema.clone(), entries)?)?;
+        }
+        writer.close()?;
+        Ok(rows)
+    }
+}
@@ -0,0 +1,78 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::BTree;
+use std::fmt::Debug;
+use std::ops::RangeBounds;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use tokio::task;
+
+/// a tree for async code. every call runs on tokio's blocking pool, so waiting on page reads
+/// and writes never holds up the runtime; reads run side by side, writes one at a time.
+/// clones share the same tree
+pub struct AsyncBTree<K, V> {
+    tree: Arc<RwLock<BTree<K, V>>>,
+}
+
+impl<K, V> Clone for AsyncBTree<K, V> {
+    fn clone(&self) -> Self {
+        AsyncBTree { tree: self.tree.clone() }
+    }
+}
+
+impl<K, V> AsyncBTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone + Send + Sync + 'static,
+        V: Encodable + Decodable + BinSizer + Debug + Clone + Send + Sync + 'static
+{
+    pub fn new(tree: BTree<K, V>) -> Self {
+        AsyncBTree { tree: Arc::new(RwLock::new(tree)) }
+    }
+
+    /// `BTree::open_or_create` off the runtime
+    pub async fn open<P: Into<PathBuf>>(path: P) -> Result<Self, BTreeError> {
+        let path = path.into();
+        let tree = task::spawn_blocking(move || BTree::open_or_create(path)).await??;
+        Ok(Self::new(tree))
+    }
+
+    pub async fn get(&self, key: K) -> Result<Option<V>, BTreeError> {
+        self.read(move |tree| tree.try_get(&key)).await
+    }
+
+    pub async fn set(&self, key: K, value: V) -> Result<(), BTreeError> {
+        self.write(move |tree| tree.set(&key, &value)).await
+    }
+
+    /// removes `key`, returning the value it had
+    pub async fn delete(&self, key: K) -> Result<Option<V>, BTreeError> {
+        self.write(move |tree| tree.remove(&key)).await
+    }
+
+    /// the entries with keys in `range`, in key order, read in one go
+    pub async fn range<R: RangeBounds<K> + Send + 'static>(&self, range: R) -> Result<Vec<(K, V)>, BTreeError> {
+        self.read(move |tree| Ok(tree.range(range).collect())).await
+    }
+
+    pub async fn flush(&self) -> Result<(), BTreeError> {
+        self.write(|tree| tree.flush()).await
+    }
+
+    async fn read<T, F>(&self, f: F) -> Result<T, BTreeError>
+        where
+            T: Send + 'static,
+            F: FnOnce(&BTree<K, V>) -> Result<T, BTreeError> + Send + 'static
+    {
+        let tree = self.tree.clone();
+        task::spawn_blocking(move || f(&tree.read().unwrap())).await?
+    }
+
+    async fn write<T, F>(&self, f: F) -> Result<T, BTreeError>
+        where
+            T: Send + 'static,
+            F: FnOnce(&mut BTree<K, V>) -> Result<T, BTreeError> + Send + 'static
+    {
+        let tree = self.tree.clone();
+        task::spawn_blocking(move || f(&mut tree.write().unwrap())).await?
+    }
+}
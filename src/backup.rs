@@ -0,0 +1,72 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::page::Page;
+use crate::pager::page_crc;
+use crate::BTree;
+use anyhow::Result;
+use std::fmt::Debug;
+use std::fs::{self, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    /// copies the tree page by page into a new file at `path`, which opens as the tree stands
+    /// now, writes not yet synced included. a write takes `&mut self`, so none lands halfway
+    /// through the copy, while reads on other threads go on. the meta page goes in last, a
+    /// copy cut short never opening as a tree. returns how many pages were copied
+    pub fn backup_to<P: AsRef<Path>>(&self, path: P) -> Result<u32, BTreeError> {
+        let path = path.as_ref();
+        let mut file = OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(path)?;
+        let copied = self.copy_pages(&mut file).and_then(|pages| {
+            file.sync_all()?;
+            Ok(pages)
+        });
+        if copied.is_err() {
+            let _ = fs::remove_file(path);
+        }
+        let pages = copied?;
+        event!(info, path:% = self.path.display(), backup:% = path.display(), pages = pages; "backed up a tree");
+        Ok(pages)
+    }
+
+    fn copy_pages(&self, file: &mut fs::File) -> Result<u32> {
+        let meta_page = self.meta_page.as_ref().unwrap();
+        let total_pages = meta_page.total_pages();
+        let spares = meta_page.spares();
+        let page_size = self.page_size();
+        // pages the tree holds in memory go out as they stand, not as last written
+        let held: Vec<&Page<K, V>> = self.root_page.iter().chain(self.catalog.iter().map(|(p, _)| p)).collect();
+        let mut buf = vec![0u8; page_size];
+        // the digest of the pages as copied, for the copy's meta page
+        let mut digest = 0;
+        file.seek(SeekFrom::Start(page_size as u64))?;
+        let second = meta_page.second_meta();
+        for index in 1..total_pages {
+            match held.iter().find(|p| p.index == index) {
+                Some(p) => buf.copy_from_slice(&p.image(0)?),
+                // written along with the first one, once the digest is known
+                None if Some(index) == second => buf.fill(0),
+                None => self.fd.lock().unwrap().read_page(index, &mut buf)?
+            }
+            if !spares.contains(&index) && Some(index) != second {
+                digest ^= page_crc(index, &buf);
+            }
+            file.write_all(&buf)?;
+        }
+        file.sync_data()?;
+        // both meta pages of the copy hold the same image, the same epoch and all
+        let image = meta_page.image(digest)?;
+        for index in std::iter::once(0).chain(second) {
+            file.seek(SeekFrom::Start(index as u64 * page_size as u64))?;
+            file.write_all(&image)?;
+        }
+        Ok(total_pages)
+    }
+}
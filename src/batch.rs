@@ -0,0 +1,95 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::page::Stat;
+use crate::BTree;
+use anyhow::Result;
+use std::fmt::Debug;
+
+/// writes gathered up to go to a tree together through `BTree::write_batch`, which writes
+/// the pages they touch out once for all of them, or through `BTree::apply`, which also makes
+/// them durable all at once
+pub struct WriteBatch<K, V> {
+    // every write in the order it was made, None deleting the key
+    writes: Vec<(K, Option<V>)>,
+}
+
+impl<K, V> Default for WriteBatch<K, V> {
+    fn default() -> Self {
+        WriteBatch { writes: Vec::new() }
+    }
+}
+
+impl<K: Clone, V: Clone> WriteBatch<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, key: &K, value: &V) {
+        self.writes.push((key.clone(), Some(value.clone())));
+    }
+
+    pub fn delete(&mut self, key: &K) {
+        self.writes.push((key.clone(), None));
+    }
+
+    /// how many writes the batch holds
+    pub fn len(&self) -> usize {
+        self.writes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.writes.clear()
+    }
+
+    /// adds the writes of `other` after those the batch holds
+    pub fn extend(&mut self, other: &WriteBatch<K, V>) {
+        self.writes.extend(other.writes.iter().cloned())
+    }
+}
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    /// applies the writes of `batch` in order, then writes every page they touched out and
+    /// syncs the file once, as `flush` does. unlike `apply` the batch is not atomic: a failure
+    /// or a crash part way leaves the writes before it applied
+    pub fn write_batch(&mut self, batch: WriteBatch<K, V>) -> Result<(), BTreeError> {
+        if self.read_only {
+            return Err(BTreeError::ReadOnly { path: self.path.clone() });
+        }
+        if batch.is_empty() {
+            return Ok(());
+        }
+        for (key, value) in batch.writes.iter() {
+            match value {
+                Some(value) => self.put(key, value)?,
+                None => {
+                    self.delete(key)?;
+                }
+            }
+        }
+        // once for the batch, deletes taking part as much as puts
+        self.touch(Stat::LastModified);
+        // copy-on-write syncs the batch in as a single write
+        match self.cow {
+            Some(_) => Ok(self.finish_write()?),
+            None => self.flush()
+        }
+    }
+
+    /// applies the writes of `batch` in order as a single commit, as a `Txn` does: after a
+    /// crash the file opens with all of them or none, and a failure leaves the tree as it was.
+    /// every page the writes touch stays in memory until it is written out
+    pub fn apply(&mut self, batch: WriteBatch<K, V>) -> Result<(), BTreeError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        Ok(self.apply_atomically(&batch.writes)?)
+    }
+}
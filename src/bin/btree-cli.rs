@@ -0,0 +1,365 @@
+//! looks into and changes tree files from the command line. tree files do not record their
+//! key and value types, so they are given along with the path:
+//!
+//!     btree-cli <path> --key u64 --value str:50 <command> [args]
+//!
+//! `get <key>` prints the value stored under a key, `set <key> <value>` stores one, creating
+//! the file when there is none, `del <key>` removes one. `scan [--start <key>] [--end <key>]
+//! [--limit <n>]` prints entries in key order, a tab between key and value; the start is
+//! included and the end is not. `stats` prints the counters kept in the meta page.
+//! `verify [--deep]` checks the pages against the digest the meta page recorded, and with
+//! `--deep` walks every tree for problems the digest does not catch. `dump [--dot]` prints the
+//! pages of every tree, or a graphviz digraph of them. `merge <other> [--keep mine|theirs]`
+//! copies in the entries of another file of the same types.
+//!
+//! `str:<n>` is a string of up to n bytes, as `define_fixed_len_str!` stores them.
+
+use btree::*;
+use anyhow::{anyhow, Result};
+use std::io::{self, Write};
+use std::ops::Bound;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const TYPES: &str = "u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 str:<n>";
+
+// the capacity of str:<n> keys and values, known only once the arguments are read
+static KEY_STR_LEN: AtomicUsize = AtomicUsize::new(0);
+static VALUE_STR_LEN: AtomicUsize = AtomicUsize::new(0);
+
+macro_rules! sized_str {
+    ($name: ident, $len: ident) => {
+        /// a string stored like `define_fixed_len_str!` does, with the capacity given on the command line
+        #[derive(Debug, Clone, PartialEq, PartialOrd)]
+        struct $name(String);
+
+        impl BinSizer for $name {
+            fn bin_size() -> usize {
+                $len.load(Ordering::Relaxed)
+            }
+        }
+
+        impl Encodable for $name {
+            fn encode(&self, buf: &mut [u8]) -> Result<usize> {
+                let capacity = Self::bin_size();
+                check_len(buf, capacity)?;
+                let bytes = self.0.as_bytes();
+                if bytes.len() > capacity {
+                    return Err(anyhow!("{} bytes do not fit in str:{}", bytes.len(), capacity));
+                }
+                buf[..bytes.len()].copy_from_slice(bytes);
+                if bytes.len() < capacity {
+                    buf[bytes.len()] = 0;
+                }
+                Ok(capacity)
+            }
+        }
+
+        impl Decodable for $name {
+            fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+                let capacity = Self::bin_size();
+                check_len(buf, capacity)?;
+                let buf = &buf[..capacity];
+                let end = buf.iter().position(|b| *b == 0).unwrap_or(capacity);
+                Ok(($name(std::str::from_utf8(&buf[..end])?.to_owned()), capacity))
+            }
+        }
+
+        impl Field for $name {
+            fn parse(s: &str) -> Option<Self> {
+                if s.len() <= Self::bin_size() { Some($name(s.to_owned())) } else { None }
+            }
+
+            fn text(&self) -> String {
+                self.0.clone()
+            }
+        }
+    }
+}
+
+/// how a key or value type is read from and written to the command line
+trait Field: Sized {
+    fn parse(s: &str) -> Option<Self>;
+
+    fn text(&self) -> String;
+}
+
+macro_rules! num_field {
+    ($($ty: ty),*) => {
+        $(impl Field for $ty {
+            fn parse(s: &str) -> Option<Self> {
+                s.parse().ok()
+            }
+
+            fn text(&self) -> String {
+                self.to_string()
+            }
+        })*
+    }
+}
+
+num_field!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+sized_str!(KeyStr, KEY_STR_LEN);
+sized_str!(ValueStr, VALUE_STR_LEN);
+
+/// a tree with its types erased, so the commands are compiled once
+trait Tool {
+    fn get(&self, key: &str) -> Result<Option<String>>;
+
+    fn set(&mut self, key: &str, value: &str) -> Result<()>;
+
+    fn del(&mut self, key: &str) -> Result<bool>;
+
+    fn scan(&self, start: Bound<&str>, end: Bound<&str>, limit: usize, out: &mut dyn Write) -> Result<()>;
+
+    fn stats(&self, out: &mut dyn Write) -> Result<()>;
+
+    fn verify(&mut self, deep: bool) -> Result<Vec<String>>;
+
+    fn dump(&self, dot: bool, out: &mut dyn Write) -> Result<()>;
+
+    fn merge(&mut self, other: &str, keep_theirs: bool) -> Result<usize>;
+}
+
+impl<K, V> Tool for BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + std::fmt::Debug + Clone + Field + 'static,
+        V: Encodable + Decodable + BinSizer + std::fmt::Debug + Clone + Field + 'static
+{
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self.try_get(&parse_key::<K>(key)?)?.map(|v| v.text()))
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let v = V::parse(value).ok_or_else(|| anyhow!("'{}' is not a valid value", value))?;
+        BTree::set(self, &parse_key::<K>(key)?, &v)?;
+        Ok(self.flush()?)
+    }
+
+    fn del(&mut self, key: &str) -> Result<bool> {
+        Ok(self.remove(&parse_key::<K>(key)?)?.is_some())
+    }
+
+    fn scan(&self, start: Bound<&str>, end: Bound<&str>, limit: usize, out: &mut dyn Write) -> Result<()> {
+        let bounds = (parse_bound::<K>(start)?, parse_bound::<K>(end)?);
+        for (k, v) in BTree::scan(self, bounds).take(limit) {
+            writeln!(out, "{}\t{}", k.text(), v.text())?;
+        }
+        Ok(())
+    }
+
+    fn stats(&self, out: &mut dyn Write) -> Result<()> {
+        let stats = BTree::stats(self);
+        writeln!(out, "entries: {}", self.len())?;
+        writeln!(out, "page size: {}", self.page_size())?;
+        writeln!(out, "inserts: {}", stats.inserts)?;
+        writeln!(out, "overwrites: {}", stats.overwrites)?;
+        writeln!(out, "deletes: {}", stats.deletes)?;
+        writeln!(out, "splits: {}", stats.splits)?;
+        writeln!(out, "last modified: {}", time(self.last_modified()))?;
+        writeln!(out, "last compaction: {}", time(stats.last_compaction))?;
+        Ok(())
+    }
+
+    fn verify(&mut self, deep: bool) -> Result<Vec<String>> {
+        let mut problems = Vec::new();
+        if let Err(e) = self.verify_checksum() {
+            problems.push(e.to_string());
+        }
+        if deep {
+            problems.extend(BTree::verify(self)?.iter().map(|p| p.to_string()));
+        }
+        Ok(problems)
+    }
+
+    fn dump(&self, dot: bool, mut out: &mut dyn Write) -> Result<()> {
+        if dot { self.dump_dot(&mut out)? } else { self.dump_text(&mut out)? }
+        Ok(())
+    }
+
+    fn merge(&mut self, other: &str, keep_theirs: bool) -> Result<usize> {
+        let other = BTree::<K, V>::open_read_only(other)?;
+        let conflict = if keep_theirs { Conflict::KeepTheirs } else { Conflict::KeepMine };
+        Ok(self.merge_from(&other, conflict)?)
+    }
+}
+
+fn parse_key<K: Field>(s: &str) -> Result<K> {
+    K::parse(s).ok_or_else(|| anyhow!("'{}' is not a valid key", s))
+}
+
+fn parse_bound<K: Field>(bound: Bound<&str>) -> Result<Bound<K>> {
+    Ok(match bound {
+        Bound::Included(s) => Bound::Included(parse_key(s)?),
+        Bound::Excluded(s) => Bound::Excluded(parse_key(s)?),
+        Bound::Unbounded => Bound::Unbounded
+    })
+}
+
+// seconds since the unix epoch, as the meta page keeps no time zone
+fn time(t: Option<SystemTime>) -> String {
+    match t.and_then(|t| t.duration_since(UNIX_EPOCH).ok()) {
+        Some(d) => format!("{}.{:03}", d.as_secs(), d.subsec_millis()),
+        None => "never".to_owned()
+    }
+}
+
+/// how a command needs the file opened
+#[derive(Clone, Copy)]
+enum Access {
+    Read,
+    Write,
+    Create,
+}
+
+fn open<K, V>(path: &str, access: Access) -> Result<Box<dyn Tool>>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + std::fmt::Debug + Clone + Field + 'static,
+        V: Encodable + Decodable + BinSizer + std::fmt::Debug + Clone + Field + 'static
+{
+    Ok(match access {
+        Access::Read => Box::new(BTree::<K, V>::open_read_only(path)?),
+        Access::Write => Box::new(BTree::<K, V>::open(path)?),
+        Access::Create => Box::new(BTree::<K, V>::open_or_create(path)?)
+    })
+}
+
+/// a `str:<n>` type's capacity, recorded where `sized_str!` reads it
+fn str_len(ty: &str, len: &AtomicUsize) -> Option<()> {
+    let n: usize = ty.strip_prefix("str:")?.parse().ok().filter(|n| *n > 0)?;
+    len.store(n, Ordering::Relaxed);
+    Some(())
+}
+
+macro_rules! open_with_value {
+    ($key: ty, $path: expr, $value: expr, $access: expr) => {
+        match $value {
+            "u8" => open::<$key, u8>($path, $access),
+            "u16" => open::<$key, u16>($path, $access),
+            "u32" => open::<$key, u32>($path, $access),
+            "u64" => open::<$key, u64>($path, $access),
+            "i8" => open::<$key, i8>($path, $access),
+            "i16" => open::<$key, i16>($path, $access),
+            "i32" => open::<$key, i32>($path, $access),
+            "i64" => open::<$key, i64>($path, $access),
+            "f32" => open::<$key, f32>($path, $access),
+            "f64" => open::<$key, f64>($path, $access),
+            other if str_len(other, &VALUE_STR_LEN).is_some() => open::<$key, ValueStr>($path, $access),
+            other => Err(anyhow!("unknown value type {}, expected one of: {}", other, TYPES))
+        }
+    }
+}
+
+fn open_typed(path: &str, key: &str, value: &str, access: Access) -> Result<Box<dyn Tool>> {
+    match key {
+        "u8" => open_with_value!(u8, path, value, access),
+        "u16" => open_with_value!(u16, path, value, access),
+        "u32" => open_with_value!(u32, path, value, access),
+        "u64" => open_with_value!(u64, path, value, access),
+        "i8" => open_with_value!(i8, path, value, access),
+        "i16" => open_with_value!(i16, path, value, access),
+        "i32" => open_with_value!(i32, path, value, access),
+        "i64" => open_with_value!(i64, path, value, access),
+        "f32" => open_with_value!(f32, path, value, access),
+        "f64" => open_with_value!(f64, path, value, access),
+        other if str_len(other, &KEY_STR_LEN).is_some() => open_with_value!(KeyStr, path, value, access),
+        other => Err(anyhow!("unknown key type {}, expected one of: {}", other, TYPES))
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("usage: btree-cli <path> --key <type> --value <type> <command> [args]");
+    eprintln!("commands:");
+    eprintln!("  get <key>");
+    eprintln!("  set <key> <value>");
+    eprintln!("  del <key>");
+    eprintln!("  scan [--start <key>] [--end <key>] [--limit <n>]");
+    eprintln!("  stats");
+    eprintln!("  verify [--deep]");
+    eprintln!("  dump [--dot]");
+    eprintln!("  merge <other> [--keep mine|theirs]");
+    eprintln!("types: {}", TYPES);
+    std::process::exit(2);
+}
+
+fn main() -> Result<()> {
+    let mut key = None;
+    let mut value = None;
+    let mut options: Vec<(String, Option<String>)> = Vec::new();
+    let mut words = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--key" => key = Some(args.next().unwrap_or_else(|| usage())),
+            "--value" => value = Some(args.next().unwrap_or_else(|| usage())),
+            "--deep" | "--dot" => options.push((arg, None)),
+            "--start" | "--end" | "--limit" | "--keep" => options.push((arg, Some(args.next().unwrap_or_else(|| usage())))),
+            _ if arg.starts_with("--") => usage(),
+            _ => words.push(arg)
+        }
+    }
+    let option = |name: &str| options.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_deref());
+    let (key, value) = match (key, value) {
+        (Some(key), Some(value)) => (key, value),
+        _ => usage()
+    };
+    let (path, command, args) = match words.as_slice() {
+        [path, command, args @ ..] => (path.as_str(), command.as_str(), args),
+        _ => usage()
+    };
+    let access = match command {
+        "set" => Access::Create,
+        "del" | "merge" => Access::Write,
+        _ => Access::Read
+    };
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match (command, args) {
+        ("get", [k]) => match open_typed(path, &key, &value, access)?.get(k)? {
+            Some(v) => writeln!(out, "{}", v)?,
+            None => {
+                eprintln!("no such key");
+                std::process::exit(1);
+            }
+        },
+        ("set", [k, v]) => open_typed(path, &key, &value, access)?.set(k, v)?,
+        ("del", [k]) => if !open_typed(path, &key, &value, access)?.del(k)? {
+            eprintln!("no such key");
+            std::process::exit(1);
+        },
+        ("scan", []) => {
+            let start = option("--start").flatten().map_or(Bound::Unbounded, Bound::Included);
+            let end = option("--end").flatten().map_or(Bound::Unbounded, Bound::Excluded);
+            let limit = match option("--limit").flatten() {
+                Some(n) => n.parse().map_err(|_| anyhow!("'{}' is not a valid limit", n))?,
+                None => usize::MAX
+            };
+            open_typed(path, &key, &value, access)?.scan(start, end, limit, &mut out)?;
+        }
+        ("stats", []) => open_typed(path, &key, &value, access)?.stats(&mut out)?,
+        ("verify", []) => {
+            let problems = open_typed(path, &key, &value, access)?.verify(option("--deep").is_some())?;
+            for problem in problems.iter() {
+                writeln!(out, "{}", problem)?;
+            }
+            if !problems.is_empty() {
+                out.flush()?;
+                std::process::exit(1);
+            }
+            writeln!(out, "ok")?;
+        }
+        ("dump", []) => open_typed(path, &key, &value, access)?.dump(option("--dot").is_some(), &mut out)?,
+        ("merge", [other]) => {
+            let keep_theirs = match option("--keep").flatten() {
+                None | Some("mine") => false,
+                Some("theirs") => true,
+                Some(_) => usage()
+            };
+            let written = open_typed(path, &key, &value, access)?.merge(other, keep_theirs)?;
+            writeln!(out, "merged {} entries", written)?;
+        }
+        _ => usage()
+    }
+    Ok(())
+}
@@ -0,0 +1,397 @@
+//! a read-only web view of a tree file. tree files do not record their key and value types,
+//! so they are given on the command line:
+//!
+//!     btree-explorer <path> --key u32 --value str64 [--addr 127.0.0.1:8080]
+//!
+//! `/` pages through the entries, `/get?key=..` and `/range?start=..&end=..&limit=..`
+//! answer in json. a range includes its start and excludes its end, both are optional.
+
+use btree::*;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::ops::Bound;
+
+define_fixed_len_str!(Str16, 16);
+define_fixed_len_str!(Str32, 32);
+define_fixed_len_str!(Str64, 64);
+define_fixed_len_str!(Str256, 256);
+
+const PAGE_ROWS: usize = 50;
+const MAX_ROWS: usize = 1000;
+
+const TYPES: &str = "u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 str16 str32 str64 str256";
+
+/// how a key or value type is read from a url and shown in html and json
+trait Field: Sized {
+    fn parse(s: &str) -> Option<Self>;
+
+    fn text(&self) -> String;
+
+    fn json(&self) -> String;
+}
+
+macro_rules! num_field {
+    ($($ty: ty),*) => {
+        $(impl Field for $ty {
+            fn parse(s: &str) -> Option<Self> {
+                s.parse().ok()
+            }
+
+            fn text(&self) -> String {
+                self.to_string()
+            }
+
+            fn json(&self) -> String {
+                self.to_string()
+            }
+        })*
+    }
+}
+
+num_field!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+macro_rules! float_field {
+    ($($ty: ty),*) => {
+        $(impl Field for $ty {
+            fn parse(s: &str) -> Option<Self> {
+                s.parse().ok()
+            }
+
+            fn text(&self) -> String {
+                self.to_string()
+            }
+
+            fn json(&self) -> String {
+                // json has no spelling for nan or the infinities
+                if self.is_finite() { self.to_string() } else { "null".to_owned() }
+            }
+        })*
+    }
+}
+
+float_field!(f32, f64);
+
+macro_rules! str_field {
+    ($($ty: ident),*) => {
+        $(impl Field for $ty {
+            fn parse(s: &str) -> Option<Self> {
+                if s.len() <= $ty::bin_size() { Some($ty::new(s)) } else { None }
+            }
+
+            fn text(&self) -> String {
+                self.0.clone()
+            }
+
+            fn json(&self) -> String {
+                json_str(&self.0)
+            }
+        })*
+    }
+}
+
+str_field!(Str16, Str32, Str64, Str256);
+
+/// a key or value rendered both ways
+struct Shown {
+    text: String,
+    json: String,
+}
+
+impl Shown {
+    fn new<T: Field>(val: &T) -> Self {
+        Shown { text: val.text(), json: val.json() }
+    }
+}
+
+/// a tree with its types erased, so the server is compiled once
+trait Explore {
+    fn get(&mut self, key: &str) -> Result<Option<(Shown, Shown)>, String>;
+
+    fn range(&self, start: Bound<&str>, end: Bound<&str>, limit: usize) -> Result<Vec<(Shown, Shown)>, String>;
+}
+
+impl<K, V> Explore for BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + std::fmt::Debug + Clone + Field,
+        V: Encodable + Decodable + BinSizer + std::fmt::Debug + Clone + Field
+{
+    fn get(&mut self, key: &str) -> Result<Option<(Shown, Shown)>, String> {
+        let k = parse_key::<K>(key)?;
+        Ok(BTree::get(self, &k).map(|v| (Shown::new(&k), Shown::new(&v))))
+    }
+
+    fn range(&self, start: Bound<&str>, end: Bound<&str>, limit: usize) -> Result<Vec<(Shown, Shown)>, String> {
+        let bounds = (parse_bound::<K>(start)?, parse_bound::<K>(end)?);
+        Ok(self.scan(bounds).take(limit).map(|(k, v)| (Shown::new(&k), Shown::new(&v))).collect())
+    }
+}
+
+fn parse_key<K: Field>(s: &str) -> Result<K, String> {
+    K::parse(s).ok_or_else(|| format!("'{}' is not a valid key", s))
+}
+
+fn parse_bound<K: Field>(bound: Bound<&str>) -> Result<Bound<K>, String> {
+    Ok(match bound {
+        Bound::Included(s) => Bound::Included(parse_key(s)?),
+        Bound::Excluded(s) => Bound::Excluded(parse_key(s)?),
+        Bound::Unbounded => Bound::Unbounded
+    })
+}
+
+macro_rules! open_with_value {
+    ($key: ty, $path: expr, $value: expr) => {
+        match $value {
+            "u8" => open::<$key, u8>($path),
+            "u16" => open::<$key, u16>($path),
+            "u32" => open::<$key, u32>($path),
+            "u64" => open::<$key, u64>($path),
+            "i8" => open::<$key, i8>($path),
+            "i16" => open::<$key, i16>($path),
+            "i32" => open::<$key, i32>($path),
+            "i64" => open::<$key, i64>($path),
+            "f32" => open::<$key, f32>($path),
+            "f64" => open::<$key, f64>($path),
+            "str16" => open::<$key, Str16>($path),
+            "str32" => open::<$key, Str32>($path),
+            "str64" => open::<$key, Str64>($path),
+            "str256" => open::<$key, Str256>($path),
+            other => Err(anyhow::anyhow!("unknown value type {}, expected one of: {}", other, TYPES))
+        }
+    }
+}
+
+fn open<K, V>(path: &str) -> anyhow::Result<Box<dyn Explore>>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + std::fmt::Debug + Clone + Field + 'static,
+        V: Encodable + Decodable + BinSizer + std::fmt::Debug + Clone + Field + 'static
+{
+    Ok(Box::new(BTree::<K, V>::open_read_only(path)?))
+}
+
+fn open_typed(path: &str, key: &str, value: &str) -> anyhow::Result<Box<dyn Explore>> {
+    match key {
+        "u8" => open_with_value!(u8, path, value),
+        "u16" => open_with_value!(u16, path, value),
+        "u32" => open_with_value!(u32, path, value),
+        "u64" => open_with_value!(u64, path, value),
+        "i8" => open_with_value!(i8, path, value),
+        "i16" => open_with_value!(i16, path, value),
+        "i32" => open_with_value!(i32, path, value),
+        "i64" => open_with_value!(i64, path, value),
+        "f32" => open_with_value!(f32, path, value),
+        "f64" => open_with_value!(f64, path, value),
+        "str16" => open_with_value!(Str16, path, value),
+        "str32" => open_with_value!(Str32, path, value),
+        "str64" => open_with_value!(Str64, path, value),
+        "str256" => open_with_value!(Str256, path, value),
+        other => Err(anyhow::anyhow!("unknown key type {}, expected one of: {}", other, TYPES))
+    }
+}
+
+fn json_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn url_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes.get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], escaped) {
+            (b'+', _) => out.push(b' '),
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 2;
+            }
+            (b, _) => out.push(b)
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn url_encode(s: &str) -> String {
+    s.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+        b => format!("%{:02X}", b)
+    }).collect()
+}
+
+struct Query(Vec<(String, String)>);
+
+impl Query {
+    fn parse(s: &str) -> Self {
+        Query(s.split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+                (url_decode(k), url_decode(v))
+            })
+            .collect())
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0.iter().find(|(k, _)| k == name).map(|(_, v)| v.as_str())
+    }
+
+    fn limit(&self, default: usize) -> Result<usize, String> {
+        match self.get("limit") {
+            Some(n) => n.parse().map(|n: usize| n.min(MAX_ROWS)).map_err(|_| format!("'{}' is not a valid limit", n)),
+            None => Ok(default)
+        }
+    }
+}
+
+struct Reply {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Reply {
+    fn json(status: &'static str, body: String) -> Self {
+        Reply { status, content_type: "application/json", body }
+    }
+
+    fn html(status: &'static str, body: String) -> Self {
+        Reply { status, content_type: "text/html; charset=utf-8", body }
+    }
+
+    fn json_error(status: &'static str, msg: &str) -> Self {
+        Reply::json(status, format!("{{\"error\":{}}}", json_str(msg)))
+    }
+}
+
+fn entry_json((k, v): &(Shown, Shown)) -> String {
+    format!("{{\"key\":{},\"value\":{}}}", k.json, v.json)
+}
+
+fn route(tree: &mut dyn Explore, path: &str, query: &Query) -> Reply {
+    match path {
+        "/" => browse(tree, query),
+        "/get" => match query.get("key") {
+            Some(key) => match tree.get(key) {
+                Ok(Some(entry)) => Reply::json("200 OK", entry_json(&entry)),
+                Ok(None) => Reply::json_error("404 Not Found", "no such key"),
+                Err(e) => Reply::json_error("400 Bad Request", &e)
+            },
+            None => Reply::json_error("400 Bad Request", "the key parameter is missing")
+        },
+        "/range" => {
+            let start = query.get("start").map_or(Bound::Unbounded, Bound::Included);
+            let end = query.get("end").map_or(Bound::Unbounded, Bound::Excluded);
+            match query.limit(PAGE_ROWS).and_then(|limit| tree.range(start, end, limit)) {
+                Ok(entries) => {
+                    let items: Vec<String> = entries.iter().map(entry_json).collect();
+                    Reply::json("200 OK", format!("[{}]", items.join(",")))
+                }
+                Err(e) => Reply::json_error("400 Bad Request", &e)
+            }
+        }
+        _ => Reply::json_error("404 Not Found", "no such page")
+    }
+}
+
+/// one page of entries, `after` being the last key of the previous page
+fn browse(tree: &mut dyn Explore, query: &Query) -> Reply {
+    let start = query.get("after").map_or(Bound::Unbounded, Bound::Excluded);
+    let result = query.limit(PAGE_ROWS).and_then(|limit| Ok((limit, tree.range(start, Bound::Unbounded, limit + 1)?)));
+    let (limit, mut entries) = match result {
+        Ok(page) => page,
+        Err(e) => return Reply::html("400 Bad Request", format!("<p>{}</p>", html_escape(&e)))
+    };
+    let more = entries.len() > limit;
+    entries.truncate(limit);
+    let mut body = String::from("<!doctype html><title>btree explorer</title>\
+        <style>body{font-family:monospace}td{padding:2px 12px;border-bottom:1px solid #ddd}</style>\
+        <table><tr><th>key</th><th>value</th></tr>");
+    for (k, v) in entries.iter() {
+        body.push_str(&format!("<tr><td>{}</td><td>{}</td></tr>", html_escape(&k.text), html_escape(&v.text)));
+    }
+    body.push_str("</table><p><a href=\"/\">first</a>");
+    if let (true, Some((last, _))) = (more, entries.last()) {
+        body.push_str(&format!(" <a href=\"/?after={}&limit={}\">next</a>", url_encode(&last.text), limit));
+    }
+    body.push_str("</p>");
+    Reply::html("200 OK", body)
+}
+
+fn serve(tree: &mut dyn Explore, stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // the headers are of no interest, but have to be read off the socket
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header != "\r\n" {
+        header.clear();
+    }
+    let mut parts = request_line.split_whitespace();
+    let reply = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) => {
+            let (path, query) = target.split_once('?').unwrap_or((target, ""));
+            route(tree, path, &Query::parse(query))
+        }
+        _ => Reply::json_error("405 Method Not Allowed", "the explorer is read-only")
+    };
+    let mut out = stream;
+    write!(out, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+           reply.status, reply.content_type, reply.body.len(), reply.body)?;
+    out.flush()
+}
+
+fn usage() -> ! {
+    eprintln!("usage: btree-explorer <path> --key <type> --value <type> [--addr <host:port>]");
+    eprintln!("types: {}", TYPES);
+    std::process::exit(2);
+}
+
+fn main() -> anyhow::Result<()> {
+    let mut path = None;
+    let mut key = None;
+    let mut value = None;
+    let mut addr = "127.0.0.1:8080".to_owned();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--key" => key = Some(args.next().unwrap_or_else(|| usage())),
+            "--value" => value = Some(args.next().unwrap_or_else(|| usage())),
+            "--addr" => addr = args.next().unwrap_or_else(|| usage()),
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => usage()
+        }
+    }
+    let (path, key, value) = match (path, key, value) {
+        (Some(path), Some(key), Some(value)) => (path, key, value),
+        _ => usage()
+    };
+    let mut tree = open_typed(&path, &key, &value)?;
+    let listener = TcpListener::bind(&addr)?;
+    println!("exploring {} on http://{}", path, addr);
+    for stream in listener.incoming() {
+        if let Err(e) = serve(tree.as_mut(), stream?) {
+            println!("request failed: {}", e);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,250 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::iter::Iter;
+use crate::page::{Page, PageType, Stat, MAX_TREE_NAME, corrupted};
+use crate::reclaim::{child_ptrs, chain_ptrs};
+use crate::BTree;
+use anyhow::{anyhow, Result};
+use std::fmt::Debug;
+use std::ops::RangeBounds;
+
+/// a named tree kept in the same file as the tree it was opened from, with keys and values of
+/// the same types but entries of its own. while it is open it stands in for the tree it was
+/// opened from, whose root it puts back when dropped. salvaging the file gathers the entries
+/// of every tree in it into one
+pub struct Bucket<'a, K, V> {
+    tree: &'a mut BTree<K, V>,
+    // the root of the file's own tree, put aside
+    root_page: Option<Page<K, V>>,
+}
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    /// opens the tree called `name` in this file, starting it empty and listing it in the
+    /// catalog page when it is not there yet. names take up to 32 bytes
+    pub fn open_tree(&mut self, name: &str) -> Result<Bucket<'_, K, V>, BTreeError> {
+        if name.is_empty() || name.len() > MAX_TREE_NAME || name.contains('\0') {
+            return Err(anyhow!("tree names take 1 to {} bytes without zeros, not {:?}", MAX_TREE_NAME, name).into());
+        }
+        if self.cow.is_some() {
+            return Err(anyhow!("named trees are not written copy-on-write").into());
+        }
+        let (catalog, slot, added) = match self.find_tree(name)? {
+            Some((catalog, slot)) => (catalog, slot, false),
+            None => {
+                let (catalog, slot) = self.add_tree(name)?;
+                (catalog, slot, true)
+            }
+        };
+        let root = Page::<K, V>::load_node(self.fd.clone(), catalog.catalog_root(slot))?;
+        if !self.read_only {
+            self.sync()?;
+        }
+        let root_page = self.root_page.replace(root);
+        self.catalog = Some((catalog, slot));
+        let bucket = Bucket { tree: self, root_page };
+        if added {
+            bucket.tree.sync()?;
+            bucket.tree.finish_write()?;
+        }
+        Ok(bucket)
+    }
+
+    /// the names of the trees kept in this file besides its own, in the order they were added
+    pub fn tree_names(&self) -> Result<Vec<String>, BTreeError> {
+        Ok(match self.load_catalog()? {
+            Some(catalog) => (0..catalog.catalog_len()).map(|i| catalog.catalog_name(i).to_owned()).collect(),
+            None => Vec::new()
+        })
+    }
+
+    /// deletes the tree called `name` and frees its pages; returns false when there is none
+    pub fn drop_tree(&mut self, name: &str) -> Result<bool, BTreeError> {
+        if self.read_only {
+            return Err(BTreeError::ReadOnly { path: self.path.clone() });
+        }
+        let (mut catalog, slot) = match self.find_tree(name)? {
+            Some(found) => found,
+            None => return Ok(false)
+        };
+        let mut todo = vec![catalog.catalog_root(slot)];
+        catalog.remove_catalog_entry(slot);
+        drop(catalog);
+        // the tree is out of the catalog before its pages go on the free list
+        self.sync()?;
+        while let Some(index) = todo.pop() {
+            let p = Page::<K, V>::load_node(self.fd.clone(), index)?;
+            todo.extend(child_ptrs(&p));
+            for first in chain_ptrs(&p) {
+                self.free_chain(first)?;
+            }
+            drop(p);
+            self.free_page(index)?;
+        }
+        self.sync()?;
+        self.finish_write()?;
+        Ok(true)
+    }
+
+    fn load_catalog(&self) -> Result<Option<Page<K, V>>> {
+        let index = self.meta_page.as_ref().unwrap().catalog_index();
+        if index == 0 {
+            return Ok(None);
+        }
+        let catalog = Page::<K, V>::load(self.fd.clone(), index)?;
+        if catalog.page_type != PageType::CATALOG {
+            return Err(corrupted(index, format!("a {:?} page in place of the catalog", catalog.page_type)));
+        }
+        Ok(Some(catalog))
+    }
+
+    fn find_tree(&self, name: &str) -> Result<Option<(Page<K, V>, usize)>> {
+        Ok(self.load_catalog()?.and_then(|catalog| catalog.catalog_find(name).map(|slot| (catalog, slot))))
+    }
+
+    fn add_tree(&mut self, name: &str) -> Result<(Page<K, V>, usize)> {
+        if self.read_only {
+            return Err(anyhow!("{} is opened read only, it has no tree called {:?}", self.path.display(), name));
+        }
+        let catalog = self.load_catalog()?;
+        self.reserve_pages(if catalog.is_some() { 1 } else { 2 })?;
+        let mut catalog = match catalog {
+            Some(catalog) => catalog,
+            None => {
+                let catalog = self.new_page(PageType::CATALOG)?;
+                self.meta_page.as_mut().unwrap().set_catalog_index(catalog.index);
+                catalog
+            }
+        };
+        if catalog.catalog_len() == Page::<K, V>::catalog_capacity(self.page_size()) {
+            return Err(anyhow!("the catalog is full, {} trees are all {} byte pages have room for", catalog.catalog_len(), self.page_size()));
+        }
+        let root = self.new_page(PageType::LEAF)?;
+        let slot = catalog.push_catalog_entry(name, root.index).unwrap();
+        drop(root);
+        self.touch(Stat::LastModified);
+        Ok((catalog, slot))
+    }
+
+    /// the root of the tree writes go to, the file's own or the named tree open in its place
+    pub(crate) fn set_root_index(&mut self, index: u32) {
+        match self.catalog.as_mut() {
+            Some((catalog, slot)) => catalog.set_catalog_root(*slot, index),
+            None => self.meta_page.as_mut().unwrap().set_root_index(index)
+        }
+    }
+
+    /// the entry count of the tree open, kept in the catalog for named trees
+    pub(crate) fn entries(&self) -> u64 {
+        match self.catalog.as_ref() {
+            Some((catalog, slot)) => catalog.catalog_entries(*slot),
+            None => self.meta_page.as_ref().unwrap().stat(Stat::Entries)
+        }
+    }
+
+    pub(crate) fn set_entries(&mut self, entries: u64) {
+        match self.catalog.as_mut() {
+            Some((catalog, slot)) => catalog.set_catalog_entries(*slot, entries),
+            None => self.meta_page.as_mut().unwrap().set_stat(Stat::Entries, entries)
+        }
+    }
+}
+
+impl<'a, K, V> Bucket<'a, K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.tree.get(key)
+    }
+
+    pub fn try_get(&self, key: &K) -> Result<Option<V>, BTreeError> {
+        self.tree.try_get(key)
+    }
+
+    pub fn get_many(&self, keys: &[K]) -> Vec<Option<V>> {
+        self.tree.get_many(keys)
+    }
+
+    pub fn set(&mut self, key: &K, value: &V) -> Result<(), BTreeError> {
+        self.tree.set(key, value)
+    }
+
+    pub fn remove(&mut self, key: &K) -> Result<Option<V>, BTreeError> {
+        self.tree.remove(key)
+    }
+
+    pub fn update(&mut self, key: &K, f: impl FnOnce(&mut V)) -> Result<bool, BTreeError> {
+        self.tree.update(key, f)
+    }
+
+    pub fn get_or_insert_with(&mut self, key: &K, default: impl FnOnce() -> V) -> Result<V, BTreeError> {
+        self.tree.get_or_insert_with(key, default)
+    }
+
+    pub fn rank(&self, key: &K) -> usize {
+        self.tree.rank(key)
+    }
+
+    pub fn try_rank(&self, key: &K) -> Result<usize, BTreeError> {
+        self.tree.try_rank(key)
+    }
+
+    pub fn select(&self, n: usize) -> Option<(K, V)> {
+        self.tree.select(n)
+    }
+
+    pub fn try_select(&self, n: usize) -> Result<Option<(K, V)>, BTreeError> {
+        self.tree.try_select(n)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.tree.entries()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<'a, K, V> Bucket<'a, K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        self.tree.iter()
+    }
+
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Iter<'_, K, V> {
+        self.tree.range(range)
+    }
+
+    pub fn first(&self) -> Option<(K, V)> {
+        self.tree.first()
+    }
+
+    pub fn last(&self) -> Option<(K, V)> {
+        self.tree.last()
+    }
+
+    pub fn pop_first(&mut self) -> Result<Option<(K, V)>, BTreeError> {
+        self.tree.pop_first()
+    }
+
+    pub fn pop_last(&mut self) -> Result<Option<(K, V)>, BTreeError> {
+        self.tree.pop_last()
+    }
+}
+
+impl<'a, K, V> Drop for Bucket<'a, K, V> {
+    fn drop(&mut self) {
+        // the named tree's root and the catalog go back to the pager as they drop
+        self.tree.root_page = self.root_page.take();
+        self.tree.catalog = None;
+    }
+}
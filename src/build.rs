@@ -0,0 +1,293 @@
+use crate::error::BTreeError;
+use crate::page::{Page, PageType, Slot, Stat};
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::overflow::write_chain;
+use crate::{BTree, PAGE_SIZE};
+use anyhow::{anyhow, Result};
+use std::fmt::Debug;
+use crate::pager::{Pager, check_page_size};
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// writes a brand-new tree file bottom-up from entries arriving in ascending key order:
+/// leaves are packed full one after another, then each internal level is laid over the one below
+pub(crate) struct Builder<K, V> {
+    fd: Arc<Mutex<Pager>>,
+    next_index: u32,
+    leaf: Option<Page<K, V>>,
+    // (smallest key, page index, entries under it) of every finished page on the level being built
+    children: Vec<(K, u32, u64)>,
+    last_key: Option<K>,
+    count: usize,
+    // how many entries go in a leaf and how many pointers in an internal page
+    leaf_fill: usize,
+    internal_fill: usize,
+    // whether internal pages keep counts, as in trees created by `BTree::new`
+    counted: bool,
+    // the file being built and where it goes once finished, removed unless it gets there
+    target: Option<(PathBuf, PathBuf)>,
+}
+
+/// where a tree file is built before it takes the place it was asked for
+fn build_path(path: &Path) -> PathBuf {
+    let mut build = OsString::from(path.as_os_str());
+    build.push("-build");
+    build.into()
+}
+
+impl<K, V> Builder<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    /// refuses to touch an existing file, a bulk build always starts from nothing. the tree
+    /// is written beside `path` and only shows up there once `finish` is done, a build failing
+    /// part way leaving nothing behind
+    pub fn create<P: AsRef<Path>>(path: P, page_size: usize) -> Result<Self> {
+        check_page_size(page_size)?;
+        BTree::<K, V>::check_sizes(page_size)?;
+        let path = path.as_ref();
+        if path.exists() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already exists", path.display())).into());
+        }
+        let counted = Page::<K, V>::internal_capacity(page_size, true) >= 2;
+        let build = build_path(path);
+        // left over by a build that never finished
+        let fd = OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .read(true)
+            .write(true)
+            .open(&build)?;
+        // pages 0 and 1 are kept for the meta pages, written last
+        let mut builder = Self::over(Arc::new(Mutex::new(Pager::new(fd, page_size))), 2, counted);
+        builder.target = Some((build, path.to_owned()));
+        Ok(builder)
+    }
+
+    /// lays the tree out in the pages of `fd` from `next_index` on, for a tree already open
+    /// there to take up with `finish_tree`
+    pub fn over(fd: Arc<Mutex<Pager>>, next_index: u32, counted: bool) -> Self {
+        let page_size = fd.lock().unwrap().page_size();
+        let leaf_fill = Page::<K, V>::capacity(page_size, &PageType::LEAF);
+        let internal_fill = Page::<K, V>::internal_capacity(page_size, counted) + 1;
+        Builder {
+            fd,
+            next_index,
+            leaf: None,
+            children: Vec::new(),
+            last_key: None,
+            count: 0,
+            leaf_fill,
+            internal_fill,
+            counted,
+            target: None,
+        }
+    }
+
+    /// fills pages only to `fill`, above 0 and at most 1, of what they hold
+    pub fn set_fill(&mut self, fill: f64) -> Result<()> {
+        check_fill(fill)?;
+        let page_size = self.fd.lock().unwrap().page_size();
+        let share = |n: usize| ((n as f64 * fill).ceil() as usize).clamp(2, n);
+        self.leaf_fill = share(Page::<K, V>::capacity(page_size, &PageType::LEAF));
+        self.internal_fill = share(Page::<K, V>::internal_capacity(page_size, self.counted) + 1);
+        Ok(())
+    }
+
+    fn alloc(&mut self, pt: PageType) -> Result<Page<K, V>> {
+        let index = self.next_index;
+        self.next_index += 1;
+        Page::<K, V>::new(self.fd.clone(), index, pt)
+    }
+
+    pub fn push(&mut self, key: &K, value: &V) -> Result<()> {
+        if let Some(last) = self.last_key.as_ref() {
+            if *key <= *last {
+                return Err(anyhow!("bulk build input is not sorted: {:?} after {:?}", key, last));
+            }
+        }
+        let page_size = self.fd.lock().unwrap().page_size();
+        let spilled = if Page::<K, V>::spills(page_size) {
+            let mut bytes = vec![0u8; V::bin_size()];
+            value.encode(&mut bytes)?;
+            Some(write_chain(&bytes, page_size, || self.alloc(PageType::OVERFLOW))?)
+        } else {
+            None
+        };
+        if self.leaf.as_ref().is_some_and(|p| p.item_count() == self.leaf_fill) {
+            self.finish_leaf();
+        }
+        if self.leaf.is_none() {
+            let leaf = self.alloc(PageType::LEAF)?;
+            self.children.push((key.clone(), leaf.index, 0));
+            self.leaf = Some(leaf);
+        }
+        let leaf = self.leaf.as_mut().unwrap();
+        let i = leaf.item_count();
+        leaf.set_item_count(i + 1)?;
+        leaf.set_key_at(i, key)?;
+        leaf.set_value_at(i, &match spilled {
+            Some(first) => Slot::Spilled(first),
+            None => Slot::Value(value)
+        })?;
+        self.last_key = Some(key.clone());
+        self.count += 1;
+        Ok(())
+    }
+
+    // dropping the leaf writes it out, it is the last page on the level so far
+    fn finish_leaf(&mut self) {
+        if let Some(leaf) = self.leaf.take() {
+            self.children.last_mut().unwrap().2 = leaf.total();
+        }
+    }
+
+    /// lays out the internal levels and the meta page, returning how many entries were written
+    pub fn finish(mut self) -> Result<usize> {
+        let (root_index, _, _) = self.finish_tree()?;
+        let mut meta_page = Page::<K, V>::new(self.fd.clone(), 0, PageType::META)?;
+        meta_page.keep_second_meta();
+        if self.counted {
+            meta_page.keep_counts();
+        }
+        meta_page.set_total_page(self.next_index);
+        meta_page.set_root_index(root_index);
+        meta_page.set_stat(Stat::Entries, self.count as u64);
+        meta_page.sync()?;
+        drop(meta_page);
+        self.fd.lock().unwrap().flush()?;
+        if let Some((build, path)) = self.target.as_ref() {
+            // a link, unlike a rename, never replaces a file that showed up at `path` meanwhile
+            fs::hard_link(build, path)?;
+            fs::remove_file(build)?;
+            self.target = None;
+        }
+        Ok(self.count)
+    }
+
+    /// how many entries were pushed so far
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// lays out the internal levels, returning the root, the first page past the tree and
+    /// how many entries it holds
+    pub fn finish_tree(&mut self) -> Result<(u32, u32, usize)> {
+        self.finish_leaf();
+        let root_index = if self.children.is_empty() {
+            self.alloc(PageType::LEAF)?.index
+        } else {
+            let mut level = std::mem::take(&mut self.children);
+            while level.len() > 1 {
+                level = self.build_level(level)?;
+            }
+            level[0].1
+        };
+        Ok((root_index, self.next_index, self.count))
+    }
+
+    fn build_level(&mut self, children: Vec<(K, u32, u64)>) -> Result<Vec<(K, u32, u64)>> {
+        let fanout = self.internal_fill;
+        // spread the children evenly so that no internal page ends up with a single pointer
+        let nodes = children.len().div_ceil(fanout);
+        let (base, extra) = (children.len() / nodes, children.len() % nodes);
+        let mut parents = Vec::with_capacity(nodes);
+        let mut children = children.into_iter();
+        for n in 0..nodes {
+            let group: Vec<(K, u32, u64)> = children.by_ref().take(base + usize::from(n < extra)).collect();
+            let mut page = self.alloc(PageType::INTERNAL)?;
+            if self.counted {
+                page.keep_counts();
+            }
+            page.set_item_count(group.len() - 1)?;
+            page.set_ptr_at(0, group[0].1)?;
+            page.set_count_at(0, group[0].2)?;
+            for (j, (k, ptr, count)) in group.iter().enumerate().skip(1) {
+                page.set_key_at(j - 1, k)?;
+                page.set_ptr_at(j, *ptr)?;
+                page.set_count_at(j, *count)?;
+            }
+            parents.push((group[0].0.clone(), page.index, page.total()));
+        }
+        Ok(parents)
+    }
+}
+
+impl<K, V> Drop for Builder<K, V> {
+    fn drop(&mut self) {
+        if let Some((build, _)) = self.target.take() {
+            let _ = fs::remove_file(build);
+        }
+    }
+}
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    /// builds a new tree file at `path` bottom-up from `entries` in ascending key order, with
+    /// every page packed full, where `set` one entry after another would leave each leaf it
+    /// splits half empty. returns the tree and how many entries went in
+    pub fn bulk_load<P, I>(path: P, entries: I) -> Result<(Self, usize), BTreeError>
+        where
+            P: AsRef<Path>,
+            I: IntoIterator<Item = (K, V)>
+    {
+        Self::bulk_load_with_fill(path, entries, 1.0)
+    }
+
+    /// like `bulk_load`, filling pages only to `fill`, above 0 and at most 1, of what they
+    /// hold, so that later inserts among the loaded keys find room before splitting
+    pub fn bulk_load_with_fill<P, I>(path: P, entries: I, fill: f64) -> Result<(Self, usize), BTreeError>
+        where
+            P: AsRef<Path>,
+            I: IntoIterator<Item = (K, V)>
+    {
+        // before the file gets created
+        check_fill(fill)?;
+        let mut builder = Builder::<K, V>::create(path.as_ref(), PAGE_SIZE)?;
+        builder.set_fill(fill)?;
+        for (key, value) in entries {
+            builder.push(&key, &value)?;
+        }
+        let count = builder.finish()?;
+        Ok((BTree::open(path)?, count))
+    }
+}
+
+fn check_fill(fill: f64) -> Result<()> {
+    if !(fill > 0.0 && fill <= 1.0) {
+        return Err(anyhow!("fill factor {} is not above 0 and at most 1", fill));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build_path;
+    use crate::BTree;
+    use std::fs;
+
+    #[test]
+    fn unsorted_input_leaves_nothing_behind_and_a_retry_builds_the_tree() {
+        let path = std::env::temp_dir().join(format!("btree-build-test-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let unsorted = (0..5000u32).map(|i| (if i == 3000 { 10 } else { i }, i as u64));
+        assert!(BTree::<u32, u64>::bulk_load(&path, unsorted).is_err());
+        assert!(!path.exists());
+        assert!(!build_path(&path).exists());
+        let (tree, count) = BTree::<u32, u64>::bulk_load(&path, (0..5000u32).map(|i| (i, i as u64))).unwrap();
+        assert_eq!(count, 5000);
+        assert_eq!(tree.len(), 5000);
+        assert_eq!(tree.get(&4999), Some(4999));
+        assert!(tree.verify().unwrap().is_empty());
+        assert!(!build_path(&path).exists());
+        drop(tree);
+        fs::remove_file(&path).unwrap();
+    }
+}
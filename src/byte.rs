@@ -1,8 +1,39 @@
 use anyhow::{anyhow, Result};
+use core::cmp::Ordering;
 use core::mem;
+use std::borrow::Cow;
 
 pub trait BinSizer {
     fn bin_size() -> usize;
+
+    /// the name of the order keys of this type sort in when it is not their natural one,
+    /// recorded in the meta page so a file is never read under another order. empty for the
+    /// natural one
+    fn key_order() -> Cow<'static, str> {
+        Cow::Borrowed("")
+    }
+
+    /// whether `compare_encoded` orders stored keys of this type as their `PartialOrd` does,
+    /// so a search runs over the bytes in the pages without decoding a key
+    fn ordered_encoding() -> bool {
+        false
+    }
+
+    /// the order of two stored keys, only ever used for types with an `ordered_encoding`
+    fn compare_encoded(a: &[u8], b: &[u8]) -> Ordering {
+        a.cmp(b)
+    }
+
+    /// how many leading bytes every stored key sorting from `a` up to `b` starts with, the
+    /// prefix internal pages keep once for all their keys. none, unless the type says otherwise
+    fn shared_prefix(_a: &[u8], _b: &[u8]) -> usize {
+        0
+    }
+}
+
+/// how many leading bytes `a` and `b` have in common
+pub(crate) fn common_len(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b).take_while(|(x, y)| x == y).count()
 }
 
 pub trait Encodable {
@@ -11,8 +42,28 @@ pub trait Encodable {
 
 pub trait Decodable where Self: Sized{
     fn decode(buf: &[u8]) -> Result<(Self, usize)>;
+
+    /// checks `buf` holds a valid encoding without keeping the value, types whose decoding
+    /// allocates override this to skip the allocation
+    fn validate(buf: &[u8]) -> Result<()> {
+        Self::decode(buf).map(|_| ())
+    }
+
+    /// decodes over an existing value, types owning heap storage override this to reuse it
+    fn decode_into(&mut self, buf: &[u8]) -> Result<usize> {
+        let (val, size) = Self::decode(buf)?;
+        *self = val;
+        Ok(size)
+    }
+}
+
+/// a projection of a stored `V` that decodes only the encoded bytes it needs, such as
+/// a single field out of a wide record
+pub trait DecodePartial<V>: Sized {
+    fn decode_partial(buf: &[u8]) -> Result<Self>;
 }
 
+#[inline]
 pub fn check_len(buf: &[u8], size: usize) -> Result<()>{
     if buf.len() < size {
         Err(anyhow!("buf too short {} {}", buf.len(), size))
@@ -21,41 +72,58 @@ pub fn check_len(buf: &[u8], size: usize) -> Result<()>{
     }
 }
 
+// big-endian, which sorts unsigned ints by their bytes
 macro_rules! num_impl {
-    ($ty: ty, $size: tt) => {
+    ($ty: ty, $size: expr, $ordered: expr) => {
         impl BinSizer for $ty {
             #[inline]
             fn bin_size() -> usize {
                 $size
             }
+
+            #[inline]
+            fn ordered_encoding() -> bool {
+                $ordered
+            }
+
+            // signed ones too, the sign bit telling apart the keys of either sign
+            fn shared_prefix(a: &[u8], b: &[u8]) -> usize {
+                common_len(&a[..$size], &b[..$size])
+            }
         }
         impl Encodable for $ty {
             fn encode(&self, buf: &mut [u8]) -> Result<usize> {
                 check_len(buf, $size)?;
-                unsafe { *(&mut buf[0] as *mut _ as *mut _) = self.to_be() };
+                buf[..$size].copy_from_slice(&self.to_be_bytes());
                 Ok($size)
             }
         }
         impl Decodable for $ty {
             fn decode(buf: &[u8]) -> Result<(Self, usize)> {
                 check_len(buf, $size)?;
-                let val: $ty = unsafe { *(&buf[0] as *const _ as *const _) };
-                Ok((val.to_be(), $size))
+                let mut bytes = [0u8; $size];
+                bytes.copy_from_slice(&buf[..$size]);
+                Ok((<$ty>::from_be_bytes(bytes), $size))
+            }
+
+            #[inline]
+            fn validate(buf: &[u8]) -> Result<()> {
+                check_len(buf, $size)
             }
         }
     }
 }
 
-num_impl!(u8, 1);
-num_impl!(u16, 2);
-num_impl!(u32, 4);
-num_impl!(u64, 8);
-num_impl!(i8, 1);
-num_impl!(i16, 2);
-num_impl!(i32, 4);
-num_impl!(i64, 8);
-num_impl!(usize, (mem::size_of::<usize>()));
-num_impl!(isize, (mem::size_of::<isize>()));
+num_impl!(u8, 1, true);
+num_impl!(u16, 2, true);
+num_impl!(u32, 4, true);
+num_impl!(u64, 8, true);
+num_impl!(i8, 1, false);
+num_impl!(i16, 2, false);
+num_impl!(i32, 4, false);
+num_impl!(i64, 8, false);
+num_impl!(usize, mem::size_of::<usize>(), true);
+num_impl!(isize, mem::size_of::<isize>(), false);
 
 macro_rules! float_impl {
     ($ty: ty, $base: ty) => {
@@ -68,15 +136,19 @@ macro_rules! float_impl {
         impl Encodable for $ty {
             fn encode(&self, buf: &mut [u8]) -> Result<usize> {
                 check_len(buf, mem::size_of::<$base>())?;
-                let val: $base = unsafe { mem::transmute(*self) };
-                val.encode(buf)
+                self.to_bits().encode(buf)
             }
         }
         impl Decodable for $ty {
             fn decode(buf: &[u8]) -> Result<(Self, usize)> {
                 check_len(buf, mem::size_of::<$base>())?;
                 let (val, size) = <$base>::decode(buf)?;
-                Ok((unsafe {mem::transmute(val)}, size))
+                Ok((<$ty>::from_bits(val), size))
+            }
+
+            #[inline]
+            fn validate(buf: &[u8]) -> Result<()> {
+                check_len(buf, mem::size_of::<$base>())
             }
         }
     };
@@ -85,6 +157,383 @@ macro_rules! float_impl {
 float_impl!(f32, u32);
 float_impl!(f64, u64);
 
+macro_rules! ordered_float_impl {
+    ($name: ident, $ty: ty, $bits: ty) => {
+        /// a float key in the total order of `total_cmp`: negative nan first, then -inf up to
+        /// -0 and 0 and on to inf, then nan. the stored bytes sort the same way
+        #[derive(Debug, Clone, Copy, Default)]
+        pub struct $name(pub $ty);
+
+        impl $name {
+            // the sign bit flipped for positives and every bit for negatives turns the bits
+            // into an unsigned int of the same order
+            fn to_key(self) -> $bits {
+                let bits = self.0.to_bits();
+                if bits >> (<$bits>::BITS - 1) == 1 { !bits } else { bits ^ (1 << (<$bits>::BITS - 1)) }
+            }
+
+            fn from_key(key: $bits) -> Self {
+                let bits = if key >> (<$bits>::BITS - 1) == 1 { key ^ (1 << (<$bits>::BITS - 1)) } else { !key };
+                $name(<$ty>::from_bits(bits))
+            }
+        }
+
+        impl From<$ty> for $name {
+            fn from(val: $ty) -> Self {
+                $name(val)
+            }
+        }
+
+        impl PartialEq for $name {
+            fn eq(&self, other: &Self) -> bool {
+                self.0.total_cmp(&other.0) == std::cmp::Ordering::Equal
+            }
+        }
+
+        impl Eq for $name {}
+
+        impl PartialOrd for $name {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for $name {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.0.total_cmp(&other.0)
+            }
+        }
+
+        impl BinSizer for $name {
+            #[inline]
+            fn bin_size() -> usize {
+                mem::size_of::<$bits>()
+            }
+
+            #[inline]
+            fn ordered_encoding() -> bool {
+                true
+            }
+
+            fn shared_prefix(a: &[u8], b: &[u8]) -> usize {
+                <$bits>::shared_prefix(a, b)
+            }
+        }
+        impl Encodable for $name {
+            fn encode(&self, buf: &mut [u8]) -> Result<usize> {
+                self.to_key().encode(buf)
+            }
+        }
+        impl Decodable for $name {
+            fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+                let (key, size) = <$bits>::decode(buf)?;
+                Ok(($name::from_key(key), size))
+            }
+
+            #[inline]
+            fn validate(buf: &[u8]) -> Result<()> {
+                check_len(buf, mem::size_of::<$bits>())
+            }
+        }
+    };
+}
+
+ordered_float_impl!(OrderedF32, f32, u32);
+ordered_float_impl!(OrderedF64, f64, u64);
+
+// tuples encode field after field, and order field by field as rust tuples do
+macro_rules! tuple_impl {
+    ($($name: ident $field: ident),+) => {
+        impl<$($name: BinSizer),+> BinSizer for ($($name,)+) {
+            #[inline]
+            fn bin_size() -> usize {
+                0 $(+ $name::bin_size())+
+            }
+
+            fn ordered_encoding() -> bool {
+                true $(&& $name::ordered_encoding())+
+            }
+
+            // the orders of the fields, when one of them sorts in other than its natural one
+            fn key_order() -> Cow<'static, str> {
+                let orders = [$($name::key_order()),+];
+                if orders.iter().all(|order| order.is_empty()) {
+                    return Cow::Borrowed("");
+                }
+                let orders: Vec<&str> = orders.iter()
+                    .map(|order| if order.is_empty() { "natural" } else { order.as_ref() })
+                    .collect();
+                Cow::Owned(format!("({})", orders.join(", ")))
+            }
+
+            fn compare_encoded(a: &[u8], b: &[u8]) -> Ordering {
+                let mut at = 0;
+                $(
+                    let size = $name::bin_size();
+                    match $name::compare_encoded(&a[at..at + size], &b[at..at + size]) {
+                        Ordering::Equal => at += size,
+                        order => return order
+                    }
+                )+
+                let _ = at;
+                Ordering::Equal
+            }
+
+            // a field shared only in part ends the prefix
+            fn shared_prefix(a: &[u8], b: &[u8]) -> usize {
+                let mut at = 0;
+                $(
+                    let size = $name::bin_size();
+                    let shared = $name::shared_prefix(&a[at..at + size], &b[at..at + size]);
+                    if shared < size {
+                        return at + shared;
+                    }
+                    at += size;
+                )+
+                at
+            }
+        }
+        impl<$($name: Encodable + BinSizer),+> Encodable for ($($name,)+) {
+            fn encode(&self, buf: &mut [u8]) -> Result<usize> {
+                check_len(buf, Self::bin_size())?;
+                let ($($field,)+) = self;
+                let mut at = 0;
+                $(
+                    $field.encode(&mut buf[at..])?;
+                    at += $name::bin_size();
+                )+
+                Ok(at)
+            }
+        }
+        impl<$($name: Decodable + BinSizer),+> Decodable for ($($name,)+) {
+            fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+                check_len(buf, Self::bin_size())?;
+                let mut at = 0;
+                $(
+                    let $field = $name::decode(&buf[at..])?.0;
+                    at += $name::bin_size();
+                )+
+                Ok((($($field,)+), at))
+            }
+
+            fn validate(buf: &[u8]) -> Result<()> {
+                check_len(buf, Self::bin_size())?;
+                let mut at = 0;
+                $(
+                    $name::validate(&buf[at..])?;
+                    at += $name::bin_size();
+                )+
+                let _ = at;
+                Ok(())
+            }
+        }
+    };
+}
+
+tuple_impl!(A a, B b);
+tuple_impl!(A a, B b, C c);
+tuple_impl!(A a, B b, C c, D d);
+
+// a tag byte, 0 for none and 1 for some, then room for the payload either way
+impl<T: BinSizer> BinSizer for Option<T> {
+    #[inline]
+    fn bin_size() -> usize {
+        1 + T::bin_size()
+    }
+
+    fn ordered_encoding() -> bool {
+        T::ordered_encoding()
+    }
+
+    fn key_order() -> Cow<'static, str> {
+        match T::key_order() {
+            order if order.is_empty() => order,
+            order => Cow::Owned(format!("none first, then {}", order))
+        }
+    }
+
+    // none first, whatever the zeroed payload of a none would say
+    fn compare_encoded(a: &[u8], b: &[u8]) -> Ordering {
+        match a[0].cmp(&b[0]) {
+            Ordering::Equal if a[0] == 1 => T::compare_encoded(&a[1..], &b[1..]),
+            order => order
+        }
+    }
+
+    fn shared_prefix(a: &[u8], b: &[u8]) -> usize {
+        if a[0] == 1 && b[0] == 1 { 1 + T::shared_prefix(&a[1..], &b[1..]) } else { 0 }
+    }
+}
+
+impl<T: Encodable + BinSizer> Encodable for Option<T> {
+    fn encode(&self, buf: &mut [u8]) -> Result<usize> {
+        check_len(buf, Self::bin_size())?;
+        match self {
+            Some(val) => {
+                buf[0] = 1;
+                val.encode(&mut buf[1..])?;
+            }
+            None => {
+                for b in buf[..Self::bin_size()].iter_mut() {
+                    *b = 0;
+                }
+            }
+        }
+        Ok(Self::bin_size())
+    }
+}
+
+impl<T: Decodable + BinSizer> Decodable for Option<T> {
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        check_len(buf, Self::bin_size())?;
+        match buf[0] {
+            0 => Ok((None, Self::bin_size())),
+            1 => Ok((Some(T::decode(&buf[1..])?.0), Self::bin_size())),
+            tag => Err(anyhow!("an option tagged {}", tag))
+        }
+    }
+
+    fn validate(buf: &[u8]) -> Result<()> {
+        check_len(buf, Self::bin_size())?;
+        match buf[0] {
+            0 => Ok(()),
+            1 => T::validate(&buf[1..]),
+            tag => Err(anyhow!("an option tagged {}", tag))
+        }
+    }
+
+    fn decode_into(&mut self, buf: &[u8]) -> Result<usize> {
+        match (self.as_mut(), buf.first()) {
+            (Some(val), Some(1)) => {
+                val.decode_into(&buf[1..])?;
+                Ok(Self::bin_size())
+            }
+            _ => {
+                let (val, size) = Self::decode(buf)?;
+                *self = val;
+                Ok(size)
+            }
+        }
+    }
+}
+
+/// up to `N` raw bytes, such as an already serialized blob, stored behind a 4 byte length.
+/// each one takes `N + 4` bytes on the page whatever its own length
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FixedBytes<const N: usize>(Vec<u8>);
+
+impl<const N: usize> FixedBytes<N> {
+    pub fn new(bytes: &[u8]) -> Self {
+        Self::from(bytes.to_vec())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_vec(self) -> Vec<u8> {
+        self.0
+    }
+
+    // the stored bytes behind their length
+    fn decode_bytes(buf: &[u8]) -> Result<&[u8]> {
+        check_len(buf, N + 4)?;
+        let len = u32::decode(buf)?.0 as usize;
+        if len > N {
+            return Err(anyhow!("a length of {} over the {} byte capacity", len, N));
+        }
+        Ok(&buf[4..4 + len])
+    }
+}
+
+impl<const N: usize> From<Vec<u8>> for FixedBytes<N> {
+    fn from(bytes: Vec<u8>) -> Self {
+        assert!(bytes.len() <= N);
+        Self(bytes)
+    }
+}
+
+impl<const N: usize> From<&[u8]> for FixedBytes<N> {
+    fn from(bytes: &[u8]) -> Self {
+        Self::new(bytes)
+    }
+}
+
+impl<const N: usize> core::ops::Deref for FixedBytes<N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<const N: usize> BinSizer for FixedBytes<N> {
+    #[inline]
+    fn bin_size() -> usize {
+        N + 4
+    }
+}
+
+impl<const N: usize> Encodable for FixedBytes<N> {
+    fn encode(&self, buf: &mut [u8]) -> Result<usize> {
+        check_len(buf, N + 4)?;
+        let len = self.0.len();
+        if len > N {
+            return Err(anyhow!("{} bytes do not fit in {}", len, N));
+        }
+        (len as u32).encode(buf)?;
+        buf[4..4 + len].copy_from_slice(&self.0);
+        // zeroed past the end, so equal blobs encode alike
+        for b in buf[4 + len..N + 4].iter_mut() {
+            *b = 0;
+        }
+        Ok(N + 4)
+    }
+}
+
+impl<const N: usize> Decodable for FixedBytes<N> {
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        Ok((Self(Self::decode_bytes(buf)?.to_vec()), N + 4))
+    }
+
+    fn decode_into(&mut self, buf: &[u8]) -> Result<usize> {
+        let bytes = Self::decode_bytes(buf)?;
+        self.0.clear();
+        self.0.extend_from_slice(bytes);
+        Ok(N + 4)
+    }
+
+    fn validate(buf: &[u8]) -> Result<()> {
+        Self::decode_bytes(buf).map(|_| ())
+    }
+}
+
+// the `ArrowField` impl `define_fixed_len_str!` gives its types, a utf8 column, with the
+// `arrow` feature and nothing without it
+#[cfg(feature = "arrow")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __arrow_str_field {
+    ($name: ident) => {
+        impl $crate::ArrowField for $name {
+            fn data_type() -> $crate::__arrow_schema::DataType {
+                $crate::__arrow_schema::DataType::Utf8
+            }
+
+            fn to_array(values: Vec<Self>) -> $crate::__arrow_array::ArrayRef {
+                std::sync::Arc::new($crate::__arrow_array::StringArray::from_iter_values(values.iter().map(|v| v.as_str())))
+            }
+        }
+    };
+}
+
+#[cfg(not(feature = "arrow"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __arrow_str_field {
+    ($name: ident) => {};
+}
+
 #[macro_export]
 macro_rules! define_fixed_len_str {
     ($name: ident, $capacity: expr) => {
@@ -96,6 +545,23 @@ macro_rules! define_fixed_len_str {
             fn bin_size() -> usize {
                 $capacity
             }
+
+            #[inline]
+            fn ordered_encoding() -> bool {
+                true
+            }
+
+            // what follows the zero ending a shorter string is left over from before
+            fn compare_encoded(a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+                let end = |buf: &[u8]| buf.iter().position(|b| *b == 0).unwrap_or($capacity);
+                a[..end(a)].cmp(&b[..end(b)])
+            }
+
+            // the bytes past the end of a string are no part of the prefix
+            fn shared_prefix(a: &[u8], b: &[u8]) -> usize {
+                let end = |buf: &[u8]| buf.iter().position(|b| *b == 0).unwrap_or($capacity);
+                a[..end(a)].iter().zip(&b[..end(b)]).take_while(|(x, y)| x == y).count()
+            }
         }
 
         impl From<String> for $name {
@@ -116,11 +582,14 @@ macro_rules! define_fixed_len_str {
             fn encode(&self, buf: &mut [u8]) -> anyhow::Result<usize> {
                 check_len(buf, $capacity)?;
                 let bytes = self.0.as_bytes();
+                if bytes.len() > $capacity {
+                    return Err(anyhow::anyhow!("{} bytes do not fit in {}", bytes.len(), stringify!($name)));
+                }
                 unsafe {
                     std::ptr::copy_nonoverlapping(bytes.as_ptr(), &mut buf[0], bytes.len());
                 }
                 // std::ptr::copy_nonoverlapping(bytes, buf, bytes.len());
-                if bytes.len() < $capacity - 1 {
+                if bytes.len() < $capacity {
                     buf[bytes.len()] = 0;
                 }
                 // std::io::Write::write(buf, self.0.as_bytes())?;
@@ -129,22 +598,181 @@ macro_rules! define_fixed_len_str {
         }
         impl Decodable for $name {
             fn decode(buf: &[u8]) -> anyhow::Result<(Self, usize)> {
-                let mut str_end_i = $capacity;
-                for i in 0..$capacity {
-                    if buf[i] == 0 {
-                        str_end_i = i;
-                        break;
-                    }
-                }
-                let s = std::str::from_utf8(&buf[..str_end_i])?;
+                let s = Self::decode_str(buf)?;
                 Ok((Self(s.to_owned()), $capacity))
             }
+
+            fn decode_into(&mut self, buf: &[u8]) -> anyhow::Result<usize> {
+                let s = Self::decode_str(buf)?;
+                self.0.clear();
+                self.0.push_str(s);
+                Ok($capacity)
+            }
+
+            fn validate(buf: &[u8]) -> anyhow::Result<()> {
+                Self::decode_str(buf).map(|_| ())
+            }
+        }
+
+        impl $crate::TextField for $name {
+            fn to_text(&self) -> String {
+                self.0.clone()
+            }
+
+            fn from_text(s: &str) -> anyhow::Result<Self> {
+                if s.len() > $capacity {
+                    return Err(anyhow::anyhow!("{} bytes do not fit in {}", s.len(), stringify!($name)));
+                }
+                Ok(Self(s.to_owned()))
+            }
+
+            fn is_str() -> bool {
+                true
+            }
         }
 
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                &self.0
+            }
+        }
+
+        $crate::__arrow_str_field!($name);
+
         impl $name {
             pub fn new(s: &str) -> Self{
                 Self(s.to_owned())
             }
+
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+
+            // the stored string runs up to the first zero byte, or fills the whole capacity
+            fn decode_str(buf: &[u8]) -> anyhow::Result<&str> {
+                check_len(buf, $capacity)?;
+                let buf = &buf[..$capacity];
+                let str_end_i = buf.iter().position(|b| *b == 0).unwrap_or($capacity);
+                Ok(std::str::from_utf8(&buf[..str_end_i])?)
+            }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fmt::Debug;
+
+    fn encoded<T: Encodable + BinSizer>(val: &T) -> Vec<u8> {
+        let mut buf = vec![0u8; T::bin_size()];
+        val.encode(&mut buf).unwrap();
+        buf
+    }
+
+    // every pair of `vals` compares the same encoded as `cmp` has them, and each decodes back
+    fn check_order<T, F>(vals: &[T], cmp: F)
+        where
+            T: Encodable + Decodable + BinSizer + Debug,
+            F: Fn(&T, &T) -> Ordering
+    {
+        assert!(T::ordered_encoding());
+        for a in vals {
+            let a_bytes = encoded(a);
+            assert_eq!(cmp(&T::decode(&a_bytes).unwrap().0, a), Ordering::Equal, "{:?} does not decode back", a);
+            for b in vals {
+                let b_bytes = encoded(b);
+                assert_eq!(T::compare_encoded(&a_bytes, &b_bytes), cmp(a, b), "{:?} against {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn ordered_floats_encode_in_total_order() {
+        let f64s = [-f64::NAN, f64::NEG_INFINITY, f64::MIN, -1.5, -f64::MIN_POSITIVE, -1e-310, -0.0,
+                    0.0, 1e-310, f64::MIN_POSITIVE, 1.5, f64::MAX, f64::INFINITY, f64::NAN];
+        let keys: Vec<OrderedF64> = f64s.iter().map(|f| OrderedF64(*f)).collect();
+        check_order(&keys, |a, b| a.0.total_cmp(&b.0));
+        // the bytes alone sort that way too, and the bits come back as they went in
+        for pair in keys.windows(2) {
+            assert!(encoded(&pair[0]) < encoded(&pair[1]), "{:?} against {:?}", pair[0], pair[1]);
+        }
+        for key in &keys {
+            assert_eq!(OrderedF64::decode(&encoded(key)).unwrap().0 .0.to_bits(), key.0.to_bits());
+        }
+
+        let f32s = [-f32::NAN, f32::NEG_INFINITY, f32::MIN, -1.5, -1e-40, -0.0, 0.0, 1e-40, 1.5,
+                    f32::MAX, f32::INFINITY, f32::NAN];
+        let keys: Vec<OrderedF32> = f32s.iter().map(|f| OrderedF32(*f)).collect();
+        check_order(&keys, |a, b| a.0.total_cmp(&b.0));
+        for pair in keys.windows(2) {
+            assert!(encoded(&pair[0]) < encoded(&pair[1]), "{:?} against {:?}", pair[0], pair[1]);
+        }
+        for key in &keys {
+            assert_eq!(OrderedF32::decode(&encoded(key)).unwrap().0 .0.to_bits(), key.0.to_bits());
+        }
+    }
+
+    // every key sorting from one of `sorted` up to another starts with the prefix the two share
+    fn check_prefixes<T: Encodable + BinSizer + Debug>(sorted: &[T]) {
+        let bytes: Vec<Vec<u8>> = sorted.iter().map(encoded).collect();
+        for i in 0..bytes.len() {
+            for j in i..bytes.len() {
+                let shared = T::shared_prefix(&bytes[i], &bytes[j]);
+                assert!(shared <= common_len(&bytes[i], &bytes[j]), "{:?} and {:?}", sorted[i], sorted[j]);
+                for between in &bytes[i..=j] {
+                    assert_eq!(between[..shared], bytes[i][..shared], "{:?} and {:?}", sorted[i], sorted[j]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tuples_encode_in_field_by_field_order() {
+        let floats = [f64::NEG_INFINITY, -1.5, -0.0, 0.0, 2.0, f64::NAN];
+        let ints = [0u32, 1, 255, 256, u32::MAX];
+        let mut pairs: Vec<(OrderedF64, u32)> = floats.iter()
+            .flat_map(|f| ints.iter().map(move |i| (OrderedF64(*f), *i)))
+            .collect();
+        check_order(&pairs, |a, b| a.cmp(b));
+        pairs.sort();
+        check_prefixes(&pairs);
+
+        let mut triples = Vec::new();
+        for a in [0u8, 7, 255] {
+            for b in [-2.5f32, -0.0, 0.0, 1e-40, f32::INFINITY] {
+                for c in [0u16, 256, 65535] {
+                    triples.push((a, OrderedF32(b), c));
+                }
+            }
+        }
+        check_order(&triples, |a, b| a.cmp(b));
+        triples.sort();
+        check_prefixes(&triples);
+
+        // a field of signed ints, whose bytes do not sort as they do, leaves the tuple unordered
+        assert!(!<(i32, u32)>::ordered_encoding());
+        let mixed = (-5i32, 7u32);
+        assert_eq!(<(i32, u32)>::decode(&encoded(&mixed)).unwrap().0, mixed);
+    }
+
+    #[test]
+    fn options_encode_none_before_every_some() {
+        let vals = [None, Some(0u32), Some(1), Some(256), Some(u32::MAX)];
+        check_order(&vals, |a, b| a.cmp(b));
+        check_prefixes(&vals);
+        let floats = [None, Some(OrderedF64(f64::NEG_INFINITY)), Some(OrderedF64(-0.0)), Some(OrderedF64(0.0)),
+                      Some(OrderedF64(f64::NAN))];
+        check_order(&floats, |a, b| a.cmp(b));
+        check_prefixes(&floats);
+
+        // a none sorts first whatever its payload bytes hold
+        let mut none = encoded(&None::<u32>);
+        none[1..].copy_from_slice(&[0xff; 4]);
+        assert_eq!(Option::<u32>::compare_encoded(&none, &encoded(&None::<u32>)), Ordering::Equal);
+        assert_eq!(Option::<u32>::compare_encoded(&none, &encoded(&Some(0u32))), Ordering::Less);
+        assert_eq!(Option::<u32>::decode(&none).unwrap().0, None);
+        none[0] = 2;
+        assert!(Option::<u32>::decode(&none).is_err());
+        assert!(Option::<u32>::validate(&none).is_err());
+    }
+}
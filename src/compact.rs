@@ -0,0 +1,110 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::journal::journal_path;
+use crate::page::{Page, PageType, Slot, Stat};
+use crate::BTree;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    /// moves the pages in use past the first gaps into them, so free pages, orphans and spares
+    /// all end up at the end of the file, and cuts the file there. the named trees move along
+    /// with the file's own, every page keeping the entries it holds. the pages are written out
+    /// as one like a transaction's, a crash on the way leaving the file as it was.
+    /// returns how many pages the file shrank by
+    pub fn compact(&mut self) -> Result<u32, BTreeError> {
+        if self.read_only {
+            return Err(BTreeError::ReadOnly { path: self.path.clone() });
+        }
+        if self.cow.is_some() {
+            return Err(anyhow!("compacting is not done copy-on-write").into());
+        }
+        self.sync()?;
+        let live = self.live_pages()?;
+        let kept = live.iter().filter(|l| **l).count() as u32;
+        let file_pages = self.fd.lock().unwrap().file_pages()?;
+        self.fd.lock().unwrap().defer()?;
+        let moved = self.move_pages(&live, kept)
+            .and_then(|_| self.sync())
+            .and_then(|_| self.fd.lock().unwrap().commit_deferred(&journal_path(&self.path)));
+        if let Err(e) = moved {
+            self.abandon()?;
+            return Err(e.into());
+        }
+        // the meta page no longer counts the pages cut, a crash before they are gone leaves them be
+        self.fd.lock().unwrap().truncate(kept)?;
+        self.finish_write()?;
+        event!(info, path:% = self.path.display(), pages = kept, cut = file_pages.saturating_sub(kept as u64); "compacted a tree");
+        Ok(file_pages.saturating_sub(kept as u64) as u32)
+    }
+
+    fn move_pages(&mut self, live: &[bool], kept: u32) -> Result<()> {
+        // the pages in use past the first `kept` fill the gaps among them, in order
+        let gaps = (0..kept).filter(|i| !live[*i as usize]);
+        let movers = (kept..live.len() as u32).filter(|i| live[*i as usize]);
+        let moves: HashMap<u32, u32> = movers.zip(gaps).collect();
+        let to = |index: u32| moves.get(&index).copied().unwrap_or(index);
+
+        self.root_page = None;
+        for (from, index) in moves.iter() {
+            let page = Page::<K, V>::load(self.fd.clone(), *from)?;
+            // the copy takes the page's type along with the rest of it
+            let mut copy = Page::<K, V>::reuse(self.fd.clone(), *index, PageType::FREE)?;
+            copy.copy_from(&page);
+        }
+        // every pointer to a page moved points at its new place
+        let second = self.meta_page.as_ref().unwrap().second_meta();
+        for index in (1..kept).filter(|i| Some(*i) != second) {
+            let mut p = Page::<K, V>::load(self.fd.clone(), index)?;
+            match p.page_type {
+                PageType::INTERNAL => for i in 0..=p.item_count() {
+                    let ptr = p.ptr_at(i).unwrap();
+                    if to(ptr) != ptr {
+                        p.set_ptr_at(i, to(ptr))?;
+                    }
+                },
+                PageType::LEAF => for i in 0..p.item_count() {
+                    match p.spilled_at(i) {
+                        Some(first) if to(first) != first => p.set_value_at(i, &Slot::Spilled(to(first)))?,
+                        _ => {}
+                    }
+                },
+                PageType::OVERFLOW if to(p.chain_next()) != p.chain_next() => {
+                    let next = to(p.chain_next());
+                    p.set_chain_next(next);
+                }
+                PageType::CATALOG => for i in 0..p.catalog_len() {
+                    let root = p.catalog_root(i);
+                    if to(root) != root {
+                        p.set_catalog_root(i, to(root));
+                    }
+                },
+                _ => {}
+            }
+        }
+
+        let meta_page = self.meta_page.as_mut().unwrap();
+        meta_page.set_root_index(to(meta_page.root_index()));
+        if meta_page.catalog_index() != 0 {
+            meta_page.set_catalog_index(to(meta_page.catalog_index()));
+        }
+        meta_page.set_free_list(0, 0);
+        while meta_page.pop_spare().is_some() {}
+        meta_page.set_total_page(kept);
+        meta_page.set_high_water(kept);
+        let root = meta_page.root_index();
+        self.touch(Stat::LastCompaction);
+        // the pages cut drop out of the digest, no longer counted past the end of the tree
+        let mut fd = self.fd.lock().unwrap();
+        let digest = fd.compute_digest(kept, &second.into_iter().collect::<Vec<u32>>())?;
+        fd.set_digest(digest);
+        drop(fd);
+        self.root_page = Some(Page::<K, V>::load_node(self.fd.clone(), root)?);
+        Ok(())
+    }
+}
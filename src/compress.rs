@@ -0,0 +1,114 @@
+use anyhow::{anyhow, Result};
+#[cfg(feature = "lz4")]
+use crate::byte::{Encodable, Decodable, BinSizer};
+#[cfg(feature = "lz4")]
+use crate::error::BTreeError;
+#[cfg(feature = "lz4")]
+use crate::BTree;
+#[cfg(feature = "lz4")]
+use std::fmt::Debug;
+#[cfg(feature = "lz4")]
+use std::fs::File;
+
+// a page written compressed starts with this tag, then the codec and the length of the
+// compressed image following the header. the rest of the page is left a hole in the file
+pub(crate) const PACKED_TAG: u8 = 0x40;
+#[cfg(feature = "lz4")]
+const PACKED_HEADER: usize = 8;
+#[cfg(feature = "lz4")]
+pub(crate) const LZ4: u8 = 1;
+// a hole frees whole file system blocks only, so a page has to shrink by one to be worth it
+#[cfg(feature = "lz4")]
+const BLOCK: usize = 4096;
+
+/// compresses `page` into `out`, returning how many bytes of `out` to write in its place.
+/// None when that would not free a block of the page
+#[cfg(feature = "lz4")]
+pub(crate) fn pack(page: &[u8], out: &mut Vec<u8>) -> Option<usize> {
+    if page.len() <= BLOCK {
+        return None;
+    }
+    // lz4 wants room for the worst case up front, however well the page compresses
+    out.resize(PACKED_HEADER + lz4_flex::block::get_maximum_output_size(page.len()), 0);
+    let len = lz4_flex::block::compress_into(page, &mut out[PACKED_HEADER..]).ok()?;
+    if PACKED_HEADER + len > page.len() - BLOCK {
+        return None;
+    }
+    out[0] = PACKED_TAG;
+    out[1] = LZ4;
+    (len as u16).encode(&mut out[2..]).unwrap();
+    out[4..PACKED_HEADER].fill(0);
+    Some(PACKED_HEADER + len)
+}
+
+/// turns a compressed page read from the file back into the page, leaving others alone
+pub(crate) fn unpack(buf: &mut [u8], scratch: &mut Vec<u8>) -> Result<()> {
+    if buf[0] != PACKED_TAG {
+        return Ok(());
+    }
+    unpack_lz4(buf, scratch)
+}
+
+#[cfg(feature = "lz4")]
+fn unpack_lz4(buf: &mut [u8], scratch: &mut Vec<u8>) -> Result<()> {
+    if buf[1] != LZ4 {
+        return Err(anyhow!("a page compressed with unknown codec {}", buf[1]));
+    }
+    let len = u16::decode(&buf[2..])?.0 as usize;
+    if PACKED_HEADER + len > buf.len() {
+        return Err(anyhow!("a compressed page of {} bytes overruns the page", len));
+    }
+    scratch.clear();
+    scratch.extend_from_slice(&buf[PACKED_HEADER..PACKED_HEADER + len]);
+    let size = lz4_flex::block::decompress_into(scratch, buf).map_err(|e| anyhow!("a compressed page does not decompress: {}", e))?;
+    if size != buf.len() {
+        return Err(anyhow!("a compressed page decompresses to {} bytes, not {}", size, buf.len()));
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "lz4"))]
+fn unpack_lz4(_buf: &mut [u8], _scratch: &mut Vec<u8>) -> Result<()> {
+    Err(anyhow!("the file holds compressed pages, reading them takes the lz4 feature"))
+}
+
+/// frees the file system blocks from `offset` on for `len` bytes, which read back as zeros.
+/// where holes cannot be punched the bytes are just left as they are, nothing reading them
+#[cfg(feature = "lz4")]
+pub(crate) fn punch_hole(file: &File, offset: u64, len: u64) {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+        let start = offset.div_ceil(BLOCK as u64) * BLOCK as u64;
+        let end = offset + len;
+        if start < end {
+            unsafe {
+                libc::fallocate(file.as_raw_fd(), libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+                    start as libc::off_t, (end - start) as libc::off_t);
+            }
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (file, offset, len);
+}
+
+#[cfg(feature = "lz4")]
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    /// compresses every page but the meta page with lz4 as it gets written from now on, the
+    /// choice kept in the file. a page takes up whole file system blocks still, so only pages
+    /// larger than 4096 bytes shrink on disk; pages already written stay as they are until
+    /// rewritten, and either kind reads back the same
+    pub fn set_page_compression(&mut self, on: bool) -> Result<(), BTreeError> {
+        if self.read_only {
+            return Err(BTreeError::ReadOnly { path: self.path.clone() });
+        }
+        self.meta_page.as_mut().unwrap().set_compression(if on { LZ4 } else { 0 });
+        self.fd.lock().unwrap().set_compression(on);
+        self.sync()?;
+        Ok(self.finish_write()?)
+    }
+}
@@ -0,0 +1,31 @@
+use crate::build::Builder;
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::page::PAGE_SIZE;
+use crate::BTree;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::path::Path;
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + Ord + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    /// reads every entry into memory
+    pub fn to_btreemap(&self) -> BTreeMap<K, V> {
+        self.iter().collect()
+    }
+
+    /// bulk builds a new tree file at `path` holding the entries of `map`,
+    /// failing if the file already exists
+    pub fn from_btreemap<P: AsRef<Path>>(path: P, map: &BTreeMap<K, V>) -> Result<Self, BTreeError> {
+        let mut builder = Builder::<K, V>::create(path.as_ref(), PAGE_SIZE)?;
+        for (k, v) in map.iter() {
+            builder.push(k, v)?;
+        }
+        builder.finish()?;
+        BTree::open(path)
+    }
+}
@@ -0,0 +1,225 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::page::{Page, PageType, MAX_SPARES, corrupted};
+use crate::{BTree, Durability, MAX_DEPTH};
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+
+/// what a tree writing copy-on-write tracks between the start of a write and the meta page
+/// switching over to its result
+#[derive(Default)]
+pub(crate) struct CopyOnWrite {
+    // handed out during the current write, nothing on disk points at them yet
+    pub fresh: HashSet<u32>,
+    // dropped by the current write, still part of the tree on disk until the switch
+    pub retired: Vec<u32>,
+    // retired at an earlier switch with the spare list full, put on the free list at the next
+    pub overflow: Vec<u32>,
+}
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    /// with copy-on-write on, a write never changes a page the tree on disk uses: the pages it
+    /// changes are written to new places, as are the pages above them up to a new root, and the
+    /// file is synced before and after the meta page switches to that root. a crash leaves the
+    /// tree as of the last write that finished. each write syncing the file twice, this is a
+    /// lot slower than writing in place
+    pub fn set_copy_on_write(&mut self, on: bool) -> Result<(), BTreeError> {
+        if self.read_only {
+            return Err(BTreeError::ReadOnly { path: self.path.clone() });
+        }
+        if on == self.cow.is_some() {
+            return Ok(());
+        }
+        if on {
+            self.sync()?;
+            self.fd.lock().unwrap().defer()?;
+            self.cow = Some(CopyOnWrite::default());
+            return Ok(());
+        }
+        let cow = self.cow.take().unwrap();
+        for index in cow.overflow {
+            self.free_page(index)?;
+        }
+        self.sync()?;
+        Ok(self.fd.lock().unwrap().stop_deferring()?)
+    }
+
+    /// gets a write just done onto disk without overwriting what it changed. pages the tree on
+    /// disk uses that the write changed move to spare pages or the end of the file, and so do
+    /// the pages above them; the pages they left become spares once the meta page has switched
+    pub(crate) fn finish_write(&mut self) -> Result<()> {
+        if self.cow.is_none() {
+            return match self.durability {
+                Durability::SyncEveryWrite => Ok(self.flush()?),
+                _ => Ok(())
+            };
+        }
+        self.sync()?;
+        // every page on disk to move, with how deep it sits and the page pointing at it
+        let mut moving: HashMap<u32, (usize, u32)> = HashMap::new();
+        let dirty = self.fd.lock().unwrap().dirty_pages();
+        for index in dirty {
+            if index == 0 || self.cow.as_ref().unwrap().fresh.contains(&index) {
+                continue;
+            }
+            let path = self.path_to(index)?;
+            for (depth, page) in path.iter().enumerate() {
+                if !self.cow.as_ref().unwrap().fresh.contains(page) {
+                    // 0 standing for the meta page above the root
+                    let parent = if depth == 0 { 0 } else { path[depth - 1] };
+                    moving.insert(*page, (depth, parent));
+                }
+            }
+        }
+        let mut moving: Vec<(u32, (usize, u32))> = moving.into_iter().collect();
+        moving.sort_unstable_by_key(|(_, (depth, _))| std::cmp::Reverse(*depth));
+        for (index, (_, parent)) in moving {
+            let old = Page::<K, V>::load_node(self.fd.clone(), index)?;
+            let mut new = self.new_page(PageType::LEAF)?;
+            new.copy_from(&old);
+            let moved_to = new.index;
+            drop(new);
+            drop(old);
+            self.fd.lock().unwrap().forget_page(index)?;
+            self.cow.as_mut().unwrap().retired.push(index);
+            if parent == 0 {
+                self.meta_page.as_mut().unwrap().set_root_index(moved_to);
+            } else {
+                Page::<K, V>::load_node(self.fd.clone(), parent)?.replace_ptr(index, moved_to)?;
+            }
+        }
+        let root_index = self.meta_page.as_ref().unwrap().root_index();
+        self.root_page = Some(Page::<K, V>::load_node(self.fd.clone(), root_index)?);
+
+        self.stock_spares()?;
+        self.sync()?;
+        self.fd.lock().unwrap().write_ordered()?;
+        self.cow.as_mut().unwrap().fresh.clear();
+        Ok(())
+    }
+
+    /// the pages from the root down to page `index`, found by following its first key
+    fn path_to(&self, index: u32) -> Result<Vec<u32>> {
+        let root = self.root_page.as_ref().unwrap();
+        let mut path = vec![root.index];
+        if index == root.index {
+            return Ok(path);
+        }
+        let key = Page::<K, V>::load_node(self.fd.clone(), index)?.key_at(0)
+            .ok_or_else(|| corrupted(index, "a page changed by a write is left without keys".to_owned()))?;
+        let mut next = root.child_for(&key);
+        while next != index {
+            if path.len() == MAX_DEPTH {
+                return Err(corrupted(index, "a page changed by a write is not in the tree".to_owned()));
+            }
+            path.push(next);
+            let p = Page::<K, V>::load_node(self.fd.clone(), next)?;
+            if p.page_type != PageType::INTERNAL {
+                return Err(corrupted(index, "a page changed by a write is not in the tree".to_owned()));
+            }
+            next = p.child_for(&key);
+        }
+        path.push(index);
+        Ok(path)
+    }
+
+    /// lists the pages the write retired as spares for the writes to come, topping the spares
+    /// up from the free list. taking a page off the free list leaves it as it is on disk, so
+    /// the tree there is never touched
+    fn stock_spares(&mut self) -> Result<()> {
+        let cow = self.cow.as_mut().unwrap();
+        // garbage to the tree on disk since the last switch, free to overwrite now
+        let overflow = std::mem::take(&mut cow.overflow);
+        let retired = std::mem::take(&mut cow.retired);
+        for index in overflow {
+            if !self.add_spare(index)? {
+                self.link_free(Page::<K, V>::reuse(self.fd.clone(), index, PageType::FREE)?);
+            }
+        }
+        while self.meta_page.as_ref().unwrap().spares().len() < MAX_SPARES / 2 {
+            let head = self.meta_page.as_ref().unwrap().free_head();
+            if head == 0 {
+                break;
+            }
+            let page = Page::<K, V>::load(self.fd.clone(), head)?;
+            if page.page_type != PageType::FREE {
+                return Err(corrupted(head, format!("a {:?} page is on the free list", page.page_type)));
+            }
+            let meta_page = self.meta_page.as_mut().unwrap();
+            meta_page.set_free_list(page.next_free(), meta_page.free_count() - 1);
+            drop(page);
+            self.add_spare(head)?;
+        }
+        for index in retired {
+            if !self.add_spare(index)? {
+                self.cow.as_mut().unwrap().overflow.push(index);
+            }
+        }
+        Ok(())
+    }
+
+    /// lists page `index` as a spare, taking what it holds out of the digest. false when the
+    /// list is full
+    pub(crate) fn add_spare(&mut self, index: u32) -> Result<bool> {
+        if !self.meta_page.as_mut().unwrap().push_spare(index) {
+            return Ok(false);
+        }
+        let mut fd = self.fd.lock().unwrap();
+        let crc = fd.image_crc(index)?;
+        fd.roll_digest(crc, 0);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BTree, PAGE_SIZE, WriteBatch};
+    use std::fs::{self, File, OpenOptions};
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    #[test]
+    fn a_write_cut_short_leaves_the_old_root() {
+        let path = std::env::temp_dir().join(format!("btree-cow-test-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        {
+            let mut tree = BTree::<u32, u64>::open_or_create(&path).unwrap();
+            tree.set_copy_on_write(true).unwrap();
+            for i in 0..3000u32 {
+                tree.set(&i, &(i as u64)).unwrap();
+            }
+        }
+        let before: Vec<(u32, u64)> = BTree::open_read_only(&path).unwrap().iter().collect();
+        // both places of the meta page, as they were before the write got to switch either
+        let mut meta = vec![0u8; 2 * PAGE_SIZE];
+        File::open(&path).unwrap().read_exact(&mut meta).unwrap();
+        {
+            let mut tree = BTree::<u32, u64>::open(&path).unwrap();
+            tree.set_copy_on_write(true).unwrap();
+            let mut batch = WriteBatch::new();
+            for i in 1000..6000u32 {
+                batch.set(&i, &0);
+            }
+            for i in 0..500u32 {
+                batch.delete(&i);
+            }
+            tree.write_batch(batch).unwrap();
+        }
+        // the new pages are on disk, the meta page never switched to them
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.write_all(&meta).unwrap();
+        drop(file);
+
+        let tree = BTree::<u32, u64>::open(&path).unwrap();
+        assert_eq!(tree.len(), before.len() as u64);
+        assert_eq!(tree.iter().collect::<Vec<(u32, u64)>>(), before);
+        assert!(tree.verify().unwrap().is_empty());
+        drop(tree);
+        fs::remove_file(&path).unwrap();
+    }
+}
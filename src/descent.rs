@@ -0,0 +1,72 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::page::{Page, PageType, corrupted};
+use crate::{BTree, MAX_DEPTH};
+use anyhow::Result;
+use std::fmt::Debug;
+
+/// the internal pages below the root the last descent went through, top down. they stay
+/// pinned until an internal page gets written, so a key landing in the same subtree as the
+/// one before loads no more than its leaf
+pub(crate) struct PinnedPath<K, V> {
+    // the pager's generation the pages were pinned in
+    generation: u64,
+    pages: Vec<Page<K, V>>,
+}
+
+impl<K, V> Default for PinnedPath<K, V> {
+    fn default() -> Self {
+        PinnedPath { generation: 0, pages: Vec::new() }
+    }
+}
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    /// the leaf `key` belongs in, None when the root is the only leaf. `visit` sees every
+    /// internal page on the way down below the root, the pinned ones included
+    pub(crate) fn descend(&self, key: &K, mut visit: impl FnMut(&Page<K, V>)) -> Result<Option<Page<K, V>>> {
+        let root_page = self.root_page.as_ref().unwrap();
+        if root_page.page_type == PageType::LEAF {
+            return Ok(None);
+        }
+        // readers racing for the pinned pages go down without them
+        let mut pinned = self.pinned.try_lock().ok();
+        if let Some(path) = pinned.as_mut() {
+            let generation = self.fd.lock().unwrap().generation();
+            if path.generation != generation {
+                path.pages.clear();
+                path.generation = generation;
+            }
+        }
+        let mut index = root_page.child_for(key);
+        let mut depth = 0;
+        loop {
+            if depth == MAX_DEPTH {
+                return Err(corrupted(index, "the tree loops back on itself".to_owned()));
+            }
+            if let Some(path) = pinned.as_mut() {
+                if let Some(p) = path.pages.get(depth).filter(|p| p.index == index) {
+                    visit(p);
+                    index = p.child_for(key);
+                    depth += 1;
+                    continue;
+                }
+                path.pages.truncate(depth);
+            }
+            let mut p = Page::<K, V>::load_node(self.fd.clone(), index)?;
+            if p.page_type == PageType::LEAF {
+                return Ok(Some(p));
+            }
+            visit(&p);
+            index = p.child_for(key);
+            depth += 1;
+            if let Some(path) = pinned.as_mut() {
+                // a copy that is never written back, what it holds is in the file already
+                p.detach();
+                path.pages.push(p);
+            }
+        }
+    }
+}
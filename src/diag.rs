@@ -0,0 +1,14 @@
+// internal diagnostics go to the `log` facade with the `log` feature and are compiled out
+// without it. events carry their details as key-values, `event!(debug, index = 3; "split leaf")`
+
+#[cfg(feature = "log")]
+macro_rules! event {
+    ($level: ident, $($arg: tt)+) => {
+        log::$level!($($arg)+)
+    };
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! event {
+    ($level: ident, $($arg: tt)+) => {};
+}
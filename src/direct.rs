@@ -0,0 +1,59 @@
+use anyhow::{anyhow, Result};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// what direct reads and writes line up with, the largest block size devices commonly have
+const ALIGN: usize = 4096;
+
+/// a second handle on the tree file reading and writing pages past the os page cache, through
+/// a buffer lined up as direct io wants it
+pub(crate) struct DirectFile {
+    file: File,
+    buf: Vec<u8>,
+    // where the lined up page starts in `buf`
+    start: usize,
+    page_size: usize,
+}
+
+impl DirectFile {
+    #[cfg(target_os = "linux")]
+    pub fn open(path: &Path, writable: bool, page_size: usize) -> Result<Self> {
+        use std::os::unix::fs::OpenOptionsExt;
+        if !page_size.is_multiple_of(ALIGN) {
+            return Err(anyhow!("direct io takes pages of a multiple of {} bytes, not {}", ALIGN, page_size));
+        }
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(writable)
+            .custom_flags(libc::O_DIRECT)
+            .open(path)?;
+        let buf = vec![0u8; page_size + ALIGN];
+        let start = buf.as_ptr().align_offset(ALIGN);
+        Ok(DirectFile { file, buf, start, page_size })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn open(_path: &Path, _writable: bool, _page_size: usize) -> Result<Self> {
+        Err(anyhow!("direct io is only there on linux"))
+    }
+
+    pub fn read(&mut self, offset: u64, out: &mut [u8]) -> Result<()> {
+        let page = &mut self.buf[self.start..self.start + self.page_size];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(page)?;
+        out.copy_from_slice(page);
+        Ok(())
+    }
+
+    /// writes `image` as the page at `offset`, zeros making up the rest of the page when it
+    /// is shorter
+    pub fn write(&mut self, offset: u64, image: &[u8]) -> Result<()> {
+        let page = &mut self.buf[self.start..self.start + self.page_size];
+        page[..image.len()].copy_from_slice(image);
+        page[image.len()..].fill(0);
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(page)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+use crate::byte::Decodable;
+use anyhow::Result;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const BUFFER_MAGIC: &[u8; 8] = b"BTREEDWB";
+// magic and image count
+const HEADER_SIZE: usize = 8 + 4;
+
+/// where pages of the tree file at `path` go first when it is written through a double-write
+/// buffer. it outlives every write, holding the last images written
+pub(crate) fn buffer_path(path: &Path) -> PathBuf {
+    let mut buffer = OsString::from(path.as_os_str());
+    buffer.push("-dwb");
+    buffer.into()
+}
+
+/// page images on their way to the tree file, each written whole to the buffer file and synced
+/// before any of them is written in place. a power loss tearing a page in place then leaves a
+/// whole image of it behind to write again
+pub(crate) struct DoubleWrite {
+    file: File,
+    // the images written since the buffer was last flushed, each after its offset and length
+    staged: Vec<u8>,
+    count: u32,
+}
+
+impl DoubleWrite {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).truncate(false).write(true).open(buffer_path(path))?;
+        Ok(DoubleWrite { file, staged: Vec::new(), count: 0 })
+    }
+
+    /// holds on to `image` for page `offset` of the tree file until the next `flush`
+    pub fn stage(&mut self, offset: u64, image: &[u8]) {
+        self.staged.extend_from_slice(&offset.to_be_bytes());
+        self.staged.extend_from_slice(&(image.len() as u32).to_be_bytes());
+        self.staged.extend_from_slice(image);
+        self.count += 1;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// writes the staged images to the buffer file and waits for them to reach the disk, then
+    /// hands each to `write_at` to write in place, and forgets them
+    pub fn flush(&mut self, mut write_at: impl FnMut(u64, &[u8]) -> Result<()>) -> Result<()> {
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        header.extend_from_slice(BUFFER_MAGIC);
+        header.extend_from_slice(&self.count.to_be_bytes());
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&header);
+        hasher.update(&self.staged);
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)?;
+        self.file.write_all(&self.staged)?;
+        self.file.write_all(&hasher.finalize().to_be_bytes())?;
+        self.file.set_len((HEADER_SIZE + self.staged.len() + 4) as u64)?;
+        self.file.sync_data()?;
+        for (offset, image) in records(&self.staged, self.count).unwrap() {
+            write_at(offset, image)?;
+        }
+        self.staged.clear();
+        self.count = 0;
+        Ok(())
+    }
+}
+
+/// writes the images a double-write buffer left behind back in place and removes it, returning
+/// whether there were any. the images are those written last, whole, so writing them again
+/// only repairs pages torn on the way. a buffer torn itself was written before the file was
+/// touched and is just removed
+pub(crate) fn recover(path: &Path, file: &mut File) -> Result<bool> {
+    let buffer = buffer_path(path);
+    let bytes = match fs::read(&buffer) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into())
+    };
+    let complete = parse(&bytes);
+    if let Some(images) = complete.as_ref() {
+        for (offset, image) in images {
+            file.seek(SeekFrom::Start(*offset))?;
+            file.write_all(image)?;
+        }
+        file.sync_all()?;
+    }
+    fs::remove_file(&buffer)?;
+    Ok(complete.is_some())
+}
+
+// the images of a complete buffer file with their offsets
+fn parse(bytes: &[u8]) -> Option<Vec<(u64, &[u8])>> {
+    if bytes.len() < HEADER_SIZE + 4 || &bytes[..8] != BUFFER_MAGIC {
+        return None;
+    }
+    let (body, crc) = bytes.split_at(bytes.len() - 4);
+    if crc32fast::hash(body) != u32::decode(crc).ok()?.0 {
+        return None;
+    }
+    let count = u32::decode(&body[8..]).ok()?.0;
+    records(&body[HEADER_SIZE..], count)
+}
+
+// the `count` images of `bytes`, each after its offset and length, None unless they fill it
+fn records(mut bytes: &[u8], count: u32) -> Option<Vec<(u64, &[u8])>> {
+    let mut images = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let offset = u64::decode(bytes).ok()?.0;
+        let len = u32::decode(bytes.get(8..)?).ok()?.0 as usize;
+        let image = bytes.get(12..12 + len)?;
+        images.push((offset, image));
+        bytes = &bytes[12 + len..];
+    }
+    bytes.is_empty().then_some(images)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{buffer_path, parse};
+    use crate::{BTree, PAGE_SIZE};
+    use std::fs::{self, OpenOptions};
+    use std::io::{Seek, SeekFrom, Write};
+
+    #[test]
+    fn a_torn_page_is_written_again_from_the_buffer() {
+        let path = std::env::temp_dir().join(format!("btree-dwb-test-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(buffer_path(&path));
+        {
+            let mut tree = BTree::<u32, u64>::builder(&path).create_if_missing(true).double_write(true).open().unwrap();
+            for i in 0..3000u32 {
+                tree.set(&i, &(i as u64)).unwrap();
+            }
+        }
+        let before: Vec<(u32, u64)> = BTree::open_read_only(&path).unwrap().iter().collect();
+        // the buffer holds the last images written, a page past the meta pages among them
+        let buffer = fs::read(buffer_path(&path)).unwrap();
+        let offset = parse(&buffer).unwrap().iter()
+            .map(|(offset, _)| *offset)
+            .find(|offset| *offset >= 2 * PAGE_SIZE as u64)
+            .unwrap();
+        // torn halfway through its write in place
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(offset + PAGE_SIZE as u64 / 2)).unwrap();
+        file.write_all(&vec![0xa5; PAGE_SIZE / 2]).unwrap();
+        drop(file);
+        // opening read only repairs nothing
+        let torn = BTree::<u32, u64>::open_read_only(&path);
+        assert!(torn.map_or(true, |tree| tree.verify().map_or(true, |problems| !problems.is_empty())));
+
+        let tree = BTree::<u32, u64>::open(&path).unwrap();
+        assert!(!buffer_path(&path).exists());
+        assert_eq!(tree.iter().collect::<Vec<(u32, u64)>>(), before);
+        assert!(tree.verify().unwrap().is_empty());
+        drop(tree);
+        fs::remove_file(&path).unwrap();
+    }
+}
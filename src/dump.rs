@@ -0,0 +1,162 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::page::{Page, PageType, corrupted};
+use crate::{BTree, MAX_DEPTH};
+use anyhow::Result;
+use std::fmt::Debug;
+use std::io::Write;
+
+// a leaf with more keys shows only this many from either end
+const SHOWN_KEYS: usize = 4;
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    /// writes the pages of the tree to `out` one per line, indented by how deep they sit.
+    /// an internal page lists its pointers in angle brackets between its keys, a leaf its keys,
+    /// cut down to the first and last few when there are many. named trees follow the file's own
+    pub fn dump_text<W: Write>(&self, out: &mut W) -> Result<(), BTreeError> {
+        for (name, root) in self.roots()? {
+            if let Some(name) = name {
+                writeln!(out, "tree {:?}", name)?;
+            }
+            self.walk_tree(root, |p, depth| {
+                write!(out, "{:indent$}", "", indent = depth * 2)?;
+                match p.page_type {
+                    PageType::INTERNAL => {
+                        write!(out, "internal {}: <{}>", p.index, p.ptr_at(0).unwrap())?;
+                        for i in 0..p.item_count() {
+                            write!(out, " {:?} <{}>", p.key_at(i).unwrap(), p.ptr_at(i + 1).unwrap())?;
+                        }
+                    }
+                    _ => {
+                        write!(out, "leaf {}:", p.index)?;
+                        for key in shown_keys(p) {
+                            match key {
+                                Some(key) => write!(out, " {:?}", key)?,
+                                None => write!(out, " ...")?
+                            }
+                        }
+                        write!(out, " ({} entries)", p.item_count())?;
+                    }
+                }
+                writeln!(out)?;
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+
+    /// writes the tree to `out` as a graphviz digraph, a record per page with an edge from
+    /// every pointer of an internal page to the page it points at. named trees go in clusters
+    /// of their own. `dot -Tsvg` renders it
+    pub fn dump_dot<W: Write>(&self, out: &mut W) -> Result<(), BTreeError> {
+        writeln!(out, "digraph btree {{")?;
+        writeln!(out, "  node [shape=record];")?;
+        for (name, root) in self.roots()? {
+            if let Some(name) = name.as_ref() {
+                writeln!(out, "  subgraph \"cluster_{}\" {{", escape(name, QUOTED))?;
+                writeln!(out, "    label=\"{}\";", escape(name, QUOTED))?;
+            }
+            self.walk_tree(root, |p, _| {
+                let mut fields = Vec::new();
+                match p.page_type {
+                    PageType::INTERNAL => {
+                        for i in 0..=p.item_count() {
+                            fields.push(format!("<p{}>", i));
+                            if i < p.item_count() {
+                                fields.push(escape(&format!("{:?}", p.key_at(i).unwrap()), RECORD));
+                            }
+                        }
+                    }
+                    _ => for key in shown_keys(p) {
+                        fields.push(match key {
+                            Some(key) => escape(&format!("{:?}", key), RECORD),
+                            None => "...".to_owned()
+                        });
+                    }
+                }
+                writeln!(out, "  page{} [label=\"{{{}|{{{}}}}}\"];", p.index, p.index, fields.join("|"))?;
+                if p.page_type == PageType::INTERNAL {
+                    for i in 0..=p.item_count() {
+                        writeln!(out, "  page{}:p{} -> page{};", p.index, i, p.ptr_at(i).unwrap())?;
+                    }
+                }
+                Ok(())
+            })?;
+            if name.is_some() {
+                writeln!(out, "  }}")?;
+            }
+        }
+        writeln!(out, "}}")?;
+        Ok(())
+    }
+
+    // the file's own tree and then the named trees in the catalog, by name and root page
+    fn roots(&self) -> Result<Vec<(Option<String>, u32)>> {
+        let meta_page = self.meta_page.as_ref().unwrap();
+        let mut roots = vec![(None, meta_page.root_index())];
+        let catalog = meta_page.catalog_index();
+        if catalog != 0 {
+            let p = Page::<K, V>::load(self.fd.clone(), catalog)?;
+            if p.page_type != PageType::CATALOG {
+                return Err(corrupted(catalog, format!("a {:?} page in place of the catalog", p.page_type)));
+            }
+            roots.extend((0..p.catalog_len()).map(|i| (Some(p.catalog_name(i).to_owned()), p.catalog_root(i))));
+        }
+        Ok(roots)
+    }
+
+    // calls `f` with every page of the tree under `root` and how deep it sits, parents first
+    fn walk_tree<F: FnMut(&Page<K, V>, usize) -> Result<()>>(&self, root: u32, mut f: F) -> Result<()> {
+        let mut todo = vec![(root, 0)];
+        while let Some((index, depth)) = todo.pop() {
+            // the root may hold changes not synced yet
+            let p = match self.root_page.as_ref().filter(|root| root.index == index) {
+                Some(root) => root.snapshot(),
+                None => Page::<K, V>::load_node(self.fd.clone(), index)?
+            };
+            f(&p, depth)?;
+            if p.page_type == PageType::INTERNAL {
+                if depth == MAX_DEPTH {
+                    return Err(corrupted(index, "the tree loops back on itself".to_owned()));
+                }
+                todo.extend((0..=p.item_count()).rev().map(|i| (p.ptr_at(i).unwrap(), depth + 1)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// the keys of a leaf to show, None standing for the ones left out between the first and last few
+fn shown_keys<K, V>(p: &Page<K, V>) -> Vec<Option<K>>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    let n = p.item_count();
+    if n <= SHOWN_KEYS * 2 {
+        return (0..n).map(|i| p.key_at(i)).collect();
+    }
+    let mut keys: Vec<Option<K>> = (0..SHOWN_KEYS).map(|i| p.key_at(i)).collect();
+    keys.push(None);
+    keys.extend((n - SHOWN_KEYS..n).map(|i| p.key_at(i)));
+    keys
+}
+
+// characters that end a quoted string, and those that also mean something in a record label
+const QUOTED: &str = "\"\\";
+const RECORD: &str = "\"\\{}|<>";
+
+fn escape(s: &str, special: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if special.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
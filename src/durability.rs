@@ -0,0 +1,82 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::BTree;
+use crate::error::BTreeError;
+use anyhow::Result;
+use std::fmt::Debug;
+
+/// how far a tree goes to get its writes onto the disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    /// every write reaches the file, synced, before it returns
+    SyncEveryWrite,
+    /// writes reach the file as the page cache evicts them and on `flush`, which syncs it
+    #[default]
+    SyncOnFlush,
+    /// like `SyncOnFlush` without ever syncing, a crash of the machine may lose writes the
+    /// file was handed
+    NoSync,
+}
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    /// trees start out with `Durability::SyncOnFlush`. copy-on-write and transactions sync
+    /// what they write whatever this says
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.durability = durability
+    }
+
+    pub fn durability(&self) -> Durability {
+        self.durability
+    }
+
+    /// flushes the tree and closes the file, returning the error dropping the tree would only log
+    pub fn close(mut self) -> Result<(), BTreeError> {
+        let flushed = self.flush();
+        match flushed {
+            // on the disk already, dropping has nothing left to sync
+            Ok(_) => self.durability = Durability::NoSync,
+            Err(_) => self.give_up()
+        }
+        flushed
+    }
+}
+
+// needs no bounds on the key and value types, so dropping a tree can flush it
+impl<K, V> BTree<K, V> {
+    /// writes every change made so far out to the file and, unless `Durability::NoSync`,
+    /// waits for it to reach the disk
+    pub fn flush(&mut self) -> Result<(), BTreeError> {
+        if self.read_only {
+            return Ok(());
+        }
+        self.sync()?;
+        match self.durability {
+            Durability::NoSync => Ok(()),
+            _ => Ok(self.fd.lock().unwrap().sync_file()?)
+        }
+    }
+
+    // drops the changes that failed to reach the file, which the pages and the pager would
+    // otherwise try to write again on their way out, and panic failing
+    fn give_up(&mut self) {
+        let held = self.root_page.iter_mut().chain(self.catalog.iter_mut().map(|(p, _)| p)).chain(self.meta_page.iter_mut());
+        for p in held {
+            p.detach();
+        }
+        self.fd.lock().unwrap().discard_deferred();
+    }
+}
+
+impl<K, V> Drop for BTree<K, V> {
+    /// flushes the tree, as `close` does. a failure here has nowhere to go but the log
+    #[cfg_attr(not(feature = "log"), allow(unused_variables))]
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            event!(error, path:% = self.path.display(), error:% = e; "failed to flush a tree as it was dropped");
+            self.give_up();
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::page::{Page, Pos, Slot, Stat};
+use crate::BTree;
+use anyhow::Result;
+use std::fmt::Debug;
+
+// what the descent to a key's leaf turned up
+enum Lookup<V> {
+    Found(V),
+    Inserted(V),
+    // the leaf has no room, or values spill, or the pages above it count the entries under
+    // them, so inserting takes the long way
+    Missing(V),
+}
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    /// the value of `key`, inserting the one `default` makes first when the key is missing.
+    /// the lookup and the insert share one descent unless the leaf has to split
+    pub fn get_or_insert_with(&mut self, key: &K, default: impl FnOnce() -> V) -> Result<V, BTreeError> {
+        if self.read_only {
+            return Err(BTreeError::ReadOnly { path: self.path.clone() });
+        }
+        let spills = Page::<K, V>::spills(self.page_size());
+        let counted = self.meta_page.as_ref().unwrap().counted();
+        let lookup = self.with_leaf(key, |p| {
+            if let Some((i, Pos::Current)) = p.find(key) {
+                let value = V::decode(p.value_bytes(i, &mut Vec::new())?)?.0;
+                return Ok(Lookup::Found(value));
+            }
+            let value = default();
+            if spills || counted || p.is_full() {
+                return Ok(Lookup::Missing(value));
+            }
+            // encoding errors are caught before the leaf changes, as with `set`
+            let mut buf = vec![0u8; K::bin_size().max(V::bin_size())];
+            key.encode(&mut buf)?;
+            value.encode(&mut buf)?;
+            p.insert(key, &Slot::Value(&value))?;
+            Ok::<_, anyhow::Error>(Lookup::Inserted(value))
+        })??;
+        let value = match lookup {
+            Lookup::Found(value) => return Ok(value),
+            Lookup::Inserted(value) => {
+                self.bump_stat(Stat::Inserts);
+                self.sync()?;
+                value
+            }
+            Lookup::Missing(value) => {
+                self.put(key, &value)?;
+                value
+            }
+        };
+        self.touch(Stat::LastModified);
+        self.finish_write()?;
+        Ok(value)
+    }
+
+    /// runs `f` on the value of `key` and stores what it leaves, in the leaf it was read from;
+    /// returns false when the key is missing. values that spill are read and put back the
+    /// long way
+    pub fn update(&mut self, key: &K, f: impl FnOnce(&mut V)) -> Result<bool, BTreeError> {
+        if self.read_only {
+            return Err(BTreeError::ReadOnly { path: self.path.clone() });
+        }
+        if Page::<K, V>::spills(self.page_size()) {
+            let mut value = match self.try_get(key)? {
+                Some(value) => value,
+                None => return Ok(false)
+            };
+            f(&mut value);
+            self.put(key, &value)?;
+        } else {
+            let updated = self.with_leaf(key, |p| {
+                let i = match p.find(key) {
+                    Some((i, Pos::Current)) => i,
+                    _ => return Ok(false)
+                };
+                let mut value = V::decode(p.raw_value_at(i))?.0;
+                f(&mut value);
+                let mut buf = vec![0u8; V::bin_size()];
+                value.encode(&mut buf)?;
+                p.set_raw_value_at(i, &buf)?;
+                Ok::<_, anyhow::Error>(true)
+            })??;
+            if !updated {
+                return Ok(false);
+            }
+            self.bump_stat(Stat::Overwrites);
+            self.sync()?;
+        }
+        self.touch(Stat::LastModified);
+        self.finish_write()?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,82 @@
+use crate::page::PageError;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// what a tree's public methods fail with, to tell failures apart by matching. errors coming
+/// out of the key and value types' own encoding end up as `Other`
+#[derive(Error, Debug)]
+pub enum BTreeError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("page {page} is corrupted: {reason}")]
+    Corrupted { page: u32, reason: String },
+    #[error("keys of {size} bytes are over the {max} byte limit")]
+    KeyTooLarge { size: usize, max: usize },
+    #[error("values of {size} bytes are over the {max} byte limit")]
+    ValueTooLarge { size: usize, max: usize },
+    /// the file is no tree, or one this build or these key and value types cannot read
+    #[error("{0}")]
+    InvalidFormat(String),
+    #[error("{} is opened read only", path.display())]
+    ReadOnly { path: PathBuf },
+    #[error("{} is already open for writing in this process", path.display())]
+    AlreadyOpen { path: PathBuf },
+    #[error("quota of {max_pages} pages exceeded")]
+    QuotaExceeded { max_pages: u32 },
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl From<PageError> for BTreeError {
+    fn from(e: PageError) -> Self {
+        match e {
+            PageError::Corrupted { index, reason } => BTreeError::Corrupted { page: index, reason },
+            PageError::OrderMismatch { index } => BTreeError::Corrupted { page: index, reason: e.to_string() },
+            PageError::LayoutMismatch { .. } | PageError::KeyOrderMismatch { .. } | PageError::NotATree | PageError::UnsupportedVersion { .. } => {
+                BTreeError::InvalidFormat(e.to_string())
+            }
+            PageError::AlreadyOpen { path } => BTreeError::AlreadyOpen { path },
+            PageError::QuotaExceeded { max_pages } => BTreeError::QuotaExceeded { max_pages },
+            PageError::Full => BTreeError::Other(e.into())
+        }
+    }
+}
+
+impl From<anyhow::Error> for BTreeError {
+    /// finds the kind of failure behind the errors passed up inside the crate
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<BTreeError>() {
+            Ok(e) => return e,
+            Err(e) => e
+        };
+        let e = match e.downcast::<PageError>() {
+            Ok(e) => return e.into(),
+            Err(e) => e
+        };
+        match e.downcast::<io::Error>() {
+            Ok(e) => BTreeError::Io(e),
+            Err(e) => BTreeError::Other(e)
+        }
+    }
+}
+
+// errors of the optional dependencies, kept as they are
+macro_rules! other_from {
+    ($($feature: literal => $ty: ty),*) => {
+        $(#[cfg(feature = $feature)]
+        impl From<$ty> for BTreeError {
+            fn from(e: $ty) -> Self {
+                BTreeError::Other(e.into())
+            }
+        })*
+    }
+}
+
+other_from!(
+    "arrow" => arrow_schema::ArrowError,
+    "parquet" => parquet::errors::ParquetError,
+    "sqlite" => rusqlite::Error,
+    "grpc" => tonic::transport::Error,
+    "tokio" => tokio::task::JoinError
+);
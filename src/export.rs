@@ -0,0 +1,411 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::BTree;
+use crate::error::BTreeError;
+use anyhow::{anyhow, Result};
+use std::fmt::Debug;
+use std::io::{BufRead, Write};
+
+/// how `BTree::export` writes entries and `BTree::import` reads them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// a `key,value` header line, then a line per entry. fields holding a comma, a quote, a
+    /// line break or leading or trailing spaces are quoted, with quotes in them doubled
+    Csv,
+    /// a `{"key":..,"value":..}` object per line, numbers as json numbers and strings as json strings
+    JsonLines,
+}
+
+/// a key or value type written out as text and read back from it
+pub trait TextField: Sized {
+    fn to_text(&self) -> String;
+
+    fn from_text(s: &str) -> Result<Self>;
+
+    /// whether json holds it as a string rather than a number
+    fn is_str() -> bool {
+        false
+    }
+}
+
+macro_rules! text_field_impl {
+    ($($ty: ty),*) => {
+        $(impl TextField for $ty {
+            fn to_text(&self) -> String {
+                self.to_string()
+            }
+
+            fn from_text(s: &str) -> Result<Self> {
+                s.parse().map_err(|_| anyhow!("'{}' is not a valid {}", s, stringify!($ty)))
+            }
+        })*
+    }
+}
+
+text_field_impl!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone + TextField,
+        V: Encodable + Decodable + BinSizer + Debug + Clone + TextField
+{
+    /// writes every entry to `out` in key order, returning how many were written
+    pub fn export<W: Write>(&self, mut out: W, format: Format) -> Result<usize, BTreeError> {
+        if format == Format::Csv {
+            writeln!(out, "key,value")?;
+        }
+        let mut written = 0;
+        for (k, v) in self.iter() {
+            match format {
+                Format::Csv => writeln!(out, "{},{}", csv_field(&k.to_text()), csv_field(&v.to_text()))?,
+                Format::JsonLines => writeln!(out, "{{\"key\":{},\"value\":{}}}", json_field(&k), json_field(&v))?
+            }
+            written += 1;
+        }
+        out.flush()?;
+        Ok(written)
+    }
+
+    /// sets every entry read from `input`, as `export` writes them, returning how many were
+    /// read. a malformed line stops the import with its line number, the entries before it set
+    pub fn import<R: BufRead>(&mut self, mut input: R, format: Format) -> Result<usize, BTreeError> {
+        let mut read = 0;
+        let mut line_no = 0;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if input.read_line(&mut line)? == 0 {
+                break;
+            }
+            line_no += 1;
+            let first = line_no;
+            let entry = match format {
+                Format::Csv => {
+                    // a quoted field may run over several lines
+                    while open_quote(&line) {
+                        if input.read_line(&mut line)? == 0 {
+                            return Err(anyhow!("line {}: a quoted field is never closed", first).into());
+                        }
+                        line_no += 1;
+                    }
+                    let fields = trim_line_end(&line);
+                    if fields.is_empty() || (first == 1 && fields == "key,value") {
+                        continue;
+                    }
+                    parse_csv(fields)
+                }
+                Format::JsonLines => {
+                    let fields = trim_line_end(&line);
+                    if fields.trim().is_empty() {
+                        continue;
+                    }
+                    parse_json(fields)
+                }
+            };
+            let (k, v) = entry
+                .and_then(|(k, v)| Ok((K::from_text(&k)?, V::from_text(&v)?)))
+                .map_err(|e| anyhow!("line {}: {}", first, e))?;
+            self.set(&k, &v)?;
+            read += 1;
+        }
+        self.sync()?;
+        Ok(read)
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    let quoted = s.contains([',', '"', '\n', '\r']) || s.trim() != s;
+    if quoted { format!("\"{}\"", s.replace('"', "\"\"")) } else { s.to_owned() }
+}
+
+fn json_field<T: TextField>(val: &T) -> String {
+    let text = val.to_text();
+    // json has no spelling for nan or the infinities, they are kept as strings
+    let number = !T::is_str() && text.parse::<f64>().is_ok_and(|n| n.is_finite());
+    if number { text } else { json_str(&text) }
+}
+
+fn json_str(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn trim_line_end(line: &str) -> &str {
+    line.strip_suffix('\n').map_or(line, |l| l.strip_suffix('\r').unwrap_or(l))
+}
+
+// whether the line so far ends inside a quoted field
+fn open_quote(line: &str) -> bool {
+    line.chars().filter(|c| *c == '"').count() % 2 == 1
+}
+
+fn parse_csv(line: &str) -> Result<(String, String)> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => field.push(c),
+                    None => return Err(anyhow!("a quoted field is never closed"))
+                }
+            }
+            if !matches!(chars.peek(), None | Some(',')) {
+                return Err(anyhow!("text after a quoted field"));
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ',') {
+                field.push(c);
+            }
+        }
+        fields.push(field);
+        if chars.next().is_none() {
+            break;
+        }
+    }
+    if fields.len() != 2 {
+        return Err(anyhow!("{} fields, expected a key and a value", fields.len()));
+    }
+    let value = fields.pop().unwrap();
+    Ok((fields.pop().unwrap(), value))
+}
+
+/// the key and value of a `{"key":..,"value":..}` line, the fields in any order
+fn parse_json(line: &str) -> Result<(String, String)> {
+    let mut json = Json { s: line, at: 0 };
+    let (mut key, mut value) = (None, None);
+    json.expect('{')?;
+    loop {
+        let name = json.string()?;
+        json.expect(':')?;
+        let field = json.value()?;
+        match name.as_str() {
+            "key" => key = Some(field),
+            "value" => value = Some(field),
+            _ => {}
+        }
+        if !json.next_is(',') {
+            break;
+        }
+    }
+    json.expect('}')?;
+    if !json.rest().trim().is_empty() {
+        return Err(anyhow!("text after the object"));
+    }
+    match (key, value) {
+        (Some(key), Some(value)) => Ok((key, value)),
+        _ => Err(anyhow!("an object without a key and a value"))
+    }
+}
+
+struct Json<'a> {
+    s: &'a str,
+    at: usize,
+}
+
+impl<'a> Json<'a> {
+    fn rest(&self) -> &'a str {
+        &self.s[self.at..]
+    }
+
+    fn skip_space(&mut self) {
+        self.at = self.s.len() - self.rest().trim_start().len();
+    }
+
+    fn next_is(&mut self, c: char) -> bool {
+        self.skip_space();
+        if self.rest().starts_with(c) {
+            self.at += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<()> {
+        if self.next_is(c) { Ok(()) } else { Err(anyhow!("expected '{}' at column {}", c, self.at + 1)) }
+    }
+
+    // a string as is, or a number, true and false as their text
+    fn value(&mut self) -> Result<String> {
+        self.skip_space();
+        if self.rest().starts_with('"') {
+            return self.string();
+        }
+        let len = self.rest().find(|c: char| c == ',' || c == '}' || c.is_whitespace()).unwrap_or(self.rest().len());
+        if len == 0 {
+            return Err(anyhow!("expected a value at column {}", self.at + 1));
+        }
+        let token = &self.rest()[..len];
+        self.at += len;
+        Ok(token.to_owned())
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        let mut chars = self.rest().char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.at += i + 1;
+                    return Ok(out);
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('"') => out.push('"'),
+                    Some('\\') => out.push('\\'),
+                    Some('/') => out.push('/'),
+                    Some('b') => out.push('\u{8}'),
+                    Some('f') => out.push('\u{c}'),
+                    Some('n') => out.push('\n'),
+                    Some('r') => out.push('\r'),
+                    Some('t') => out.push('\t'),
+                    Some('u') => {
+                        let high = utf16_unit(&mut chars)?;
+                        // characters past the first plane come as a surrogate pair
+                        let units = if (0xd800..0xdc00).contains(&high) {
+                            if chars.next().map(|(_, c)| c) != Some('\\') || chars.next().map(|(_, c)| c) != Some('u') {
+                                return Err(anyhow!("a lone surrogate in a string"));
+                            }
+                            vec![high, utf16_unit(&mut chars)?]
+                        } else {
+                            vec![high]
+                        };
+                        out.push_str(&String::from_utf16(&units).map_err(|_| anyhow!("a lone surrogate in a string"))?);
+                    }
+                    _ => return Err(anyhow!("a bad escape in a string"))
+                },
+                c => out.push(c)
+            }
+        }
+        Err(anyhow!("a string is never closed"))
+    }
+}
+
+// the four hex digits of a \u escape
+fn utf16_unit(chars: &mut std::str::CharIndices) -> Result<u16> {
+    let hex: String = chars.take(4).map(|(_, c)| c).collect();
+    u16::from_str_radix(&hex, 16).map_err(|_| anyhow!("'\\u{}' is not a valid escape", hex))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Format;
+    use crate::byte::{BinSizer, Encodable, Decodable, check_len};
+    use crate::BTree;
+    use std::fs;
+    use std::path::PathBuf;
+
+    crate::define_fixed_len_str!(Text, 32);
+
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("btree-export-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    const AWKWARD: [&str; 7] = ["plain", "a,b", "say \"hi\"", "two\nlines", " padded ", "tab\there\u{1}", "é😀"];
+
+    #[test]
+    fn awkward_strings_come_back_in_either_format() {
+        for (name, format) in [("csv", Format::Csv), ("json", Format::JsonLines)] {
+            let (from, to) = (temp_path(&format!("{}-from", name)), temp_path(&format!("{}-to", name)));
+            let mut tree = BTree::<Text, Text>::open_or_create(&from).unwrap();
+            for (i, s) in AWKWARD.iter().enumerate() {
+                tree.set(&Text::new(s), &Text::new(AWKWARD[(i + 1) % AWKWARD.len()])).unwrap();
+            }
+            let mut out = Vec::new();
+            assert_eq!(tree.export(&mut out, format).unwrap(), AWKWARD.len());
+            if format == Format::Csv {
+                let text = String::from_utf8(out.clone()).unwrap();
+                assert!(text.contains("\"a,b\"") && text.contains("\"say \"\"hi\"\"\"") && text.contains("\"two\nlines\""));
+            }
+            let mut back = BTree::<Text, Text>::open_or_create(&to).unwrap();
+            assert_eq!(back.import(&out[..], format).unwrap(), AWKWARD.len());
+            assert_eq!(back.iter().collect::<Vec<_>>(), tree.iter().collect::<Vec<_>>());
+            assert_eq!(back.first().unwrap().0.as_str(), " padded ");
+            drop((tree, back));
+            fs::remove_file(&from).unwrap();
+            fs::remove_file(&to).unwrap();
+        }
+    }
+
+    #[test]
+    fn nan_and_the_infinities_go_out_as_strings_and_come_back() {
+        let floats = [1.5, f64::NAN, f64::INFINITY, f64::NEG_INFINITY];
+        for (name, format) in [("csv", Format::Csv), ("json", Format::JsonLines)] {
+            let (from, to) = (temp_path(&format!("nan-{}-from", name)), temp_path(&format!("nan-{}-to", name)));
+            let mut tree = BTree::<u32, f64>::open_or_create(&from).unwrap();
+            for (i, f) in floats.iter().enumerate() {
+                tree.set(&(i as u32), f).unwrap();
+            }
+            let mut out = Vec::new();
+            tree.export(&mut out, format).unwrap();
+            if format == Format::JsonLines {
+                let text = String::from_utf8(out.clone()).unwrap();
+                assert!(text.contains("\"value\":1.5}"));
+                assert!(text.contains("\"value\":\"NaN\"") && text.contains("\"value\":\"-inf\""));
+            }
+            let mut back = BTree::<u32, f64>::open_or_create(&to).unwrap();
+            back.import(&out[..], format).unwrap();
+            for (i, f) in floats.iter().enumerate() {
+                assert_eq!(back.get(&(i as u32)).unwrap().to_bits(), f.to_bits());
+            }
+            drop((tree, back));
+            fs::remove_file(&from).unwrap();
+            fs::remove_file(&to).unwrap();
+        }
+    }
+
+    #[test]
+    fn escapes_and_surrogate_pairs_decode() {
+        let path = temp_path("escapes");
+        let mut tree = BTree::<Text, u32>::open_or_create(&path).unwrap();
+        let input = "{\"key\":\"\\ud83d\\ude00 \\u00e9\\/\\\"\",\"value\":1}\n{ \"value\" : 2 , \"key\" : \"b\" }\n";
+        assert_eq!(tree.import(input.as_bytes(), Format::JsonLines).unwrap(), 2);
+        assert_eq!(tree.get(&Text::new("😀 é/\"")), Some(1));
+        assert_eq!(tree.get(&Text::new("b")), Some(2));
+        assert!(tree.import("{\"key\":\"\\ud83d\",\"value\":3}\n".as_bytes(), Format::JsonLines).is_err());
+        drop(tree);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_malformed_line_stops_the_import_with_its_number() {
+        let path = temp_path("malformed");
+        let mut tree = BTree::<Text, u32>::open_or_create(&path).unwrap();
+        // the quoted key takes lines 2 and 3, the line missing its value is the 4th
+        let csv = "key,value\n\"a\nb\",1\nc\nd,4\n";
+        let e = tree.import(csv.as_bytes(), Format::Csv).unwrap_err().to_string();
+        assert!(e.contains("line 4"), "{}", e);
+        assert_eq!(tree.get(&Text::new("a\nb")), Some(1));
+        assert_eq!(tree.get(&Text::new("d")), None);
+        let json = "{\"key\":\"e\",\"value\":5}\n{\"key\":\"f\",\"value\":x}\n";
+        let e = tree.import(json.as_bytes(), Format::JsonLines).unwrap_err().to_string();
+        assert!(e.contains("line 2"), "{}", e);
+        let csv = "key,value\n\"never closed,1\n";
+        let e = tree.import(csv.as_bytes(), Format::Csv).unwrap_err().to_string();
+        assert!(e.contains("line 2"), "{}", e);
+        drop(tree);
+        fs::remove_file(&path).unwrap();
+    }
+}
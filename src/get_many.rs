@@ -0,0 +1,58 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::page::{Page, PageType, Pos, corrupted};
+use crate::{BTree, MAX_DEPTH};
+use anyhow::Result;
+use std::cmp::Ordering;
+use std::fmt::Debug;
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    /// the values of `keys`, in the order asked for. None as well for keys under a corrupted
+    /// page, which `try_get_many` tells apart
+    pub fn get_many(&self, keys: &[K]) -> Vec<Option<V>> {
+        match self.try_get_many(keys) {
+            Ok(values) => values,
+            Err(_) => keys.iter().map(|key| self.get(key)).collect()
+        }
+    }
+
+    /// looks `keys` up in key order, each page on the way to them loaded once however many
+    /// of them sit below it
+    pub fn try_get_many(&self, keys: &[K]) -> Result<Vec<Option<V>>, BTreeError> {
+        let mut sorted: Vec<(usize, &K)> = keys.iter().enumerate().collect();
+        sorted.sort_by(|a, b| a.1.partial_cmp(b.1).unwrap_or(Ordering::Equal));
+        let mut values = Vec::with_capacity(keys.len());
+        values.resize_with(keys.len(), || None);
+        self.get_below(self.root_page.as_ref().unwrap(), &sorted, &mut values, 0)?;
+        Ok(values)
+    }
+
+    fn get_below(&self, p: &Page<K, V>, keys: &[(usize, &K)], values: &mut [Option<V>], depth: usize) -> Result<()> {
+        if p.page_type != PageType::INTERNAL {
+            for (at, key) in keys {
+                values[*at] = match p.find(key) {
+                    Some((i, Pos::Current)) => Some(V::decode(p.value_bytes(i, &mut Vec::new())?)?.0),
+                    _ => None
+                };
+            }
+            return Ok(());
+        }
+        let mut rest = keys;
+        while let Some((_, key)) = rest.first() {
+            let child = p.child_for(key);
+            // sorted keys going to the same child sit next to each other
+            let n = rest.iter().take_while(|(_, k)| p.child_for(k) == child).count();
+            if depth == MAX_DEPTH {
+                return Err(corrupted(child, "the tree loops back on itself".to_owned()));
+            }
+            let page = Page::<K, V>::load_node(self.fd.clone(), child)?;
+            self.get_below(&page, &rest[..n], values, depth + 1)?;
+            rest = &rest[n..];
+        }
+        Ok(())
+    }
+}
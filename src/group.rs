@@ -0,0 +1,100 @@
+use crate::batch::WriteBatch;
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::BTree;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// a tree many threads commit batches to, the batches that come in together going to the
+/// tree as a single commit, so they share what it takes to make them durable. each batch is
+/// atomic as with `BTree::apply`, and commits only once it is durable
+pub struct GroupCommit<K, V> {
+    tree: Mutex<BTree<K, V>>,
+    // how long the first batch of a group waits for others to join it
+    window: Duration,
+    queue: Mutex<Queue<K, V>>,
+    applied: Condvar,
+}
+
+struct Queue<K, V> {
+    // the batches waiting for the next group, with the ticket each was handed
+    waiting: Vec<(u64, WriteBatch<K, V>)>,
+    next_ticket: u64,
+    // whether a committer is gathering or applying a group
+    leading: bool,
+    // how the batches of the groups applied went, until their committers pick it up
+    outcomes: HashMap<u64, Result<(), BTreeError>>,
+}
+
+impl<K, V> GroupCommit<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    /// `window` is how long the first batch of a group waits for others to join it. even
+    /// without one, the batches coming in while a group is applied go together in the next
+    pub fn new(tree: BTree<K, V>, window: Duration) -> Self {
+        GroupCommit {
+            tree: Mutex::new(tree),
+            window,
+            queue: Mutex::new(Queue { waiting: Vec::new(), next_ticket: 0, leading: false, outcomes: HashMap::new() }),
+            applied: Condvar::new(),
+        }
+    }
+
+    /// applies `batch` along with the batches other threads commit meanwhile, returning once
+    /// it is durable. a batch that fails leaves the others of its group to commit without it
+    pub fn commit(&self, batch: WriteBatch<K, V>) -> Result<(), BTreeError> {
+        let mut queue = self.queue.lock().unwrap();
+        let ticket = queue.next_ticket;
+        queue.next_ticket += 1;
+        queue.waiting.push((ticket, batch));
+        loop {
+            if let Some(outcome) = queue.outcomes.remove(&ticket) {
+                return outcome;
+            }
+            if queue.leading {
+                queue = self.applied.wait(queue).unwrap();
+                continue;
+            }
+            // no one gathers a group, this committer does
+            queue.leading = true;
+            drop(queue);
+            thread::sleep(self.window);
+            let group = std::mem::take(&mut self.queue.lock().unwrap().waiting);
+            let outcomes = self.apply_group(group);
+            queue = self.queue.lock().unwrap();
+            queue.outcomes.extend(outcomes);
+            queue.leading = false;
+            self.applied.notify_all();
+        }
+    }
+
+    // one commit for the whole group, or one per batch when that fails so only the batches
+    // at fault fail
+    fn apply_group(&self, group: Vec<(u64, WriteBatch<K, V>)>) -> Vec<(u64, Result<(), BTreeError>)> {
+        let mut tree = self.tree.lock().unwrap();
+        if group.len() > 1 {
+            let mut all = WriteBatch::new();
+            for (_, batch) in group.iter() {
+                all.extend(batch);
+            }
+            if tree.apply(all).is_ok() {
+                return group.into_iter().map(|(ticket, _)| (ticket, Ok(()))).collect();
+            }
+        }
+        group.into_iter().map(|(ticket, batch)| (ticket, tree.apply(batch))).collect()
+    }
+
+    /// the value under `key` as the batches committed so far left it
+    pub fn get(&self, key: &K) -> Result<Option<V>, BTreeError> {
+        self.tree.lock().unwrap().try_get(key)
+    }
+
+    pub fn into_inner(self) -> BTree<K, V> {
+        self.tree.into_inner().unwrap()
+    }
+}
@@ -0,0 +1,268 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::BTree;
+use crate::error::BTreeError;
+use anyhow::{anyhow, Result};
+use std::fmt::Debug;
+use std::net::SocketAddr;
+use std::ops::Bound;
+use std::path::PathBuf;
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, RecvTimeoutError, TryRecvError};
+use std::thread;
+use std::time::Duration;
+use tokio::sync::{mpsc as stream, oneshot};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+    tonic::include_proto!("btree");
+}
+
+use proto::tree_server::{Tree, TreeServer};
+use proto::{DeleteReply, DeleteRequest, Entry, GetReply, GetRequest, RangeRequest, SetReply, SetRequest};
+
+// entries buffered ahead of a slow range client, the stream waits with the rest until it
+// reads some
+const RANGE_BUFFER: usize = 64;
+// entries a range stream gets sent at most before the tree thread takes other requests
+const RANGE_CHUNK: usize = 64;
+// how long the tree thread waits for requests while every range stream it serves is full
+const RANGE_POLL: Duration = Duration::from_millis(5);
+
+enum Op {
+    Get(Vec<u8>, oneshot::Sender<Result<Option<Vec<u8>>, Status>>),
+    Set(Vec<u8>, Vec<u8>, oneshot::Sender<Result<(), Status>>),
+    Delete(Vec<u8>, oneshot::Sender<Result<bool, Status>>),
+    Range(Bound<Vec<u8>>, Bound<Vec<u8>>, stream::Sender<Result<Entry, Status>>),
+}
+
+/// the gRPC `Tree` service over one tree file. the tree lives on a thread of its own taking
+/// requests one at a time, so none of them blocks the async runtime. range streams get their
+/// entries a chunk at a time in between, so a client not reading holds up no other; entries
+/// written meanwhile past where a stream got to show up in it
+pub struct TreeService {
+    ops: mpsc::Sender<Op>,
+}
+
+impl TreeService {
+    /// opens the tree file at `path`, creating it when missing, on the thread it lives on.
+    /// fails the way `BTree::open_or_create` does before any request is taken
+    pub fn open<K, V, P>(path: P) -> Result<Self, BTreeError>
+        where
+            K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone + 'static,
+            V: Encodable + Decodable + BinSizer + Debug + Clone + 'static,
+            P: Into<PathBuf>
+    {
+        let path = path.into();
+        let (ops, rx) = mpsc::channel();
+        let (opened, opening) = mpsc::channel();
+        thread::spawn(move || {
+            let mut tree = match BTree::<K, V>::open_or_create(&path) {
+                Ok(tree) => tree,
+                Err(e) => {
+                    let _ = opened.send(Err(e));
+                    return;
+                }
+            };
+            let _ = opened.send(Ok(()));
+            serve(&mut tree, rx);
+        });
+        opening.recv().map_err(|_| anyhow!("the tree thread is gone"))??;
+        Ok(TreeService { ops })
+    }
+
+    pub fn into_server(self) -> TreeServer<Self> {
+        TreeServer::new(self)
+    }
+
+    fn send(&self, op: Op) -> Result<(), Status> {
+        self.ops.send(op).map_err(|_| Status::internal("the tree thread is gone"))
+    }
+}
+
+/// serves the tree file at `path` on `addr` until the server fails
+pub async fn serve_grpc<K, V, P>(path: P, addr: SocketAddr) -> Result<(), BTreeError>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone + 'static,
+        V: Encodable + Decodable + BinSizer + Debug + Clone + 'static,
+        P: Into<PathBuf>
+{
+    tonic::transport::Server::builder()
+        .add_service(TreeService::open::<K, V, P>(path)?.into_server())
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+// a range stream being served, from `start` on, where the chunk sent last stopped
+struct Streaming {
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    out: stream::Sender<Result<Entry, Status>>,
+}
+
+// how far sending a range stream a chunk got
+enum Chunk {
+    Sent,
+    // its buffer is full, the client has yet to read
+    Full,
+    Done,
+}
+
+// takes requests until the service is dropped, sending each range stream a chunk in turn
+fn serve<K, V>(tree: &mut BTree<K, V>, rx: mpsc::Receiver<Op>)
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    let mut ranges = VecDeque::new();
+    loop {
+        let mut sent = false;
+        for _ in 0..ranges.len() {
+            let mut range = ranges.pop_front().unwrap();
+            match send_chunk(tree, &mut range) {
+                Chunk::Sent => {
+                    sent = true;
+                    ranges.push_back(range);
+                }
+                Chunk::Full => ranges.push_back(range),
+                Chunk::Done => {}
+            }
+        }
+        let op = if ranges.is_empty() {
+            rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else if sent {
+            rx.try_recv().map_err(|e| match e {
+                TryRecvError::Empty => RecvTimeoutError::Timeout,
+                TryRecvError::Disconnected => RecvTimeoutError::Disconnected
+            })
+        } else {
+            rx.recv_timeout(RANGE_POLL)
+        };
+        match op {
+            Ok(Op::Range(start, end, out)) => ranges.push_back(Streaming { start, end, out }),
+            Ok(op) => run(tree, op),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return
+        }
+    }
+}
+
+// sends `range` up to `RANGE_CHUNK` entries, as many as its buffer has room for
+fn send_chunk<K, V>(tree: &BTree<K, V>, range: &mut Streaming) -> Chunk
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    if range.out.capacity() == 0 {
+        return if range.out.is_closed() { Chunk::Done } else { Chunk::Full };
+    }
+    let bounds = match (decode_bound::<K>(range.start.clone()), decode_bound::<K>(range.end.clone())) {
+        (Ok(start), Ok(end)) => (start, end),
+        (Err(e), _) | (_, Err(e)) => {
+            let _ = range.out.try_send(Err(e));
+            return Chunk::Done;
+        }
+    };
+    let mut iter = tree.streaming_iter(bounds);
+    for _ in 0..RANGE_CHUNK {
+        // only this thread sends, so the room there is stays until the client reads
+        if range.out.capacity() == 0 {
+            return Chunk::Sent;
+        }
+        let (k, v) = match iter.next() {
+            Some(entry) => entry,
+            None => return Chunk::Done
+        };
+        range.start = Bound::Excluded(k.to_vec());
+        let entry = Entry { key: k.to_vec(), value: v.to_vec() };
+        // the client hung up
+        if range.out.try_send(Ok(entry)).is_err() {
+            return Chunk::Done;
+        }
+    }
+    Chunk::Sent
+}
+
+fn run<K, V>(tree: &mut BTree<K, V>, op: Op)
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    match op {
+        Op::Get(key, reply) => {
+            let _ = reply.send(decode::<K>(&key).map(|k| tree.get(&k).map(|v| encode(&v))));
+        }
+        Op::Set(key, value, reply) => {
+            let result = decode::<K>(&key).and_then(|k| {
+                tree.set(&k, &decode::<V>(&value)?).map_err(|e| Status::internal(e.to_string()))
+            });
+            let _ = reply.send(result);
+        }
+        Op::Delete(key, reply) => {
+            let result = decode::<K>(&key).and_then(|k| {
+                tree.remove(&k).map(|v| v.is_some()).map_err(|e| Status::internal(e.to_string()))
+            });
+            let _ = reply.send(result);
+        }
+        // served a chunk at a time by `serve`
+        Op::Range(..) => unreachable!()
+    }
+}
+
+fn decode<T: Decodable + BinSizer>(buf: &[u8]) -> Result<T, Status> {
+    if buf.len() != T::bin_size() {
+        return Err(Status::invalid_argument(format!("expected {} bytes, got {}", T::bin_size(), buf.len())));
+    }
+    T::decode(buf).map(|(v, _)| v).map_err(|e| Status::invalid_argument(e.to_string()))
+}
+
+fn decode_bound<T: Decodable + BinSizer>(bound: Bound<Vec<u8>>) -> Result<Bound<T>, Status> {
+    Ok(match bound {
+        Bound::Included(buf) => Bound::Included(decode(&buf)?),
+        Bound::Excluded(buf) => Bound::Excluded(decode(&buf)?),
+        Bound::Unbounded => Bound::Unbounded
+    })
+}
+
+fn encode<T: Encodable + BinSizer>(val: &T) -> Vec<u8> {
+    let mut buf = vec![0; T::bin_size()];
+    val.encode(&mut buf).unwrap();
+    buf
+}
+
+#[tonic::async_trait]
+impl Tree for TreeService {
+    async fn get(&self, request: Request<GetRequest>) -> Result<Response<GetReply>, Status> {
+        let (tx, rx) = oneshot::channel();
+        self.send(Op::Get(request.into_inner().key, tx))?;
+        let value = rx.await.map_err(|_| Status::internal("the tree thread is gone"))??;
+        Ok(Response::new(GetReply { value }))
+    }
+
+    async fn set(&self, request: Request<SetRequest>) -> Result<Response<SetReply>, Status> {
+        let (tx, rx) = oneshot::channel();
+        let SetRequest { key, value } = request.into_inner();
+        self.send(Op::Set(key, value, tx))?;
+        rx.await.map_err(|_| Status::internal("the tree thread is gone"))??;
+        Ok(Response::new(SetReply {}))
+    }
+
+    async fn delete(&self, request: Request<DeleteRequest>) -> Result<Response<DeleteReply>, Status> {
+        let (tx, rx) = oneshot::channel();
+        self.send(Op::Delete(request.into_inner().key, tx))?;
+        let found = rx.await.map_err(|_| Status::internal("the tree thread is gone"))??;
+        Ok(Response::new(DeleteReply { found }))
+    }
+
+    type RangeStream = ReceiverStream<Result<Entry, Status>>;
+
+    async fn range(&self, request: Request<RangeRequest>) -> Result<Response<Self::RangeStream>, Status> {
+        let RangeRequest { start, end } = request.into_inner();
+        let (tx, rx) = stream::channel(RANGE_BUFFER);
+        let start = start.map_or(Bound::Unbounded, Bound::Included);
+        let end = end.map_or(Bound::Unbounded, Bound::Excluded);
+        self.send(Op::Range(start, end, tx))?;
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
@@ -0,0 +1,107 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::page::{Page, PageType, corrupted};
+use crate::{BTree, MAX_DEPTH};
+use anyhow::{anyhow, Result};
+use std::fmt::Debug;
+
+// leaves read to estimate how full leaves are on average
+const LEAF_SAMPLES: usize = 8;
+
+/// an approximate equi-depth histogram of the keys in a tree
+#[derive(Debug, Clone)]
+pub struct KeyHistogram<K> {
+    /// keys splitting the key space into buckets of about the same number of entries:
+    /// bucket `i` holds the keys from `bounds[i - 1]` on and below `bounds[i]`,
+    /// the first and last buckets being open ended
+    pub bounds: Vec<K>,
+    /// the estimated number of entries in each bucket
+    pub entries_per_bucket: u64,
+}
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    /// estimates the key distribution from the separator keys of the internal pages right above
+    /// the leaves, reading those and a few leaves but never scanning the whole tree
+    pub fn key_histogram(&self, buckets: usize) -> Result<KeyHistogram<K>, BTreeError> {
+        if buckets == 0 {
+            return Err(anyhow!("a histogram needs at least one bucket").into());
+        }
+        let root = self.root_page.as_ref().unwrap();
+        if root.page_type == PageType::LEAF {
+            // a single leaf is cheap enough to be exact about
+            let keys: Vec<K> = (0..root.item_count()).filter_map(|i| root.key_at(i)).collect();
+            return Ok(equi_depth(keys, 0, 1.0, buckets));
+        }
+
+        // the separators of each level of internal pages, with the pages of the level below
+        let (mut separators, mut children) = (Vec::new(), Vec::new());
+        collect(root, &mut separators, &mut children);
+        for depth in 1.. {
+            // leaves all sit at the same depth, so looking at one child tells what the level is
+            let first = Page::<K, V>::load_node(self.fd.clone(), children[0])?;
+            if first.page_type != PageType::INTERNAL {
+                break;
+            }
+            if depth == MAX_DEPTH {
+                return Err(corrupted(first.index, "the tree loops back on itself".to_owned()).into());
+            }
+            let (mut next_separators, mut next_children) = (Vec::new(), Vec::new());
+            let mut above = separators.into_iter();
+            for (i, index) in children.iter().enumerate() {
+                // the separator between two pages sorts between their own separators
+                if i > 0 {
+                    next_separators.extend(above.next());
+                }
+                let page = if i == 0 { first.snapshot() } else { Page::<K, V>::load_node(self.fd.clone(), *index)? };
+                if page.page_type != PageType::INTERNAL {
+                    return Err(corrupted(page.index, "leaves at different depths".to_owned()).into());
+                }
+                collect(&page, &mut next_separators, &mut next_children);
+            }
+            separators = next_separators;
+            children = next_children;
+        }
+
+        let step = children.len().div_ceil(LEAF_SAMPLES).max(1);
+        let samples: Vec<usize> = children.iter()
+            .step_by(step)
+            .map(|index| Page::<K, V>::load_node(self.fd.clone(), *index).map(|p| p.item_count()))
+            .collect::<Result<_>>()?;
+        let per_leaf = samples.iter().sum::<usize>() as f64 / samples.len() as f64;
+        Ok(equi_depth(separators, 1, per_leaf, buckets))
+    }
+}
+
+fn collect<K, V>(page: &Page<K, V>, separators: &mut Vec<K>, children: &mut Vec<u32>)
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    separators.extend((0..page.item_count()).filter_map(|i| page.key_at(i)));
+    children.extend((0..=page.item_count()).filter_map(|i| page.ptr_at(i)));
+}
+
+/// picks `buckets - 1` of the sorted `keys` as evenly spaced bounds, key `i` starting unit
+/// `i + first` of a key space made of units of `weight` entries each
+fn equi_depth<K>(keys: Vec<K>, first: usize, weight: f64, buckets: usize) -> KeyHistogram<K> {
+    let units = keys.len() + first;
+    let mut wanted = (1..buckets).map(|j| j * units / buckets).filter(|u| *u >= first).peekable();
+    let mut bounds = Vec::new();
+    for (i, key) in keys.into_iter().enumerate() {
+        // several bounds landing on the same key only count once
+        let mut hit = false;
+        while wanted.peek() == Some(&(i + first)) {
+            wanted.next();
+            hit = true;
+        }
+        if hit {
+            bounds.push(key);
+        }
+    }
+    let entries_per_bucket = (units as f64 * weight / (bounds.len() + 1) as f64).round() as u64;
+    KeyHistogram { bounds, entries_per_bucket }
+}
@@ -0,0 +1,736 @@
+use crate::error::BTreeError;
+use crate::page::{Page, PageType, Pos, corrupted, read_chain};
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::pager::Pager;
+use crate::prefetch::Prefetcher;
+use crate::{BTree, MAX_DEPTH};
+use anyhow::Result;
+use std::fmt::Debug;
+use std::iter::Rev;
+use std::marker::PhantomData;
+use std::ops::{Bound, RangeBounds};
+use std::sync::{Arc, Mutex};
+
+/// walks the leaves of a tree in key order, or in reverse, loading one page per level at a time
+pub(crate) struct Cursor<K, V> {
+    fd: Arc<Mutex<Pager>>,
+    // every page on the path from the root to the current leaf, with the next slot to visit,
+    // or walking in reverse how many slots are left to visit below it
+    stack: Vec<(Page<K, V>, usize)>,
+    reverse: bool,
+    // the bound of the range the walk heads for, its end or in reverse its start
+    stop: Bound<K>,
+    prefetch: Option<Prefetcher>,
+    // the last value read back from overflow pages
+    scratch: Vec<u8>,
+    // what ended the walk short of the bound, a page or an entry it could not read
+    error: Option<BTreeError>,
+}
+
+impl<K, V> Cursor<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    pub fn new<R: RangeBounds<K>>(tree: &BTree<K, V>, range: R) -> Self {
+        Self::open(tree, range, None)
+    }
+
+    /// a cursor walking `range` from its largest key down
+    pub fn new_rev<R: RangeBounds<K>>(tree: &BTree<K, V>, range: R) -> Self {
+        let mut iter = Cursor {
+            fd: tree.fd.clone(),
+            stack: Vec::new(),
+            reverse: true,
+            stop: range.start_bound().cloned(),
+            prefetch: None,
+            scratch: Vec::new(),
+            error: None,
+        };
+        let mut p = tree.root_page.as_ref().unwrap().snapshot();
+        loop {
+            match p.page_type {
+                PageType::INTERNAL => {
+                    let ptr_index = match range.end_bound() {
+                        Bound::Included(k) | Bound::Excluded(k) => {
+                            match p.find(k) {
+                                Some((i, Pos::Left)) => i,
+                                Some((i, _)) => i + 1,
+                                None => panic!("impossible for an empty internal page")
+                            }
+                        }
+                        Bound::Unbounded => p.item_count()
+                    };
+                    let child = match iter.load(p.ptr_at(ptr_index).unwrap()) {
+                        Some(child) if iter.stack.len() < MAX_DEPTH => child,
+                        child => {
+                            iter.end_descent(child.is_some(), p.index);
+                            return iter;
+                        }
+                    };
+                    iter.stack.push((p, ptr_index));
+                    p = child;
+                }
+                PageType::LEAF => {
+                    let left = match range.end_bound() {
+                        Bound::Included(k) => {
+                            match p.find(k) {
+                                Some((i, Pos::Left)) => i,
+                                Some((i, _)) => i + 1,
+                                None => 0
+                            }
+                        }
+                        Bound::Excluded(k) => {
+                            match p.find(k) {
+                                Some((i, Pos::Right)) => i + 1,
+                                Some((i, _)) => i,
+                                None => 0
+                            }
+                        }
+                        Bound::Unbounded => p.item_count()
+                    };
+                    iter.stack.push((p, left));
+                    return iter;
+                }
+                _ => {
+                    panic!("impossible a meta page")
+                }
+            }
+        }
+    }
+
+    fn open<R: RangeBounds<K>>(tree: &BTree<K, V>, range: R, prefetch: Option<Prefetcher>) -> Self {
+        // the root page may hold changes which are not synced yet, so never reload it from disk
+        Self::open_at(tree.fd.clone(), tree.root_page.as_ref().unwrap().snapshot(), range, prefetch)
+    }
+
+    /// a cursor walking `range` down from the root page `p`
+    fn open_at<R: RangeBounds<K>>(fd: Arc<Mutex<Pager>>, mut p: Page<K, V>, range: R, prefetch: Option<Prefetcher>) -> Self {
+        let mut iter = Cursor {
+            fd,
+            stack: Vec::new(),
+            reverse: false,
+            stop: range.end_bound().cloned(),
+            prefetch,
+            scratch: Vec::new(),
+            error: None,
+        };
+        loop {
+            match p.page_type {
+                PageType::INTERNAL => {
+                    let ptr_index = match range.start_bound() {
+                        Bound::Included(k) | Bound::Excluded(k) => {
+                            match p.find(k) {
+                                Some((i, Pos::Left)) => i,
+                                Some((i, _)) => i + 1,
+                                None => panic!("impossible for an empty internal page")
+                            }
+                        }
+                        Bound::Unbounded => 0
+                    };
+                    request_ahead(&mut iter.prefetch, &p, ptr_index + 1);
+                    let child = match iter.load(p.ptr_at(ptr_index).unwrap()) {
+                        Some(child) if iter.stack.len() < MAX_DEPTH => child,
+                        // a corrupted file ends the scan before it starts
+                        child => {
+                            iter.end_descent(child.is_some(), p.index);
+                            return iter;
+                        }
+                    };
+                    iter.stack.push((p, ptr_index + 1));
+                    p = child;
+                }
+                PageType::LEAF => {
+                    let i = match range.start_bound() {
+                        Bound::Included(k) => {
+                            match p.find(k) {
+                                Some((i, Pos::Current)) | Some((i, Pos::Left)) => i,
+                                Some((i, Pos::Right)) => i + 1,
+                                None => 0
+                            }
+                        }
+                        Bound::Excluded(k) => {
+                            match p.find(k) {
+                                Some((i, Pos::Left)) => i,
+                                Some((i, _)) => i + 1,
+                                None => 0
+                            }
+                        }
+                        Bound::Unbounded => 0
+                    };
+                    iter.stack.push((p, i));
+                    return iter;
+                }
+                _ => {
+                    panic!("impossible a meta page")
+                }
+            }
+        }
+    }
+
+    /// moves onto the next entry in range and returns its slot in the leaf on top of the stack
+    fn advance(&mut self) -> Option<usize> {
+        if self.reverse {
+            return self.retreat();
+        }
+        loop {
+            let (p, i) = self.stack.last_mut()?;
+            match p.page_type {
+                PageType::LEAF => {
+                    if *i < p.item_count() {
+                        let slot = *i;
+                        *i += 1;
+                        // only bounded scans pay for decoding the key here
+                        let key = match self.stop {
+                            Bound::Unbounded => None,
+                            _ => Some(p.key_at(slot))
+                        };
+                        let index = p.index;
+                        let past_end = match key {
+                            None => false,
+                            Some(None) => return self.fail(corrupted(index, format!("key {} does not decode", slot))),
+                            Some(Some(k)) => match &self.stop {
+                                Bound::Included(end) => k > *end,
+                                Bound::Excluded(end) => k >= *end,
+                                Bound::Unbounded => false
+                            }
+                        };
+                        if past_end {
+                            self.stack.clear();
+                            return None;
+                        }
+                        return Some(slot);
+                    }
+                    self.stack.pop();
+                }
+                PageType::INTERNAL => {
+                    if *i <= p.item_count() {
+                        let child_page_index = p.ptr_at(*i).unwrap();
+                        *i += 1;
+                        request_ahead(&mut self.prefetch, p, *i);
+                        let index = p.index;
+                        match self.load(child_page_index) {
+                            Some(child) if self.stack.len() < MAX_DEPTH => self.stack.push((child, 0)),
+                            child => {
+                                self.end_descent(child.is_some(), index);
+                                return None;
+                            }
+                        }
+                    } else {
+                        self.stack.pop();
+                    }
+                }
+                _ => {
+                    panic!("impossible a meta page")
+                }
+            }
+        }
+    }
+
+    /// `advance` for a cursor walking in reverse
+    fn retreat(&mut self) -> Option<usize> {
+        loop {
+            let (p, left) = self.stack.last_mut()?;
+            if *left == 0 {
+                self.stack.pop();
+                continue;
+            }
+            *left -= 1;
+            let slot = *left;
+            match p.page_type {
+                PageType::LEAF => {
+                    let key = match self.stop {
+                        Bound::Unbounded => None,
+                        _ => Some(p.key_at(slot))
+                    };
+                    let index = p.index;
+                    let past_start = match key {
+                        None => false,
+                        Some(None) => return self.fail(corrupted(index, format!("key {} does not decode", slot))),
+                        Some(Some(k)) => match &self.stop {
+                            Bound::Included(start) => k < *start,
+                            Bound::Excluded(start) => k <= *start,
+                            Bound::Unbounded => false
+                        }
+                    };
+                    if past_start {
+                        self.stack.clear();
+                        return None;
+                    }
+                    return Some(slot);
+                }
+                PageType::INTERNAL => {
+                    let child_page_index = p.ptr_at(slot).unwrap();
+                    let index = p.index;
+                    match self.load(child_page_index) {
+                        Some(child) if self.stack.len() < MAX_DEPTH => {
+                            let left = match child.page_type {
+                                PageType::INTERNAL => child.item_count() + 1,
+                                _ => child.item_count()
+                            };
+                            self.stack.push((child, left));
+                        }
+                        child => {
+                            self.end_descent(child.is_some(), index);
+                            return None;
+                        }
+                    }
+                }
+                _ => {
+                    panic!("impossible a meta page")
+                }
+            }
+        }
+    }
+
+    /// None when the page is corrupted, which ends the walk on the error
+    fn load(&mut self, index: u32) -> Option<Page<K, V>> {
+        let page = match self.prefetch.as_mut() {
+            Some(prefetch) => prefetch.load(&self.fd, index),
+            None => Page::<K, V>::load(self.fd.clone(), index)
+        };
+        match page {
+            Ok(page) if matches!(page.page_type, PageType::INTERNAL | PageType::LEAF) => Some(page),
+            Ok(page) => self.fail(corrupted(index, format!("a {:?} page is in the tree", page.page_type))),
+            Err(e) => self.fail(e)
+        }
+    }
+
+    /// ends the walk on `e`, kept for the iterator to tell why it stopped short
+    fn fail<T>(&mut self, e: impl Into<BTreeError>) -> Option<T> {
+        self.stack.clear();
+        self.error = Some(e.into());
+        None
+    }
+
+    /// ends the walk where the child of page `index` did not load, which `load` already
+    /// recorded, or sits deeper than any tree goes
+    fn end_descent(&mut self, loaded: bool, index: u32) {
+        self.stack.clear();
+        if loaded {
+            self.error = Some(corrupted(index, "the tree loops back on itself".to_owned()).into());
+        }
+    }
+
+    /// why the walk ended short of its bound, None when it got there or has not yet
+    pub fn error(&self) -> Option<&BTreeError> {
+        self.error.as_ref()
+    }
+
+    /// the key and value at `slot` of the current leaf, a spilled value read back from its
+    /// overflow pages. None when those are corrupted, which ends the scan
+    fn entry_bytes(&mut self, slot: usize) -> Option<(&[u8], &[u8])> {
+        if let Some(first) = self.stack.last().unwrap().0.spilled_at(slot) {
+            if let Err(e) = read_chain::<V>(&self.fd, first, &mut self.scratch) {
+                return self.fail(e);
+            }
+        }
+        let p = &self.stack.last().unwrap().0;
+        match p.spilled_at(slot) {
+            Some(_) => Some((p.leaf_key_at(slot), &self.scratch)),
+            None => Some((p.leaf_key_at(slot), p.raw_value_at(slot)))
+        }
+    }
+
+    /// None as well for an entry that does not decode, which ends the walk
+    pub fn next_entry(&mut self) -> Option<(K, V)> {
+        let slot = self.advance()?;
+        let index = self.stack.last().unwrap().0.index;
+        let (key, value) = self.entry_bytes(slot)?;
+        let entry = decode_entry(key, value);
+        self.decoded(entry, index, slot)
+    }
+
+    pub fn next_raw(&mut self) -> Option<(&[u8], &[u8])> {
+        let slot = self.advance()?;
+        self.entry_bytes(slot)
+    }
+
+    /// the next entry passing `f`, entries it rejects are never decoded
+    pub fn next_matching<F: FnMut(&[u8], &[u8]) -> bool>(&mut self, f: &mut F) -> Option<(K, V)> {
+        loop {
+            let slot = self.advance()?;
+            let index = self.stack.last().unwrap().0.index;
+            let (key, value) = self.entry_bytes(slot)?;
+            if f(key, value) {
+                let entry = decode_entry(key, value);
+                return self.decoded(entry, index, slot);
+            }
+        }
+    }
+
+    fn decoded(&mut self, entry: Result<(K, V)>, index: u32, slot: usize) -> Option<(K, V)> {
+        match entry {
+            Ok(entry) => Some(entry),
+            Err(e) => self.fail(corrupted(index, format!("entry {} does not decode: {}", slot, e)))
+        }
+    }
+}
+
+fn decode_entry<K: Decodable, V: Decodable>(key: &[u8], value: &[u8]) -> Result<(K, V)> {
+    Ok((K::decode(key)?.0, V::decode(value)?.0))
+}
+
+
+/// asks for the children of an internal page from slot `from` on, ahead of their turn
+fn request_ahead<K, V>(prefetch: &mut Option<Prefetcher>, p: &Page<K, V>, from: usize)
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    if let Some(prefetch) = prefetch.as_mut() {
+        let ahead = (from..=p.item_count()).take(prefetch.depth()).map(|j| p.ptr_at(j).unwrap());
+        prefetch.request(ahead);
+    }
+}
+
+/// iterator over a tree borrowed for as long as the scan runs, a corrupted page ends it early
+/// and `error` tells why. it runs from both ends, the two meeting in the middle
+pub struct Iter<'a, K, V> {
+    tree: &'a BTree<K, V>,
+    range: (Bound<K>, Bound<K>),
+    front: Cursor<K, V>,
+    // only set up once the first entry is taken from the back
+    back: Option<Cursor<K, V>>,
+    // the last keys taken from either end, nothing at or past the other one is handed out
+    front_key: Option<K>,
+    back_key: Option<K>,
+}
+
+impl<'a, K, V> Iter<'a, K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    pub(crate) fn new<R: RangeBounds<K>>(tree: &'a BTree<K, V>, range: R) -> Self {
+        Iter {
+            tree,
+            range: (range.start_bound().cloned(), range.end_bound().cloned()),
+            front: Cursor::new(tree, range),
+            back: None,
+            front_key: None,
+            back_key: None,
+        }
+    }
+
+    /// what ended the iteration early from either end, a corrupted page or an entry that does
+    /// not decode. None when it ran through the range, or is not over yet
+    pub fn error(&self) -> Option<&BTreeError> {
+        self.front.error().or_else(|| self.back.as_ref().and_then(|back| back.error()))
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.front.next_entry()?;
+        if self.back_key.as_ref().is_some_and(|back| key >= *back) {
+            self.front.stack.clear();
+            return None;
+        }
+        self.front_key = Some(key.clone());
+        Some((key, value))
+    }
+}
+
+impl<'a, K, V> DoubleEndedIterator for Iter<'a, K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (tree, range) = (self.tree, &self.range);
+        let back = self.back.get_or_insert_with(|| Cursor::new_rev(tree, range.clone()));
+        let (key, value) = back.next_entry()?;
+        if self.front_key.as_ref().is_some_and(|front| key <= *front) {
+            back.stack.clear();
+            return None;
+        }
+        self.back_key = Some(key.clone());
+        Some((key, value))
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    type Item = (K, V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// iterator owning its pages and a copy of the root taken when the scan started, so the
+/// tree stays free for point reads and writes meanwhile. once a write has changed an internal
+/// page the scan goes down again from the root as it is then, past the last key it handed
+/// out, so every entry there was all along comes out once. entries written or removed during
+/// the scan may or may not show up in it
+pub struct Scan<K, V> {
+    cursor: Cursor<K, V>,
+    // the pager's generation the pages the cursor holds were read in
+    generation: u64,
+    // where the rest of the scan starts, just past the last key handed out
+    start: Bound<K>,
+    done: bool,
+}
+
+impl<K, V> Scan<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    fn new<R: RangeBounds<K>>(tree: &BTree<K, V>, range: R, prefetch: Option<Prefetcher>) -> Self {
+        let generation = tree.fd.lock().unwrap().generation();
+        Scan {
+            generation,
+            start: range.start_bound().cloned(),
+            cursor: Cursor::open(tree, range, prefetch),
+            done: false,
+        }
+    }
+
+    /// see `Iter::error`
+    pub fn error(&self) -> Option<&BTreeError> {
+        self.cursor.error()
+    }
+
+    // a new cursor from the root the meta page points at now, the tree syncing it after
+    // every write. pages read ahead may be stale, the rest of the scan goes without them
+    fn reseek(&mut self) {
+        let fd = self.cursor.fd.clone();
+        let range = (self.start.clone(), self.cursor.stop.clone());
+        let root = Page::<K, V>::load(fd.clone(), 0)
+            .and_then(|meta| Page::<K, V>::load_node(fd.clone(), meta.root_index()));
+        match root {
+            Ok(root) => self.cursor = Cursor::open_at(fd, root, range, None),
+            Err(e) => {
+                self.cursor.fail::<()>(e);
+            }
+        }
+    }
+}
+
+impl<K, V> Iterator for Scan<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let generation = self.cursor.fd.lock().unwrap().generation();
+        if generation != self.generation {
+            self.generation = generation;
+            self.reseek();
+        }
+        match self.cursor.next_entry() {
+            Some((key, value)) => {
+                self.start = Bound::Excluded(key.clone());
+                Some((key, value))
+            }
+            None => {
+                self.done = true;
+                None
+            }
+        }
+    }
+}
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    /// every entry of the tree in key order
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter::new(self, ..)
+    }
+
+    /// the entries with keys in `range`, in key order
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Iter<'_, K, V> {
+        Iter::new(self, range)
+    }
+
+    /// every entry of the tree from the largest key down
+    pub fn iter_rev(&self) -> Rev<Iter<'_, K, V>> {
+        self.iter().rev()
+    }
+
+    /// the entry with the smallest key, reached down the leftmost pages
+    pub fn first(&self) -> Option<(K, V)> {
+        Cursor::new(self, ..).next_entry()
+    }
+
+    /// the entry with the largest key, reached down the rightmost pages
+    pub fn last(&self) -> Option<(K, V)> {
+        Cursor::new_rev(self, ..).next_entry()
+    }
+
+    /// takes the entry with the smallest key out of the tree, None when it is empty
+    pub fn pop_first(&mut self) -> Result<Option<(K, V)>, BTreeError> {
+        let key = self.first().map(|(k, _)| k);
+        Ok(self.pop(key)?)
+    }
+
+    /// takes the entry with the largest key out of the tree, None when it is empty
+    pub fn pop_last(&mut self) -> Result<Option<(K, V)>, BTreeError> {
+        let key = self.last().map(|(k, _)| k);
+        Ok(self.pop(key)?)
+    }
+
+    fn pop(&mut self, key: Option<K>) -> Result<Option<(K, V)>> {
+        match key {
+            Some(key) => Ok(self.remove(&key)?.map(|value| (key, value))),
+            None => Ok(None)
+        }
+    }
+
+    /// starts a `Scan` over `range` that does not borrow the tree
+    pub fn scan<R: RangeBounds<K>>(&self, range: R) -> Scan<K, V> {
+        Scan::new(self, range, None)
+    }
+
+    /// like `scan`, with a helper thread reading up to `depth` pages ahead of the scan, so
+    /// waiting on the disk overlaps with whatever the caller does with the entries
+    pub fn scan_prefetched<R: RangeBounds<K>>(&self, range: R, depth: usize) -> Result<Scan<K, V>, BTreeError> {
+        // the helper reads the file itself, past the page cache
+        self.fd.lock().unwrap().flush()?;
+        let prefetch = Prefetcher::spawn(&self.path, self.page_size(), depth)?;
+        Ok(Scan::new(self, range, Some(prefetch)))
+    }
+}
+
+/// scan handing out the encoded bytes of each key and value straight from the leaf buffer,
+/// valid until the next call, so nothing is decoded or allocated per entry
+pub struct StreamingIter<'a, K, V> {
+    cursor: Cursor<K, V>,
+    _tree: PhantomData<&'a BTree<K, V>>,
+}
+
+impl<'a, K, V> StreamingIter<'a, K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    // not an Iterator: every item borrows from the iterator itself
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<(&[u8], &[u8])> {
+        self.cursor.next_raw()
+    }
+
+    /// see `Iter::error`
+    pub fn error(&self) -> Option<&BTreeError> {
+        self.cursor.error()
+    }
+}
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    pub fn streaming_iter<R: RangeBounds<K>>(&self, range: R) -> StreamingIter<'_, K, V> {
+        StreamingIter {
+            cursor: Cursor::new(self, range),
+            _tree: PhantomData,
+        }
+    }
+}
+
+/// iterator over the entries whose encoded key and value pass a filter
+pub struct Filtered<'a, K, V, F> {
+    cursor: Cursor<K, V>,
+    filter: F,
+    _tree: PhantomData<&'a BTree<K, V>>,
+}
+
+impl<'a, K, V, F> Iterator for Filtered<'a, K, V, F>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone,
+        F: FnMut(&[u8], &[u8]) -> bool
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.cursor.next_matching(&mut self.filter)
+    }
+}
+
+impl<'a, K, V, F> Filtered<'a, K, V, F>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    /// see `Iter::error`
+    pub fn error(&self) -> Option<&BTreeError> {
+        self.cursor.error()
+    }
+}
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    /// scans `range`, handing `filter` the encoded key and value of each entry and decoding
+    /// only the ones it accepts
+    pub fn scan_filtered<R, F>(&self, range: R, filter: F) -> Filtered<'_, K, V, F>
+        where
+            R: RangeBounds<K>,
+            F: FnMut(&[u8], &[u8]) -> bool
+    {
+        Filtered {
+            cursor: Cursor::new(self, range),
+            filter,
+            _tree: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BTree;
+    use std::fs;
+
+    #[test]
+    fn a_scan_keeps_every_entry_through_writes_made_meanwhile() {
+        let path = std::env::temp_dir().join(format!("btree-scan-test-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut tree = BTree::<u32, u64>::open_or_create(&path).unwrap();
+        for i in (0..20000u32).step_by(2) {
+            tree.set(&i, &(i as u64)).unwrap();
+        }
+        let mut scan = tree.scan(..);
+        let mut seen = Vec::new();
+        let mut odd = 1;
+        for (key, value) in scan.by_ref() {
+            assert_eq!(value, key as u64);
+            seen.push(key);
+            // splits all over the tree, ahead of the scan and behind it
+            for _ in 0..3 {
+                tree.set(&(odd % 20000), &((odd % 20000) as u64)).unwrap();
+                odd = (odd + 7919 * 2) % 40000;
+            }
+        }
+        assert!(scan.error().is_none());
+        assert!(seen.windows(2).all(|w| w[0] < w[1]));
+        let even: Vec<u32> = seen.into_iter().filter(|k| k % 2 == 0).collect();
+        assert_eq!(even, (0..20000u32).step_by(2).collect::<Vec<u32>>());
+        assert!(tree.verify().unwrap().is_empty());
+        drop(tree);
+        fs::remove_file(&path).unwrap();
+    }
+}
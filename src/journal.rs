@@ -0,0 +1,140 @@
+use crate::byte::Decodable;
+use anyhow::{anyhow, Result};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const JOURNAL_MAGIC: &[u8; 8] = b"BTREEJNL";
+// magic, page size, file length and page count
+const HEADER_SIZE: usize = 8 + 4 + 8 + 4;
+// set in the index of a page recorded as nothing but zeros, which the journal keeps no image of
+const ZEROS: u32 = 1 << 31;
+
+// a recorded page index, with its image unless it was all zeros
+type Recorded<'a> = (u32, Option<&'a [u8]>);
+
+/// where a commit under way keeps the old images of the pages it overwrites in the tree file
+/// at `path`. the journal outlives the commit only when it is cut short
+pub(crate) fn journal_path(path: &Path) -> PathBuf {
+    let mut journal = OsString::from(path.as_os_str());
+    journal.push("-journal");
+    journal.into()
+}
+
+/// records the length of `file` and the current image of every page of `indexes` within it,
+/// and makes sure the record is on disk before anything gets overwritten
+pub(crate) fn write(journal: &Path, file: &mut File, page_size: usize, indexes: &[u32]) -> Result<()> {
+    let file_len = file.metadata()?.len();
+    // pages past the end of the file are new, cutting the file back down undoes them
+    let existing: Vec<u32> = indexes.iter().cloned()
+        .filter(|i| (*i as u64 + 1) * page_size as u64 <= file_len)
+        .collect();
+    let mut out = BufWriter::new(File::create(journal)?);
+    let mut hasher = crc32fast::Hasher::new();
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    header.extend_from_slice(JOURNAL_MAGIC);
+    header.extend_from_slice(&(page_size as u32).to_be_bytes());
+    header.extend_from_slice(&file_len.to_be_bytes());
+    header.extend_from_slice(&(existing.len() as u32).to_be_bytes());
+    out.write_all(&header)?;
+    hasher.update(&header);
+    let mut buf = vec![0u8; page_size];
+    for index in existing {
+        file.seek(SeekFrom::Start(index as u64 * page_size as u64))?;
+        file.read_exact(&mut buf)?;
+        // pages the file grew by ahead of writing them are common, and all alike
+        if buf.iter().all(|b| *b == 0) {
+            out.write_all(&(index | ZEROS).to_be_bytes())?;
+            hasher.update(&(index | ZEROS).to_be_bytes());
+            continue;
+        }
+        out.write_all(&index.to_be_bytes())?;
+        out.write_all(&buf)?;
+        hasher.update(&index.to_be_bytes());
+        hasher.update(&buf);
+    }
+    out.write_all(&hasher.finalize().to_be_bytes())?;
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    sync_dir(journal);
+    Ok(())
+}
+
+/// the commit is done once its journal is gone
+pub(crate) fn remove(journal: &Path) -> Result<()> {
+    fs::remove_file(journal)?;
+    sync_dir(journal);
+    Ok(())
+}
+
+/// puts back the pages recorded in a journal a commit cut short left behind and returns
+/// whether there were any. a journal cut short itself was written before the file was touched
+/// and is just removed
+pub(crate) fn recover(journal: &Path, file: &mut File) -> Result<bool> {
+    let bytes = match fs::read(journal) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into())
+    };
+    let complete = parse(&bytes);
+    if let Some((page_size, file_len, pages)) = complete.as_ref() {
+        let zeros = vec![0u8; *page_size];
+        for (index, image) in pages {
+            file.seek(SeekFrom::Start(*index as u64 * *page_size as u64))?;
+            file.write_all(image.unwrap_or(&zeros))?;
+        }
+        file.set_len(*file_len)?;
+        file.sync_all()?;
+    }
+    remove(journal)?;
+    Ok(complete.is_some())
+}
+
+// the page size, file length and recorded pages of a complete journal, without an image
+// for the pages of zeros
+fn parse(bytes: &[u8]) -> Option<(usize, u64, Vec<Recorded<'_>>)> {
+    if bytes.len() < HEADER_SIZE + 4 || &bytes[..8] != JOURNAL_MAGIC {
+        return None;
+    }
+    let (body, crc) = bytes.split_at(bytes.len() - 4);
+    if crc32fast::hash(body) != u32::decode(crc).ok()?.0 {
+        return None;
+    }
+    let page_size = u32::decode(&body[8..]).ok()?.0 as usize;
+    let file_len = u64::decode(&body[12..]).ok()?.0;
+    let count = u32::decode(&body[20..]).ok()?.0 as usize;
+    let mut rest = &body[HEADER_SIZE..];
+    let mut pages = Vec::with_capacity(count);
+    for _ in 0..count {
+        let index = u32::decode(rest).ok()?.0;
+        rest = &rest[4..];
+        if index & ZEROS != 0 {
+            pages.push((index & !ZEROS, None));
+        } else {
+            let image = rest.get(..page_size)?;
+            rest = &rest[page_size..];
+            pages.push((index, Some(image)));
+        }
+    }
+    if !rest.is_empty() {
+        return None;
+    }
+    Some((page_size, file_len, pages))
+}
+
+/// a file created or removed is only there for good once its directory is synced too,
+/// which not every platform can do
+fn sync_dir(path: &Path) {
+    if let Some(dir) = path.parent() {
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+        let _ = File::open(dir).and_then(|d| d.sync_all());
+    }
+}
+
+/// fails when the tree file at `path` needs a journal rolled back first, which takes write access
+pub(crate) fn check_none(path: &Path) -> Result<()> {
+    if journal_path(path).exists() {
+        return Err(anyhow!("{} has a commit cut short, open it writable to roll it back", path.display()));
+    }
+    Ok(())
+}
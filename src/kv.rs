@@ -0,0 +1,84 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::overlay::Overlay;
+use crate::BTree;
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::ops::RangeBounds;
+
+/// what code written against a generic ordered key-value store needs from it, so the store
+/// behind it can be a tree file, an overlay or a plain in-memory map
+pub trait KvStore<K, V> {
+    fn get(&self, key: &K) -> Option<V>;
+
+    fn set(&mut self, key: &K, value: &V) -> Result<(), BTreeError>;
+
+    fn remove(&mut self, key: &K) -> Result<(), BTreeError>;
+
+    /// entries with a key in `range`, in key order
+    fn range<'a, R: RangeBounds<K>>(&'a self, range: R) -> Box<dyn Iterator<Item = (K, V)> + 'a>;
+}
+
+impl<K, V> KvStore<K, V> for BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    fn get(&self, key: &K) -> Option<V> {
+        BTree::get(self, key)
+    }
+
+    fn set(&mut self, key: &K, value: &V) -> Result<(), BTreeError> {
+        BTree::set(self, key, value)
+    }
+
+    fn remove(&mut self, key: &K) -> Result<(), BTreeError> {
+        BTree::remove(self, key).map(|_| ())
+    }
+
+    fn range<'a, R: RangeBounds<K>>(&'a self, range: R) -> Box<dyn Iterator<Item = (K, V)> + 'a> {
+        Box::new(BTree::range(self, range))
+    }
+}
+
+impl<K, V> KvStore<K, V> for Overlay<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    fn get(&self, key: &K) -> Option<V> {
+        Overlay::get(self, key)
+    }
+
+    fn set(&mut self, key: &K, value: &V) -> Result<(), BTreeError> {
+        Overlay::set(self, key, value)
+    }
+
+    fn remove(&mut self, key: &K) -> Result<(), BTreeError> {
+        Overlay::remove(self, key)
+    }
+
+    fn range<'a, R: RangeBounds<K>>(&'a self, range: R) -> Box<dyn Iterator<Item = (K, V)> + 'a> {
+        Box::new(Overlay::range(self, range))
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> KvStore<K, V> for BTreeMap<K, V> {
+    fn get(&self, key: &K) -> Option<V> {
+        BTreeMap::get(self, key).cloned()
+    }
+
+    fn set(&mut self, key: &K, value: &V) -> Result<(), BTreeError> {
+        self.insert(key.clone(), value.clone());
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &K) -> Result<(), BTreeError> {
+        BTreeMap::remove(self, key);
+        Ok(())
+    }
+
+    fn range<'a, R: RangeBounds<K>>(&'a self, range: R) -> Box<dyn Iterator<Item = (K, V)> + 'a> {
+        Box::new(BTreeMap::range(self, range).map(|(k, v)| (k.clone(), v.clone())))
+    }
+}
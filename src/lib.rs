@@ -1,147 +1,550 @@
-use std::fs::{File, OpenOptions};
-use crate::page::{Page, PageType, Pos, PageError};
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use crate::page::{Page, PageType, Pos, Slot, Stat, corrupted, key_order_crc, max_key_size};
+pub use crate::page::{PAGE_SIZE, MIN_PAGE_SIZE, MAX_PAGE_SIZE, FORMAT_VERSION, MAX_KEY_SIZE, MAX_VALUE_SIZE};
+pub use crate::error::BTreeError;
+use crate::page::PageError;
+use crate::pager::{Pager, check_page_size, EXTENT_SIZE};
+use crate::registry::Registration;
+use crate::cow::CopyOnWrite;
+use crate::descent::PinnedPath;
 pub use crate::byte::*;
-use anyhow::Result;
+pub use crate::iter::{Filtered, Iter, Scan, StreamingIter};
+pub use crate::merge::{Conflict, Resolver};
+pub use crate::overlay::{Overlay, OverlayIter};
+pub use crate::stats::Stats;
+pub use crate::metrics::Metrics;
+pub use crate::schema::{Schema, Versioned};
+pub use crate::table::{Row, Rows, Table};
+pub use crate::kv::KvStore;
+pub use crate::histogram::KeyHistogram;
+pub use crate::archive::ARCHIVE_VERSION;
+pub use crate::txn::Txn;
+pub use crate::batch::WriteBatch;
+pub use crate::group::GroupCommit;
+pub use crate::durability::Durability;
+pub use crate::bucket::Bucket;
+pub use crate::verify::Problem;
+pub use crate::export::{Format, TextField};
+pub use crate::options::TreeBuilder;
+pub use crate::order::{KeyOrder, Collated, Descending, CaseInsensitive};
+#[cfg(feature = "arrow")]
+pub use crate::arrow::ArrowField;
+// for the `ArrowField` impls of `define_fixed_len_str!` types
+#[cfg(feature = "arrow")]
+#[doc(hidden)]
+pub use {arrow_array as __arrow_array, arrow_schema as __arrow_schema};
+#[cfg(feature = "grpc")]
+pub use crate::grpc::{proto, serve_grpc, TreeService};
+#[cfg(feature = "tokio")]
+pub use crate::async_tree::AsyncBTree;
+#[cfg(feature = "serde")]
+pub use crate::serde_codec::SerdeCodec;
+use anyhow::{anyhow, Result};
 use std::fmt::Debug;
-use std::rc::Rc;
-use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
 
+#[macro_use]
+mod diag;
 mod page;
+mod pager;
 mod byte;
+mod iter;
+mod merge;
+mod build;
+mod shard;
+mod overlay;
+mod stats;
+mod metrics;
+mod salvage;
+mod schema;
+mod table;
+mod convert;
+mod kv;
+mod prefetch;
+mod reclaim;
+mod histogram;
+mod archive;
+mod registry;
+mod remove;
+mod journal;
+mod doublewrite;
+mod txn;
+mod cow;
+mod overflow;
+mod batch;
+mod group;
+mod durability;
+mod order;
+mod entry;
+mod get_many;
+mod rank;
+mod bucket;
+mod compress;
+mod compact;
+mod verify;
+mod dump;
+mod export;
+mod backup;
+mod options;
+mod error;
+mod descent;
+#[cfg(feature = "direct-io")]
+mod direct;
+#[cfg(feature = "arrow")]
+mod arrow;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "tokio")]
+mod async_tree;
+#[cfg(feature = "serde")]
+mod serde_codec;
 
+// a root with fewer keys than this gets its children checked for ordering too
+const ORDER_SAMPLE: usize = 8;
+// deeper than any tree u32 page indexes can address, a descent going further is looping
+pub(crate) const MAX_DEPTH: usize = 32;
+// pages a tree keeps cached unless told otherwise
+const DEFAULT_CACHE_PAGES: usize = 64;
+
+// the encoded separators either side of a page in its parent
+type Fences = (Option<Vec<u8>>, Option<Vec<u8>>);
+
+/// a tree file. reads through `&self`, like `get` and `scan`, can run on many threads at once
+/// and only hold the pager's lock while a page is read; writes take `&mut self`, so readers
+/// and writers sharing a tree put it behind an `RwLock`
 pub struct BTree<K, V>
 {
-    path: &'static str,
-    fd: Rc<RefCell<File>>,
+    path: PathBuf,
+    fd: Arc<Mutex<Pager>>,
+    // dropped in declaration order: the root page has to reach the file before the meta page
+    // records the file digest
+    root_page: Option<Page<K, V>>,
+    // while a named tree is open in its place, the catalog page and the named tree's slot there
+    catalog: Option<(Page<K, V>, usize)>,
     meta_page: Option<Page<K, V>>,
-    root_page: Option<Page<K, V>>
+    read_only: bool,
+    max_pages: Option<u32>,
+    cow: Option<CopyOnWrite>,
+    durability: Durability,
+    pinned: Mutex<PinnedPath<K, V>>,
+    // held by writable trees, dropped last so the file is released only once it is written out
+    _registration: Option<Registration>,
+}
+
+/// what opening a tree file does about a file that is missing or already there
+#[derive(PartialEq, Clone, Copy)]
+enum Mode {
+    Open,
+    Create,
+    OpenOrCreate,
+}
+
+// keeps the tree shareable across threads
+const _: fn() = || {
+    fn shareable<T: Send + Sync>() {}
+    shareable::<BTree<u64, u64>>();
+};
+
+// needs no bounds on the key and value types, so dropping a tree can write it out
+impl<K, V> BTree<K, V> {
+    fn sync(&mut self) -> Result<()>{
+        if let Some(p) = self.root_page.as_mut() {
+            p.sync()?;
+        }
+        if let Some((p, _)) = self.catalog.as_mut() {
+            p.sync()?;
+        }
+        if let Some(p) = self.meta_page.as_mut() {
+            p.sync()?;
+        }
+        self.fd.lock().unwrap().flush()
+    }
 }
 
 impl<K, V> BTree<K, V>
     where
-        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
-        V: Encodable + Decodable + BinSizer + Debug + Clone
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
 {
-    pub fn new(path: &'static str) -> Self {
-        let fd = OpenOptions::new()
-            .create(true)
+    /// opens the tree file at `path`, creating it when missing, and panics where `try_new`
+    /// fails. the rest of the public API goes through the constructors returning `BTreeError`
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self::with_page_size(path, PAGE_SIZE)
+    }
+
+    /// like `new`, but a file created here gets pages of `page_size` bytes, a power of two
+    /// from `MIN_PAGE_SIZE` to `MAX_PAGE_SIZE`. an existing file keeps the page size it was created with
+    pub fn with_page_size<P: AsRef<Path>>(path: P, page_size: usize) -> Self {
+        Self::try_with_page_size(path, page_size).unwrap()
+    }
+
+    /// like `new`, failing instead of panicking when the file cannot be opened or is corrupted
+    pub fn try_new<P: AsRef<Path>>(path: P) -> Result<Self, BTreeError> {
+        Self::try_with_page_size(path, PAGE_SIZE)
+    }
+
+    /// fails with `BTreeError::AlreadyOpen` while another writable tree over the same file is alive.
+    /// a transaction whose commit was cut short is rolled back first
+    pub fn try_with_page_size<P: AsRef<Path>>(path: P, page_size: usize) -> Result<Self, BTreeError> {
+        Ok(Self::open_with(path, page_size, Mode::OpenOrCreate, false)?)
+    }
+
+    /// opens an existing tree file for writing. a missing file fails with the
+    /// `BTreeError::Io` of kind `NotFound`, anything but a tree with `BTreeError::InvalidFormat`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, BTreeError> {
+        Ok(Self::open_with(path, PAGE_SIZE, Mode::Open, false)?)
+    }
+
+    /// creates a new, empty tree file, failing with the `std::io::Error` of kind
+    /// `AlreadyExists` rather than touching a file already there
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, BTreeError> {
+        Ok(Self::open_with(path, PAGE_SIZE, Mode::Create, false)?)
+    }
+
+    /// `open` when the file exists, `create` otherwise, the same as `try_new`
+    pub fn open_or_create<P: AsRef<Path>>(path: P) -> Result<Self, BTreeError> {
+        Ok(Self::open_with(path, PAGE_SIZE, Mode::OpenOrCreate, false)?)
+    }
+
+    /// `double_write` writes pages through `<path>-dwb` from the first page written on, see
+    /// `TreeBuilder::double_write`
+    fn open_with<P: AsRef<Path>>(path: P, page_size: usize, mode: Mode, double_write: bool) -> Result<Self> {
+        check_page_size(page_size)?;
+        let mut fd = OpenOptions::new()
+            .create(mode == Mode::OpenOrCreate)
+            .create_new(mode == Mode::Create)
+            .truncate(false)
             .read(true)
             .write(true)
-            .open(path).expect("could not open btree file");
+            .open(path.as_ref())?;
+        let registration = Registration::acquire(path.as_ref())?;
+        // pages torn on their way to the file get repaired before the journal rolls any back
+        doublewrite::recover(path.as_ref(), &mut fd)?;
+        journal::recover(&journal::journal_path(path.as_ref()), &mut fd)?;
+        let file_len = fd.metadata()?.len();
+        if file_len == 0 && mode == Mode::Open {
+            return Err(PageError::NotATree.into());
+        }
+        let page_size = if file_len == 0 {
+            page_size
+        } else {
+            Pager::stored_page_size(&fd)?
+        };
+        Self::check_sizes(page_size)?;
+        let mut pager = Pager::new(fd, page_size);
+        pager.set_cache_capacity(DEFAULT_CACHE_PAGES)?;
+        // loading may write already, upgrading an older file or laying out a new one
+        if double_write {
+            pager.open_double_write(path.as_ref())?;
+        }
+        if file_len != 0 {
+            pager.find_meta()?;
+        }
         let mut btree = BTree::<K, V> {
-            path,
-            fd: Rc::new(RefCell::new(fd)),
-            meta_page: None,
+            path: path.as_ref().to_path_buf(),
+            fd: Arc::new(Mutex::new(pager)),
             root_page: None,
+            catalog: None,
+            meta_page: None,
+            read_only: false,
+            max_pages: None,
+            cow: None,
+            durability: Durability::default(),
+            pinned: Mutex::default(),
+            _registration: Some(registration),
         };
-        let file_len = btree.fd.as_ref().borrow().metadata().unwrap().len();
         if file_len == 0 {
-            btree.init_as_empty()
+            btree.init_as_empty()?
         } else {
-            btree.init_load()
+            btree.init_load()?
         }
-        btree
+        Ok(btree)
     }
 
-    fn sync(&mut self) -> Result<()>{
-        if let Some(p) = self.meta_page.as_mut() {
-            p.sync()?;
+    /// opens an existing tree file without write access, every `set` on it fails
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Self, BTreeError> {
+        journal::check_none(path.as_ref())?;
+        let fd = OpenOptions::new()
+            .read(true)
+            .open(path.as_ref())?;
+        if fd.metadata()?.len() == 0 {
+            return Err(BTreeError::InvalidFormat(format!("{} is not a btree file", path.as_ref().display())));
         }
-        if let Some(p) = self.root_page.as_mut() {
-            p.sync()?;
+        let page_size = Pager::stored_page_size(&fd)?;
+        Self::check_sizes(page_size)?;
+        let mut pager = Pager::new(fd, page_size);
+        pager.set_cache_capacity(DEFAULT_CACHE_PAGES)?;
+        pager.find_meta()?;
+        let mut btree = BTree::<K, V> {
+            path: path.as_ref().to_path_buf(),
+            fd: Arc::new(Mutex::new(pager)),
+            root_page: None,
+            catalog: None,
+            meta_page: None,
+            read_only: true,
+            max_pages: None,
+            cow: None,
+            durability: Durability::default(),
+            pinned: Mutex::default(),
+            _registration: None,
+        };
+        btree.init_load()?;
+        Ok(btree)
+    }
+
+    /// fails for keys or values too big for trees with pages of `page_size`, see `MAX_KEY_SIZE`
+    /// and `MAX_VALUE_SIZE`
+    fn check_sizes(page_size: usize) -> Result<(), BTreeError> {
+        // internal pages keep counts only with room for two keys besides, as `Builder::create`
+        // and `init_as_empty` decide, and a shared prefix only where it makes more room
+        if K::bin_size() > MAX_KEY_SIZE || Page::<K, V>::internal_capacity(page_size, false) < 2 {
+            return Err(BTreeError::KeyTooLarge { size: K::bin_size(), max: max_key_size(page_size) });
+        }
+        if V::bin_size() > MAX_VALUE_SIZE {
+            return Err(BTreeError::ValueTooLarge { size: V::bin_size(), max: MAX_VALUE_SIZE });
         }
         Ok(())
     }
 
-    fn init_as_empty(&mut self) {
-        println!("init empty btree");
-        let mut meta_page = Page::<K, V>::new(self.fd.clone(), 0, PageType::META).unwrap();
-        meta_page.set_total_page(2);
-        meta_page.set_root_index(1);
-        let mut root_page = Page::<K, V>::new(self.fd.clone(), 1, PageType::LEAF).unwrap();
-        root_page.set_item_count(0).unwrap();
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.fd.lock().unwrap().page_size()
+    }
+
+    /// writes everything out and returns the digest of all pages, as recorded in the meta page
+    pub fn checksum(&mut self) -> Result<u32, BTreeError> {
+        if !self.read_only {
+            self.sync()?;
+        }
+        Ok(self.meta_page.as_ref().unwrap().file_digest())
+    }
+
+    /// rereads every page from disk and checks it against the digest the meta page recorded
+    pub fn verify_checksum(&mut self) -> Result<(), BTreeError> {
+        let expected = self.checksum()?;
+        let total_pages = self.meta_page.as_ref().unwrap().total_pages();
+        let mut spares = self.meta_page.as_ref().unwrap().spares();
+        spares.extend(self.meta_page.as_ref().unwrap().second_meta());
+        let actual = self.fd.lock().unwrap().compute_digest(total_pages, &spares)?;
+        if actual != expected {
+            return Err(anyhow!("{} is corrupted: pages hash to {:08x}, meta page recorded {:08x}", self.path.display(), actual, expected).into());
+        }
+        Ok(())
+    }
+
+    fn init_as_empty(&mut self) -> Result<()> {
+        let mut meta_page = Page::<K, V>::new(self.fd.clone(), 0, PageType::META)?;
+        self.fd.lock().unwrap().require_checksums(meta_page.checksummed());
+        // page 1 holds the second meta page
+        meta_page.keep_second_meta();
+        meta_page.set_total_page(3);
+        meta_page.set_root_index(2);
+        // keys too big for internal pages to hold two along with counts get none
+        if Page::<K, V>::internal_capacity(self.page_size(), true) >= 2 {
+            meta_page.keep_counts();
+        }
+        let mut root_page = Page::<K, V>::new(self.fd.clone(), 2, PageType::LEAF)?;
+        root_page.set_item_count(0)?;
 
         self.meta_page = Some(meta_page);
         self.root_page = Some(root_page);
-        self.sync().unwrap();
+        self.sync()?;
+        event!(info, path:% = self.path.display(), page_size = self.page_size(); "created an empty tree");
+        Ok(())
     }
 
-    fn init_load(&mut self) {
-        let meta_page = Page::<K, V>::load(self.fd.clone(), 0).unwrap();
-        assert_eq!(meta_page.page_type, PageType::META);
+    /// refuses files built for other key and value types before touching anything else
+    fn init_load(&mut self) -> Result<()> {
+        let mut meta_page = Page::<K, V>::load(self.fd.clone(), 0)?;
+        if meta_page.page_type != PageType::META {
+            return Err(anyhow!("{} has no meta page", self.path.display()));
+        }
+        meta_page.check_format()?;
+        // pages dropped on the way out must not rewrite the meta page with a different digest
+        self.fd.lock().unwrap().set_digest(meta_page.file_digest());
+        self.fd.lock().unwrap().require_checksums(meta_page.checksummed());
+        #[cfg(feature = "lz4")]
+        self.fd.lock().unwrap().set_compression(meta_page.compression() == compress::LZ4);
+        let (stored_key, stored_value) = meta_page.layout();
+        let recorded = (stored_key, stored_value) != (0, 0);
+        if recorded && (stored_key != K::bin_size() || stored_value != V::bin_size()) {
+            return Err(PageError::LayoutMismatch { stored_key, stored_value, key: K::bin_size(), value: V::bin_size() }.into());
+        }
+        if meta_page.key_order() != key_order_crc::<K>() {
+            let order = match K::key_order() {
+                order if order.is_empty() => "the natural one".to_owned(),
+                name => name.into_owned()
+            };
+            return Err(PageError::KeyOrderMismatch { order }.into());
+        }
 
-        let root_page = Page::<K, V>::load(self.fd.clone(), meta_page.root_index()).unwrap();
-        println!("root page index: {}; total pages:{}; root page keys: {};", meta_page.root_index(), meta_page.total_pages(), root_page.item_count());
+        // a split cut short may leave a page per level and a new root unwritten past the end
+        let file_pages = self.fd.lock().unwrap().file_pages()?;
+        if meta_page.total_pages() as u64 > file_pages + MAX_DEPTH as u64 + 1 {
+            return Err(corrupted(0, format!("{} pages recorded in a file of {}", meta_page.total_pages(), file_pages)));
+        }
+        let root_page = Page::<K, V>::load_node(self.fd.clone(), meta_page.root_index())?;
+        self.check_order(&root_page)?;
+        if !recorded && !self.read_only {
+            meta_page.record_layout();
+        }
+        if meta_page.format_version() < FORMAT_VERSION && !self.read_only {
+            if meta_page.format_version() < 2 {
+                // older files never kept count of their entries
+                let entries = self.count_entries(&root_page)?;
+                meta_page.set_stat(Stat::Entries, entries);
+            }
+            meta_page.record_format();
+        }
+        event!(info, path:% = self.path.display(), root = meta_page.root_index(), total_pages = meta_page.total_pages(),
+            root_keys = root_page.item_count(), read_only = self.read_only; "opened a tree");
         self.meta_page = Some(meta_page);
         self.root_page = Some(root_page);
+        Ok(())
     }
 
-    pub fn set(&mut self, key: &K, value: &V) -> Result<()> {
-        let mut p = self.root_page.as_mut().unwrap();
+    /// the root's keys span the whole tree, so a key type that sorts differently from the one
+    /// the file was built with shows up among them, or among its children's when it has only a few
+    fn check_order(&self, root: &Page<K, V>) -> Result<()> {
+        // each key along with whether it is one of the root's separators
+        let mut keys = Vec::new();
+        if root.page_type == PageType::INTERNAL && root.item_count() < ORDER_SAMPLE {
+            for i in 0..=root.item_count() {
+                let child = Page::<K, V>::load_node(self.fd.clone(), root.ptr_at(i).unwrap())?;
+                keys.extend((0..child.item_count()).map(|j| child.key_at(j).map(|k| (k, false))));
+                if i < root.item_count() {
+                    keys.push(root.key_at(i).map(|k| (k, true)));
+                }
+            }
+        } else {
+            keys.extend((0..root.item_count()).map(|j| root.key_at(j).map(|k| (k, false))));
+        }
+        let keys: Option<Vec<(K, bool)>> = keys.into_iter().collect();
+        // a split copies the first key of the right page up, so a separator may equal the key after it
+        let in_order = |w: &[(K, bool)]| w[0].0 < w[1].0 || (w[0].1 && w[0].0 == w[1].0);
+        match keys {
+            Some(keys) if keys.windows(2).all(in_order) => Ok(()),
+            _ => Err(PageError::OrderMismatch { index: root.index }.into())
+        }
+    }
+
+    pub fn set(&mut self, key: &K, value: &V) -> Result<(), BTreeError> {
+        if self.read_only {
+            return Err(BTreeError::ReadOnly { path: self.path.clone() });
+        }
+        self.put(key, value)?;
+        self.touch(Stat::LastModified);
+        Ok(self.finish_write()?)
+    }
+
+    pub(crate) fn put(&mut self, key: &K, value: &V) -> Result<()> {
+        // a key or value that fails to encode, such as one too long for its type, is turned
+        // away before any page is touched
+        let mut buf = vec![0u8; K::bin_size().max(V::bin_size())];
+        key.encode(&mut buf)?;
+        value.encode(&mut buf)?;
+        let spilled = self.spill(value)?;
+        let slot = match spilled {
+            Some(first) => Slot::Spilled(first),
+            None => Slot::Value(value)
+        };
+        match (self.put_slot(key, &slot), spilled) {
+            // nothing points at the chain yet
+            (Err(e), Some(first)) => self.free_chain(first).and(Err(e)),
+            (put, _) => put
+        }
+    }
+
+    fn put_slot(&mut self, key: &K, value: &Slot<V>) -> Result<()> {
         let mut pages = Vec::new();
-        loop {
-            match p.page_type {
-                PageType::INTERNAL => {
-                    match p.find(key) {
-                        Some((i, pos)) => {
-                            let ptr_index = match pos {
-                                Pos::Left => {
-                                    i
-                                }
-                                _ => {
-                                   i + 1
-                                }
-                            };
-                            let child_page_index = p.ptr_at(ptr_index).unwrap();
-                            pages.push(Page::<K, V>::load(self.fd.clone(), child_page_index).unwrap());
-                            let len = pages.len();
-                            p = &mut pages[len - 1];
-                        }
-                        None => {
-                            panic!("impossible for an empty internal page")
-                        }
-                    }
+        // the pages on the way down get changed when the leaf splits, pinned ones are copied
+        let leaf = self.descend(key, |p| pages.push(p.copy()))?;
+        pages.extend(leaf);
+        let p = match pages.last_mut() {
+            Some(p) => p,
+            None => self.root_page.as_mut().unwrap()
+        };
+        // the chain an overwritten value spilled to goes once the new one is in
+        let replaced = match value {
+            Slot::Spilled(_) => match p.find(key) {
+                Some((i, Pos::Current)) => p.spilled_at(i),
+                _ => None
+            },
+            Slot::Value(_) => None
+        };
+        match p.insert(key, value) {
+            Ok(inserted) => {
+                // inserted, done!
+                if inserted {
+                    self.count_along(key, pages.iter_mut(), 1)?;
                 }
-                PageType::LEAF => {
-                    match p.insert(key, value) {
-                        Ok(_) => {
-                            // inserted, done!
-                            return Ok(());
-                        },
-                        Err(err) => {
-                            match err.downcast_ref::<PageError>() {
-                                Some(PageError::Full) => {
-                                    // eh..., the page is full, we need to split it
-                                    break;
-                                }
-                                _ => {
-                                    return Err(err);
-                                }
-                            }
-                        }
+                self.bump_stat(if inserted { Stat::Inserts } else { Stat::Overwrites });
+                return match replaced {
+                    Some(first) => self.free_chain(first),
+                    None => Ok(())
+                };
+            },
+            Err(err) => {
+                match err.downcast_ref::<PageError>() {
+                    Some(PageError::Full) => {
+                        // eh..., the page is full, we need to split it
+                    }
+                    _ => {
+                        return Err(err);
                     }
-                }
-                _ => {
-                    panic!("impossible a meta page")
                 }
             }
         }
         // page is full, split it!
         // println!("page is full");
+        // make sure every page the split needs can be allocated before touching anything,
+        // a split cut short would lose the entries already moved out
+        let mut needed = 0;
+        let mut reaches_root = true;
+        for p in pages.iter().rev() {
+            if p.page_type == PageType::LEAF || p.is_full() {
+                needed += 1;
+            } else {
+                reaches_root = false;
+                break;
+            }
+        }
+        if reaches_root {
+            let root_page = self.root_page.as_ref().unwrap();
+            if root_page.page_type == PageType::LEAF || root_page.is_full() {
+                // the root splits and a new root goes on top
+                needed += 2;
+            }
+        }
+        self.reserve_pages(needed)?;
+        self.bump_stat(Stat::Inserts);
+        let fences = self.fences(key, &pages);
+        // the separator going up with the new page, and how many entries each half holds
         let mut kp = None;
-        for p in pages.iter_mut().rev() {
+        for (depth, fences) in fences.into_iter().enumerate().rev() {
+            let (above, below) = pages.split_at_mut(depth);
+            let p = &mut below[0];
             match p.page_type {
                 PageType::LEAF => {
                     // leaf page must be full in this case
-                    kp = Some(self.split_leaf_page(p, key, value)?);
+                    let (k, ptr, right) = self.split_leaf_page(p, key, value, fences.1.is_none())?;
+                    kp = Some((k, ptr, p.total(), right));
                 }
                 PageType::INTERNAL => {
-                    let (k, ptr) = kp.unwrap();
+                    let (k, ptr, left, right) = kp.unwrap();
+                    p.set_count_at(p.child_slot(key), left)?;
                     if p.is_full() {
-                        kp = Some(self.split_internal_page(p, &k, ptr)?);
+                        let (k, ptr, right) = self.split_internal_page(p, &k, ptr, right, fences)?;
+                        kp = Some((k, ptr, p.total(), right));
                     } else {
-                        p.insert_ptr(&k, ptr)?;
-                        return Ok(());
+                        p.insert_ptr(&k, ptr, right)?;
+                        return self.count_along(key, above.iter_mut(), 1);
                     }
                 }
                 _ => {
@@ -152,45 +555,47 @@ impl<K, V> BTree<K, V>
 
         // so root page must be changed
         match kp {
-            Some((k, ptr)) => {
+            Some((k, ptr, left, right)) => {
                 let is_root_full;
                 {
                     let root_page = self.root_page.as_mut().unwrap();
                     assert_eq!(root_page.page_type, PageType::INTERNAL);
+                    root_page.set_count_at(root_page.child_slot(key), left)?;
                     is_root_full = root_page.is_full();
                 }
 
                 if is_root_full {
                     let mut root_page = self.root_page.take().unwrap();
-                    let (k2, ptr2) = self.split_internal_page(&mut root_page, &k, ptr)?;
-                    let mut new_root_page = self.new_page(PageType::INTERNAL)?;
+                    let (k2, ptr2, right2) = self.split_internal_page(&mut root_page, &k, ptr, right, (None, None))?;
+                    let mut new_root_page = self.new_internal_page()?;
                     new_root_page.set_item_count(1)?;
                     new_root_page.set_ptr_at(0, root_page.index)?;
+                    new_root_page.set_count_at(0, root_page.total())?;
                     new_root_page.set_key_at(0, &k2)?;
                     new_root_page.set_ptr_at(1, ptr2)?;
+                    new_root_page.set_count_at(1, right2)?;
 
-                    let meta_page = self.meta_page.as_mut().unwrap();
-                    meta_page.set_root_index(new_root_page.index);
+                    self.set_root_index(new_root_page.index);
                     self.root_page = Some(new_root_page);
                 } else {
                     let root_page = self.root_page.as_mut().unwrap();
-                    root_page.insert_ptr(&k, ptr)?;
+                    root_page.insert_ptr(&k, ptr, right)?;
                 }
             }
             None => {
                 // root page is full, do split !!!
                 let mut root_page = self.root_page.take().unwrap();
                 assert!(root_page.is_full() && root_page.page_type == PageType::LEAF);
-                let (k, ptr) = self.split_leaf_page(&mut root_page, key, value)?;
-                let mut new_root_page = self.new_page(PageType::INTERNAL)?;
+                let (k, ptr, right) = self.split_leaf_page(&mut root_page, key, value, true)?;
+                let mut new_root_page = self.new_internal_page()?;
                 new_root_page.set_item_count(1)?;
                 new_root_page.set_ptr_at(0, root_page.index)?;
+                new_root_page.set_count_at(0, root_page.total())?;
                 new_root_page.set_key_at(0, &k)?;
                 new_root_page.set_ptr_at(1, ptr)?;
+                new_root_page.set_count_at(1, right)?;
 
-                let meta_page = self.meta_page.as_mut().unwrap();
-                meta_page.set_root_index(new_root_page.index);
-
+                self.set_root_index(new_root_page.index);
                 self.root_page = Some(new_root_page);
             }
         }
@@ -198,131 +603,367 @@ impl<K, V> BTree<K, V>
         Ok(())
     }
 
-    pub fn get(&mut self, key: &K) -> Option<V> {
-        let mut p = self.root_page.as_ref().unwrap();
-        let mut pages = Vec::new();
-        loop {
-            // println!("{:?} {}", p.page_type, p.item_count());
-            match p.find(key) {
-                Some((i, pos)) => {
-                    match p.page_type {
-                        PageType::LEAF => {
-                            // println!("i: {}, pos: {:?}", i, pos);
-                            // println!("{:?}", p);
-                            return if pos == Pos::Current {
-                                p.value_at(i)
-                            } else {
-                                None
-                            }
-                        }
-                        PageType::INTERNAL => {
-                            match pos {
-                                Pos::Left => {
-                                    pages.push(Page::<K, V>::load(self.fd.clone(), p.ptr_at(i).unwrap()).unwrap());
-                                    p = &pages[pages.len() - 1];
-                                }
-                                _ => {
-                                    pages.push(Page::<K, V>::load(self.fd.clone(), p.ptr_at(i + 1).unwrap()).unwrap());
-                                    p = &pages[pages.len() - 1];
-                                }
-                            }
-                        }
-                        _ => {
-                            // impossible
-                            return None
-                        }
-                    }
-                },
-                None => {
-                    return None;
+    /// overwrites part of the stored encoding of `key`'s value in place, without decoding it;
+    /// returns false when the key is not in the tree
+    pub fn write_value_at(&mut self, key: &K, offset: usize, bytes: &[u8]) -> Result<bool, BTreeError> {
+        if self.read_only {
+            return Err(BTreeError::ReadOnly { path: self.path.clone() });
+        }
+        let written = if Page::<K, V>::spills(self.page_size()) {
+            self.patch_spilled(key, offset, bytes)?
+        } else {
+            self.with_leaf(key, |p| {
+                match p.find(key) {
+                    Some((i, Pos::Current)) => p.patch_value_at(i, offset, bytes).map(|_| true),
+                    _ => Ok(false)
                 }
+            })??
+        };
+        if written {
+            self.touch(Stat::LastModified);
+            self.finish_write()?;
+        }
+        Ok(written)
+    }
+
+    /// runs `f` on the leaf page that `key` belongs in, the page is written back afterwards
+    pub(crate) fn with_leaf<R>(&mut self, key: &K, f: impl FnOnce(&mut Page<K, V>) -> R) -> Result<R> {
+        match self.leaf_below_root(key)? {
+            Some(mut p) => Ok(f(&mut p)),
+            None => Ok(f(self.root_page.as_mut().unwrap()))
+        }
+    }
+
+    /// like `with_leaf` for reading only, which many threads sharing the tree can do at once
+    fn read_leaf<R>(&self, key: &K, f: impl FnOnce(&Page<K, V>) -> R) -> Result<R> {
+        match self.leaf_below_root(key)? {
+            Some(p) => Ok(f(&p)),
+            None => Ok(f(self.root_page.as_ref().unwrap()))
+        }
+    }
+
+    // None when the root is the only leaf
+    fn leaf_below_root(&self, key: &K) -> Result<Option<Page<K, V>>> {
+        self.descend(key, |_| {})
+    }
+
+    /// None as well when a page on the way to `key` is corrupted, which `try_get` tells apart
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.try_get(key).ok().flatten()
+    }
+
+    pub fn try_get(&self, key: &K) -> Result<Option<V>, BTreeError> {
+        Ok(self.read_leaf(key, |p| {
+            match p.find(key) {
+                Some((i, Pos::Current)) => p.value_at(i),
+                _ => None
             }
+        })?)
+    }
+
+    /// keeps up to `pages` recently used pages in memory, 64 by default. pages written while
+    /// cached reach the file when they are evicted or the tree syncs, 0 turns the cache off
+    pub fn set_cache_capacity(&mut self, pages: usize) -> Result<(), BTreeError> {
+        Ok(self.fd.lock().unwrap().set_cache_capacity(pages)?)
+    }
+
+    /// caps the file at `max_pages` pages, writes that would grow it further fail with
+    /// `BTreeError::QuotaExceeded`
+    pub fn set_max_pages(&mut self, max_pages: Option<u32>) {
+        self.max_pages = max_pages;
+    }
+
+    /// same as `set_max_pages`, in bytes
+    pub fn set_max_size(&mut self, max_size: Option<u64>) {
+        self.max_pages = max_size.map(|size| (size / self.page_size() as u64).min(u32::MAX as u64) as u32);
+    }
+
+    /// where full pages split from now on, as the percentage of their entries the left page
+    /// keeps: 50 splits them evenly, as suits keys coming in at random, while 90 leaves pages
+    /// fuller under keys coming in mostly in order. the choice is kept in the file, for its
+    /// named trees too
+    pub fn set_fill_factor(&mut self, percent: usize) -> Result<(), BTreeError> {
+        if self.read_only {
+            return Err(BTreeError::ReadOnly { path: self.path.clone() });
         }
+        Self::check_fill_factor(percent)?;
+        self.meta_page.as_mut().unwrap().set_fill_factor(percent);
+        self.sync()?;
+        Ok(self.finish_write()?)
+    }
+
+    /// see `set_fill_factor`
+    pub fn fill_factor(&self) -> usize {
+        self.meta_page.as_ref().unwrap().fill_factor()
+    }
+
+    pub(crate) fn check_fill_factor(percent: usize) -> Result<(), BTreeError> {
+        if !(1..=99).contains(&percent) {
+            return Err(anyhow!("a fill factor of {}%, not from 1% to 99%", percent).into());
+        }
+        Ok(())
+    }
+
+    fn reserve_pages(&self, count: u32) -> Result<()> {
+        if let Some(max_pages) = self.max_pages {
+            let meta_page = self.meta_page.as_ref().unwrap();
+            // spare pages and pages off the free list do not grow the file
+            let growth = count.saturating_sub(meta_page.free_count() + meta_page.spares().len() as u32);
+            if meta_page.total_pages() as u64 + growth as u64 > max_pages as u64 {
+                return Err(PageError::QuotaExceeded { max_pages }.into());
+            }
+        }
+        Ok(())
+    }
+
+    /// like `get`, but decodes the value into `out` so a caller looking up many keys can keep
+    /// reusing one allocation; returns false, leaving `out` alone, when the key is missing
+    pub fn get_into(&self, key: &K, out: &mut V) -> bool {
+        self.read_leaf(key, |p| {
+            match p.find(key) {
+                Some((i, Pos::Current)) => p.value_into(i, out).is_ok(),
+                _ => false
+            }
+        }).unwrap_or(false)
+    }
+
+    /// reads a projection `P` of the value stored under `key` without decoding the whole value
+    pub fn get_projected<P: DecodePartial<V>>(&self, key: &K) -> Option<P> {
+        self.read_leaf(key, |p| {
+            match p.find(key) {
+                Some((i, Pos::Current)) => P::decode_partial(p.value_bytes(i, &mut Vec::new()).ok()?).ok(),
+                _ => None
+            }
+        }).ok().flatten()
     }
 
+    /// hands out a spare page, or the first page of the free list, or grows the file when
+    /// both are empty. writing copy-on-write the free list is left for `finish_write` to take from
     fn new_page(&mut self, pt: PageType) -> Result<Page<K, V>> {
+        if let Some(spare) = self.meta_page.as_mut().unwrap().pop_spare() {
+            if let Some(cow) = self.cow.as_mut() {
+                cow.fresh.insert(spare);
+            }
+            // what a spare holds is left out of the digest, so there is nothing to roll back
+            return Page::<K, V>::new(self.fd.clone(), spare, pt);
+        }
+        let head = self.meta_page.as_ref().unwrap().free_head();
+        if head != 0 && self.cow.is_none() {
+            let mut page = Page::<K, V>::load(self.fd.clone(), head)?;
+            if page.page_type != PageType::FREE {
+                return Err(corrupted(head, format!("a {:?} page is on the free list", page.page_type)));
+            }
+            let meta_page = self.meta_page.as_mut().unwrap();
+            meta_page.set_free_list(page.next_free(), meta_page.free_count() - 1);
+            page.reset(pt);
+            return Ok(page);
+        }
+        self.reserve_pages(1)?;
+        let max_index = self.meta_page.as_ref().unwrap().total_pages();
+        self.make_room(max_index + 1)?;
         let meta_page = self.meta_page.as_mut().unwrap();
-        let max_index = meta_page.total_pages();
         meta_page.set_total_page(max_index + 1);
-        Ok(Page::<K, V>::new(self.fd.clone(), max_index, pt)?)
+        if let Some(cow) = self.cow.as_mut() {
+            cow.fresh.insert(max_index);
+        }
+        Page::<K, V>::new(self.fd.clone(), max_index, pt)
     }
 
-    fn split_leaf_page(&mut self, p: &mut Page<K, V>, key: &K, value: &V) -> Result<(K, u32)> {
-        assert_eq!(p.page_type, PageType::LEAF);
-        let mut new_page = self.new_page(PageType::LEAF)?;
-        let mut keys = Vec::new();
-        let mut values = Vec::new();
-        let mut inserted = false;
-        for i in 0..p.item_count() {
-            let k = p.key_at(i).unwrap();
-            if !inserted && k > *key {
-                keys.push(key.clone());
-                values.push(value.clone());
-                inserted = true;
-            }
-            keys.push(k);
-            values.push(p.value_at(i).unwrap())
-        }
-        if !inserted {
-            keys.push(key.clone());
-            values.push(value.clone());
-            // inserted = true;
+    /// a new internal page, keeping counts when the file's do
+    fn new_internal_page(&mut self) -> Result<Page<K, V>> {
+        let mut page = self.new_page(PageType::INTERNAL)?;
+        if self.meta_page.as_ref().unwrap().counted() {
+            page.keep_counts();
         }
-        let cut_i =  (keys.len() + 1) / 2;
-        p.set_item_count(cut_i)?;
-        new_page.set_item_count(keys.len() - cut_i)?;
+        Ok(page)
+    }
 
-        for i in 0..cut_i {
-            p.set_key_at(i, &keys[i])?;
-            p.set_value_at(i, &values[i])?;
+    /// counts `delta` more entries under the root and each internal page of `pages`, for the
+    /// child on the way down to `key`
+    fn count_along<'a>(&mut self, key: &K, pages: impl Iterator<Item = &'a mut Page<K, V>>, delta: i64) -> Result<()>
+        where K: 'a, V: 'a
+    {
+        let root = self.root_page.as_mut().unwrap();
+        if root.counted() {
+            root.add_count_at(root.child_slot(key), delta)?;
+        }
+        for p in pages.filter(|p| p.counted()) {
+            p.add_count_at(p.child_slot(key), delta)?;
         }
+        Ok(())
+    }
 
-        for i in cut_i..keys.len() {
-            new_page.set_key_at(i - cut_i, &keys[i])?;
-            new_page.set_value_at(i - cut_i, &values[i])?;
+    /// makes sure the file has room for `pages` pages, growing it by an extent when it runs out
+    /// rather than a page at a time. the quota caps how far ahead it grows
+    fn make_room(&mut self, pages: u32) -> Result<()> {
+        if pages <= self.meta_page.as_ref().unwrap().high_water() {
+            return Ok(());
+        }
+        let extent = (EXTENT_SIZE / self.page_size()).max(1) as u32;
+        let mut end = pages.div_ceil(extent).saturating_mul(extent);
+        if let Some(max_pages) = self.max_pages {
+            end = end.min(max_pages).max(pages);
         }
+        self.fd.lock().unwrap().preallocate(end)?;
+        self.meta_page.as_mut().unwrap().set_high_water(end);
+        Ok(())
+    }
 
-        Ok((keys[cut_i].clone(), new_page.index))
+    /// puts page `index` at the head of the free list, whatever it held is gone
+    pub(crate) fn free_page(&mut self, index: u32) -> Result<()> {
+        // a page lost past the end of the file has nothing on disk to load
+        let page = Page::<K, V>::load(self.fd.clone(), index)
+            .or_else(|_| Page::<K, V>::new(self.fd.clone(), index, PageType::FREE))?;
+        self.release(page)
     }
 
-    fn split_internal_page(&mut self, p: &mut Page<K, V>, key: &K, ptr: u32) -> Result<(K, u32)> {
-        assert_eq!(p.page_type, PageType::INTERNAL);
-        let mut new_page = self.new_page(PageType::INTERNAL)?;
-        let mut keys = Vec::new();
-        let mut ptrs = Vec::new();
-        let mut inserted = false;
-        ptrs.push(p.ptr_at(0).unwrap());
-        for i in 0..p.item_count() {
-            let k = p.key_at(i).unwrap();
-            if !inserted && k > *key {
-                keys.push(key.clone());
-                ptrs.push(ptr);
-                inserted = true;
+    /// like `free_page`, for a page already in memory. writing copy-on-write, a page the tree
+    /// on disk may still use is left alone until the meta page switches away from it
+    pub(crate) fn release(&mut self, page: Page<K, V>) -> Result<()> {
+        let cow = match self.cow.as_mut() {
+            Some(cow) => cow,
+            None => {
+                self.link_free(page);
+                return Ok(());
             }
-            keys.push(k);
-            ptrs.push(p.ptr_at(i + 1).unwrap());
+        };
+        let index = page.index;
+        page.discard();
+        self.fd.lock().unwrap().forget_page(index)?;
+        if !cow.fresh.remove(&index) {
+            cow.retired.push(index);
+        } else if !self.add_spare(index)? {
+            self.cow.as_mut().unwrap().overflow.push(index);
         }
+        Ok(())
+    }
+
+    pub(crate) fn link_free(&mut self, mut page: Page<K, V>) {
+        page.reset(PageType::FREE);
+        let meta_page = self.meta_page.as_mut().unwrap();
+        page.set_next_free(meta_page.free_head());
+        meta_page.set_free_list(page.index, meta_page.free_count() + 1);
+    }
 
-        if !inserted {
-            keys.push(key.clone());
-            ptrs.push(ptr);
-            // inserted = true;
+    /// splits a full leaf where the fill factor says. a key going in past the end of the
+    /// rightmost leaf, as keys put in in order do, starts a new leaf of its own and leaves the
+    /// old one full. returns the first key of the new leaf, its index and how many entries it holds
+    fn split_leaf_page(&mut self, p: &mut Page<K, V>, key: &K, value: &Slot<V>, rightmost: bool) -> Result<(K, u32, u64)> {
+        assert_eq!(p.page_type, PageType::LEAF);
+        self.bump_stat(Stat::Splits);
+        let mut new_page = self.new_page(PageType::LEAF)?;
+        let item_count = p.item_count();
+        // where the new key lands among the old ones
+        let ins = match p.find(key) {
+            Some((i, Pos::Left)) => i,
+            Some((i, _)) => i + 1,
+            None => 0
+        };
+        // the left page keeps the first cut_i entries counting the new one, so the old entries
+        // moving right start one slot earlier when the new key lands on the left
+        let cut_i = if rightmost && ins == item_count {
+            item_count
+        } else {
+            ((item_count + 1) * self.fill_factor()).div_ceil(100).clamp(1, item_count)
+        };
+        let from = if ins < cut_i { cut_i - 1 } else { cut_i };
+        new_page.set_item_count(item_count - from)?;
+        for i in from..item_count {
+            new_page.set_raw_key_at(i - from, p.leaf_key_at(i))?;
+            new_page.set_raw_value_at(i - from, p.raw_value_at(i))?;
+        }
+        p.set_item_count(from)?;
+        if ins < cut_i {
+            p.insert(key, value)?;
+        } else {
+            new_page.insert(key, value)?;
         }
+        event!(debug, page = p.index, new_page = new_page.index, moved = item_count - from; "split a leaf");
 
-        let up_i =  (keys.len() - 1) / 2;
-        p.set_item_count(up_i)?;
-        new_page.set_item_count(keys.len() - up_i - 1)?;
+        Ok((K::decode(new_page.leaf_key_at(0))?.0, new_page.index, new_page.total()))
+    }
 
-        for i in 0..up_i {
-            p.set_key_at(i, &keys[i])?;
-            p.set_ptr_at(i + 1, ptrs[i + 1])?;
+    /// the separators either side of the pointer to each page of `pages` in the page above it,
+    /// top down from the root's child on the way to `key`. None at the ends of the tree
+    fn fences(&self, key: &K, pages: &[Page<K, V>]) -> Vec<Fences> {
+        let mut fences = Vec::with_capacity(pages.len());
+        let (mut lo, mut hi) = (None, None);
+        let mut parent = self.root_page.as_ref().unwrap();
+        for p in pages {
+            let slot = match parent.find(key) {
+                Some((i, Pos::Left)) => i,
+                Some((i, _)) => i + 1,
+                None => 0
+            };
+            if slot > 0 {
+                lo = Some(parent.raw_key_at(slot - 1).into_owned());
+            }
+            if slot < parent.item_count() {
+                hi = Some(parent.raw_key_at(slot).into_owned());
+            }
+            fences.push((lo.clone(), hi.clone()));
+            parent = p;
         }
+        fences
+    }
+
+    /// splits a full internal page, `fences` being its separators in the page above. the two
+    /// halves keep as much of a prefix for their keys as the keys around them share. at the
+    /// right end of the tree a key going in past the last one leaves the old page all but full.
+    /// `count` entries are under `ptr`; returns the key going up, the new page's index and how
+    /// many entries are under it
+    fn split_internal_page(&mut self, p: &mut Page<K, V>, key: &K, ptr: u32, count: u64, fences: Fences) -> Result<(K, u32, u64)> {
+        assert_eq!(p.page_type, PageType::INTERNAL);
+        self.bump_stat(Stat::Splits);
+        let mut new_page = self.new_internal_page()?;
+        let item_count = p.item_count();
+        let ins = match p.find(key) {
+            Some((i, Pos::Left)) => i,
+            Some((i, _)) => i + 1,
+            None => 0
+        };
+        // counting the new key, the one at up_i moves up and everything after it goes right
+        let up_i = if fences.1.is_none() && ins == item_count {
+            item_count - 1
+        } else {
+            (item_count * self.fill_factor() / 100).clamp(1, item_count - 1)
+        };
+        let (up, from) = if ins < up_i {
+            (p.raw_key_at(up_i - 1).into_owned(), up_i)
+        } else if ins == up_i {
+            let mut buf = vec![0u8; K::bin_size()];
+            key.encode(&mut buf)?;
+            (buf, up_i)
+        } else {
+            (p.raw_key_at(up_i).into_owned(), up_i + 1)
+        };
+        // every key from one fence up to the other starts with what the two have in common
+        let kept = p.prefix().len();
+        let (lo, hi) = fences;
+        let left = lo.map_or(kept, |lo| kept.max(K::shared_prefix(&lo, &up)));
+        let right = hi.map_or(kept, |hi| kept.max(K::shared_prefix(&up, &hi)));
+        new_page.set_prefix(&up[..right])?;
 
-        new_page.set_ptr_at(0, ptrs[up_i + 1])?;
-        for i in (up_i + 1)..keys.len() {
-            new_page.set_key_at(i - up_i - 1, &keys[i])?;
-            new_page.set_ptr_at(i - up_i, ptrs[i + 1])?;
+        new_page.set_item_count(item_count - from)?;
+        let first = if ins == up_i { (ptr, count) } else { (p.ptr_at(from).unwrap(), p.count_at(from).unwrap_or_default()) };
+        new_page.set_ptr_at(0, first.0)?;
+        new_page.set_count_at(0, first.1)?;
+        for i in from..item_count {
+            new_page.set_raw_key_at(i - from, &p.raw_key_at(i))?;
+            new_page.set_ptr_at(i - from + 1, p.ptr_at(i + 1).unwrap())?;
+            new_page.set_count_at(i - from + 1, p.count_at(i + 1).unwrap_or_default())?;
+        }
+        if ins < up_i {
+            p.set_item_count(up_i - 1)?;
+            p.insert_ptr(key, ptr, count)?;
+        } else {
+            p.set_item_count(up_i)?;
+            if ins > up_i {
+                new_page.insert_ptr(key, ptr, count)?;
+            }
         }
-        Ok((keys[up_i].clone(), new_page.index))
+        p.set_prefix(&up[..left])?;
+        event!(debug, page = p.index, new_page = new_page.index, moved = item_count - from; "split an internal page");
+        Ok((K::decode(&up)?.0, new_page.index, new_page.total()))
     }
 }
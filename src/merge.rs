@@ -0,0 +1,95 @@
+use crate::batch::WriteBatch;
+use crate::build::Builder;
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::page::{PageType, Page, Stat};
+use crate::BTree;
+use crate::error::BTreeError;
+use anyhow::Result;
+use std::fmt::Debug;
+
+// how many resolved entries go to the tree in one `write_batch`, each batch syncing once
+const MERGE_CHUNK: usize = 4096;
+
+/// merges a conflicting `(key, mine, theirs)` into the value to keep
+pub type Resolver<K, V> = Box<dyn Fn(&K, &V, &V) -> V>;
+
+/// decides which value survives when both trees of a merge hold the same key
+pub enum Conflict<K, V> {
+    /// keep the value already in this tree
+    KeepMine,
+    /// overwrite with the value from the other tree
+    KeepTheirs,
+    /// compute the surviving value from `(key, mine, theirs)`
+    Resolve(Resolver<K, V>),
+}
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    /// streams every entry of `other` in key order into this tree, a batch of them at a time,
+    /// returning how many entries were written. an empty tree takes them laid out the way
+    /// `bulk_load` lays them out instead
+    pub fn merge_from(&mut self, other: &BTree<K, V>, conflict: Conflict<K, V>) -> Result<usize, BTreeError> {
+        if self.read_only {
+            return Err(BTreeError::ReadOnly { path: self.path.clone() });
+        }
+        if self.is_bare() {
+            return Ok(self.load_from(other)?);
+        }
+        let mut written = 0;
+        let mut batch = WriteBatch::new();
+        for (k, theirs) in other.iter() {
+            let v = match self.get(&k) {
+                Some(mine) => {
+                    match &conflict {
+                        Conflict::KeepMine => continue,
+                        Conflict::KeepTheirs => theirs,
+                        Conflict::Resolve(f) => f(&k, &mine, &theirs),
+                    }
+                }
+                None => theirs
+            };
+            batch.set(&k, &v);
+            written += 1;
+            if batch.len() == MERGE_CHUNK {
+                self.write_batch(std::mem::take(&mut batch))?;
+            }
+        }
+        self.write_batch(batch)?;
+        Ok(written)
+    }
+
+    // nothing in the tree yet and nothing in the way of laying pages out past the end of the
+    // file: copy-on-write keeps track of the pages it writes, and a quota of how many there are
+    fn is_bare(&self) -> bool {
+        let root = self.root_page.as_ref().unwrap();
+        root.page_type == PageType::LEAF && root.item_count() == 0
+            && self.cow.is_none() && self.max_pages.is_none()
+    }
+
+    // builds the tree of `other`'s entries in pages past the ones in use, then has the meta
+    // page point at it in place of the empty root leaf
+    fn load_from(&mut self, other: &BTree<K, V>) -> Result<usize> {
+        let meta_page = self.meta_page.as_ref().unwrap();
+        let mut builder = Builder::<K, V>::over(self.fd.clone(), meta_page.total_pages(), meta_page.counted());
+        for (k, v) in other.iter() {
+            builder.push(&k, &v)?;
+        }
+        let (root_index, end, count) = builder.finish_tree()?;
+        drop(builder);
+        self.make_room(end)?;
+        self.meta_page.as_mut().unwrap().set_total_page(end);
+        let root = Page::<K, V>::load_node(self.fd.clone(), root_index)?;
+        let empty = self.root_page.replace(root).unwrap();
+        self.set_root_index(root_index);
+        self.release(empty)?;
+        let meta_page = self.meta_page.as_mut().unwrap();
+        meta_page.set_stat(Stat::Inserts, meta_page.stat(Stat::Inserts) + count as u64);
+        self.set_entries(count as u64);
+        self.touch(Stat::LastModified);
+        self.sync()?;
+        Ok(count)
+    }
+}
@@ -0,0 +1,32 @@
+use crate::BTree;
+
+/// counters of the work a tree did since it was opened or the counters were last reset.
+/// unlike `Stats` they live in memory only, for telling where the time of a slow workload goes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// pages read from the file, verifying a checksum included
+    pub page_reads: u64,
+    /// pages written to the file, a run of adjacent pages written together counting each
+    pub page_writes: u64,
+    /// page reads the page cache answered
+    pub cache_hits: u64,
+    /// page reads that went to the file
+    pub cache_misses: u64,
+    /// leaf and internal page splits
+    pub splits: u64,
+    /// syncs of the tree file and its journal
+    pub fsyncs: u64,
+}
+
+// needs no bounds on the key and value types, reading nothing but the pager
+impl<K, V> BTree<K, V> {
+    /// a snapshot of the counters, which keep counting on from there
+    pub fn metrics(&self) -> Metrics {
+        self.fd.lock().unwrap().metrics()
+    }
+
+    /// sets every counter back to 0
+    pub fn reset_metrics(&self) {
+        self.fd.lock().unwrap().reset_metrics();
+    }
+}
@@ -0,0 +1,170 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::durability::Durability;
+use crate::error::BTreeError;
+use crate::pager::{Pager, check_page_size};
+use crate::{BTree, Mode, DEFAULT_CACHE_PAGES, PAGE_SIZE};
+use anyhow::{anyhow, Result};
+use std::fmt::Debug;
+use std::fs::File;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+/// everything a tree can be opened with, set one by one and checked together by `open`.
+/// left alone, an option has the value the plain constructors give it
+pub struct TreeBuilder<K, V> {
+    path: PathBuf,
+    page_size: Option<usize>,
+    cache_pages: usize,
+    durability: Durability,
+    read_only: bool,
+    create_if_missing: bool,
+    copy_on_write: bool,
+    max_size: Option<u64>,
+    fill_factor: Option<usize>,
+    double_write: bool,
+    #[cfg(feature = "direct-io")]
+    direct_io: bool,
+    _types: PhantomData<(K, V)>,
+}
+
+impl<K, V> TreeBuilder<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    /// the page size of a file created, a power of two from `MIN_PAGE_SIZE` to `MAX_PAGE_SIZE`.
+    /// the page size lives in the file, an existing one of another page size fails to open and
+    /// is left as it is
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// see `BTree::set_cache_capacity`
+    pub fn cache_pages(mut self, pages: usize) -> Self {
+        self.cache_pages = pages;
+        self
+    }
+
+    /// see `BTree::set_durability`
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// opens the file as `BTree::open_read_only` does, which creates nothing
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// creates an empty tree when there is no file, off by default
+    pub fn create_if_missing(mut self, create: bool) -> Self {
+        self.create_if_missing = create;
+        self
+    }
+
+    /// see `BTree::set_copy_on_write`
+    pub fn copy_on_write(mut self, on: bool) -> Self {
+        self.copy_on_write = on;
+        self
+    }
+
+    /// see `BTree::set_max_size`
+    pub fn max_size(mut self, max_size: Option<u64>) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// see `BTree::set_fill_factor`, left alone the file keeps the one it has
+    pub fn fill_factor(mut self, percent: usize) -> Self {
+        self.fill_factor = Some(percent);
+        self
+    }
+
+    /// writes every page to a double-write buffer next to the file first, `<path>-dwb`, and only
+    /// in place once the buffer is on disk, so a power loss tearing a page halfway through its
+    /// write leaves a whole image of it to be written again on the next open. every write
+    /// syncs twice; off by default
+    pub fn double_write(mut self, on: bool) -> Self {
+        self.double_write = on;
+        self
+    }
+
+    /// reads and writes pages past the os page cache, for trees whose own cache, see
+    /// `cache_pages`, is all the caching they want. linux only, with pages of a multiple of
+    /// 4096 bytes; off by default
+    #[cfg(feature = "direct-io")]
+    pub fn direct_io(mut self, on: bool) -> Self {
+        self.direct_io = on;
+        self
+    }
+
+    pub fn open(self) -> Result<BTree<K, V>, BTreeError> {
+        if let Some(page_size) = self.page_size {
+            check_page_size(page_size)?;
+        }
+        if let Some(percent) = self.fill_factor {
+            BTree::<K, V>::check_fill_factor(percent)?;
+        }
+        if self.read_only && (self.create_if_missing || self.copy_on_write || self.fill_factor.is_some() || self.double_write) {
+            return Err(anyhow!("a tree opened read only can neither be created, written copy-on-write or through a double-write buffer nor given a fill factor").into());
+        }
+        // checked against the file as it is, before opening it can create or write anything
+        if let Some(page_size) = self.page_size {
+            if let Ok(file) = File::open(&self.path) {
+                if file.metadata()?.len() != 0 {
+                    let stored = Pager::stored_page_size(&file)?;
+                    if stored != page_size {
+                        return Err(anyhow!("{} has pages of {} bytes, not {}", self.path.display(), stored, page_size).into());
+                    }
+                }
+            }
+        }
+        let mut tree = if self.read_only {
+            BTree::open_read_only(&self.path)?
+        } else {
+            let mode = if self.create_if_missing { Mode::OpenOrCreate } else { Mode::Open };
+            BTree::open_with(&self.path, self.page_size.unwrap_or(PAGE_SIZE), mode, self.double_write)?
+        };
+        #[cfg(feature = "direct-io")]
+        if self.direct_io {
+            tree.fd.lock().unwrap().open_direct(&self.path, !self.read_only)?;
+        }
+        tree.set_cache_capacity(self.cache_pages)?;
+        tree.set_durability(self.durability);
+        tree.set_max_size(self.max_size);
+        if let Some(percent) = self.fill_factor {
+            tree.set_fill_factor(percent)?;
+        }
+        if self.copy_on_write {
+            tree.set_copy_on_write(true)?;
+        }
+        Ok(tree)
+    }
+}
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    /// a `TreeBuilder` for the file at `path`, to open it with options the constructors take none of
+    pub fn builder<P: AsRef<Path>>(path: P) -> TreeBuilder<K, V> {
+        TreeBuilder {
+            path: path.as_ref().to_path_buf(),
+            page_size: None,
+            cache_pages: DEFAULT_CACHE_PAGES,
+            durability: Durability::default(),
+            read_only: false,
+            create_if_missing: false,
+            copy_on_write: false,
+            max_size: None,
+            fill_factor: None,
+            double_write: false,
+            #[cfg(feature = "direct-io")]
+            direct_io: false,
+            _types: PhantomData,
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use anyhow::Result;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::fmt::{Debug, Formatter};
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+/// an order to sort keys in other than their own `PartialOrd`. the name is recorded in the
+/// meta page of the files it builds, so it has to stay the same for as long as they are kept
+pub trait KeyOrder<K> {
+    const NAME: &'static str;
+
+    fn compare(a: &K, b: &K) -> Ordering;
+}
+
+/// largest key first
+pub struct Descending;
+
+impl<K: PartialOrd> KeyOrder<K> for Descending {
+    const NAME: &'static str = "descending";
+
+    fn compare(a: &K, b: &K) -> Ordering {
+        b.partial_cmp(a).unwrap_or(Ordering::Equal)
+    }
+}
+
+/// strings compared by their lowercase chars, so keys differing only in case are the same key
+pub struct CaseInsensitive;
+
+impl<K: AsRef<str>> KeyOrder<K> for CaseInsensitive {
+    const NAME: &'static str = "case-insensitive";
+
+    fn compare(a: &K, b: &K) -> Ordering {
+        let lower = |k: &K| k.as_ref().chars().flat_map(char::to_lowercase).collect::<Vec<_>>();
+        lower(a).cmp(&lower(b))
+    }
+}
+
+/// a key sorted by `O` instead of its own order, stored the way `K` is. two keys are equal
+/// when `O` puts neither before the other
+pub struct Collated<K, O>(pub K, PhantomData<O>);
+
+impl<K, O> Collated<K, O> {
+    pub fn new(key: K) -> Self {
+        Collated(key, PhantomData)
+    }
+
+    pub fn into_inner(self) -> K {
+        self.0
+    }
+}
+
+impl<K, O> From<K> for Collated<K, O> {
+    fn from(key: K) -> Self {
+        Self::new(key)
+    }
+}
+
+impl<K, O> Deref for Collated<K, O> {
+    type Target = K;
+
+    fn deref(&self) -> &K {
+        &self.0
+    }
+}
+
+impl<K: Clone, O> Clone for Collated<K, O> {
+    fn clone(&self) -> Self {
+        Self::new(self.0.clone())
+    }
+}
+
+impl<K: Debug, O> Debug for Collated<K, O> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl<K, O: KeyOrder<K>> PartialEq for Collated<K, O> {
+    fn eq(&self, other: &Self) -> bool {
+        O::compare(&self.0, &other.0) == Ordering::Equal
+    }
+}
+
+impl<K, O: KeyOrder<K>> PartialOrd for Collated<K, O> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(O::compare(&self.0, &other.0))
+    }
+}
+
+impl<K: BinSizer, O: KeyOrder<K>> BinSizer for Collated<K, O> {
+    #[inline]
+    fn bin_size() -> usize {
+        K::bin_size()
+    }
+
+    fn key_order() -> Cow<'static, str> {
+        Cow::Borrowed(O::NAME)
+    }
+}
+
+impl<K: Encodable, O> Encodable for Collated<K, O> {
+    fn encode(&self, buf: &mut [u8]) -> Result<usize> {
+        self.0.encode(buf)
+    }
+}
+
+impl<K: Decodable, O> Decodable for Collated<K, O> {
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        let (key, size) = K::decode(buf)?;
+        Ok((Self::new(key), size))
+    }
+
+    fn validate(buf: &[u8]) -> Result<()> {
+        K::validate(buf)
+    }
+
+    fn decode_into(&mut self, buf: &[u8]) -> Result<usize> {
+        self.0.decode_into(buf)
+    }
+}
@@ -0,0 +1,104 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::page::{Page, PageType, Pos, CHAIN_HEADER, read_chain, corrupted};
+use crate::BTree;
+use anyhow::{anyhow, Result};
+use std::fmt::Debug;
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    /// writes `value` out to a chain of overflow pages when values are too big for a leaf,
+    /// returning the first page of the chain, None when values stay in the leaves
+    pub(crate) fn spill(&mut self, value: &V) -> Result<Option<u32>> {
+        if !Page::<K, V>::spills(self.page_size()) {
+            return Ok(None);
+        }
+        let mut bytes = vec![0u8; V::bin_size()];
+        value.encode(&mut bytes)?;
+        self.spill_bytes(&bytes).map(Some)
+    }
+
+    fn spill_bytes(&mut self, bytes: &[u8]) -> Result<u32> {
+        // a chain cut short by the quota would be left behind
+        self.reserve_pages(Page::<K, V>::chain_len(self.page_size()) as u32)?;
+        write_chain(bytes, self.page_size(), || self.new_page(PageType::OVERFLOW))
+    }
+
+    /// frees the overflow chain starting at page `first`
+    pub(crate) fn free_chain(&mut self, first: u32) -> Result<()> {
+        for index in self.chain_pages(first)? {
+            self.free_page(index)?;
+        }
+        Ok(())
+    }
+
+    /// the pages of the overflow chain starting at page `first`, in order
+    pub(crate) fn chain_pages(&self, first: u32) -> Result<Vec<u32>> {
+        let len = Page::<K, V>::chain_len(self.page_size());
+        let mut pages = Vec::with_capacity(len);
+        let mut index = first;
+        while pages.len() < len {
+            let page = Page::<K, V>::load(self.fd.clone(), index)?;
+            if page.page_type != PageType::OVERFLOW {
+                return Err(corrupted(index, format!("a {:?} page in the overflow chain from page {}", page.page_type, first)));
+            }
+            pages.push(index);
+            index = page.chain_next();
+            if index == 0 && pages.len() < len {
+                return Err(corrupted(first, format!("an overflow chain of {} pages, values take {}", pages.len(), len)));
+            }
+        }
+        Ok(pages)
+    }
+
+    /// `write_value_at` for values that spill. chains are never written over, so the patched
+    /// value goes out to a new one and the old one is freed
+    pub(crate) fn patch_spilled(&mut self, key: &K, offset: usize, bytes: &[u8]) -> Result<bool> {
+        if offset + bytes.len() > V::bin_size() {
+            return Err(anyhow!("patch of {} bytes at {} overruns a {} byte value", bytes.len(), offset, V::bin_size()))
+        }
+        let old = self.read_leaf(key, |p| {
+            match p.find(key) {
+                Some((i, Pos::Current)) => p.spilled_at(i),
+                _ => None
+            }
+        })?;
+        let old = match old {
+            Some(old) => old,
+            None => return Ok(false)
+        };
+        let mut value = Vec::new();
+        read_chain::<V>(&self.fd, old, &mut value)?;
+        value[offset..offset + bytes.len()].copy_from_slice(bytes);
+        let new = self.spill_bytes(&value)?;
+        self.with_leaf(key, |p| {
+            match p.find(key) {
+                Some((i, Pos::Current)) => p.set_raw_value_at(i, &new.to_be_bytes()),
+                _ => Err(anyhow!("{:?} went missing while its value was patched", key))
+            }
+        })??;
+        self.free_chain(old)?;
+        Ok(true)
+    }
+}
+
+/// writes the encoded value `bytes` out to overflow pages taken from `alloc`, returning the
+/// first of them
+pub(crate) fn write_chain<K, V>(bytes: &[u8], page_size: usize, mut alloc: impl FnMut() -> Result<Page<K, V>>) -> Result<u32>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    let chunks: Vec<&[u8]> = bytes.chunks(page_size - CHAIN_HEADER).collect();
+    let mut pages = Vec::with_capacity(chunks.len());
+    for _ in 0..chunks.len() {
+        pages.push(alloc()?);
+    }
+    for (j, chunk) in chunks.iter().enumerate() {
+        let next = pages.get(j + 1).map(|p| p.index).unwrap_or(0);
+        pages[j].set_chain(next, chunk);
+    }
+    Ok(pages[0].index)
+}
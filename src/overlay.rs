@@ -0,0 +1,140 @@
+use crate::byte::{Encodable, Decodable, BinSizer, check_len};
+use crate::BTree;
+use crate::error::BTreeError;
+use anyhow::Result;
+use std::fmt::Debug;
+use std::iter::Peekable;
+use std::ops::RangeBounds;
+use std::path::Path;
+
+/// what the writable top layer records for a key: a new value, or a tombstone hiding the bases
+#[derive(Debug, Clone)]
+pub(crate) enum Patch<V> {
+    Put(V),
+    Tombstone,
+}
+
+impl<V: BinSizer> BinSizer for Patch<V> {
+    #[inline]
+    fn bin_size() -> usize {
+        1 + V::bin_size()
+    }
+}
+
+impl<V: Encodable + BinSizer> Encodable for Patch<V> {
+    fn encode(&self, buf: &mut [u8]) -> Result<usize> {
+        check_len(buf, Self::bin_size())?;
+        match self {
+            Patch::Put(v) => {
+                buf[0] = 1;
+                v.encode(&mut buf[1..])?;
+            }
+            Patch::Tombstone => {
+                buf[0] = 0;
+            }
+        }
+        Ok(Self::bin_size())
+    }
+}
+
+impl<V: Decodable + BinSizer> Decodable for Patch<V> {
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        check_len(buf, Self::bin_size())?;
+        if buf[0] == 0 {
+            Ok((Patch::Tombstone, Self::bin_size()))
+        } else {
+            Ok((Patch::Put(V::decode(&buf[1..])?.0), Self::bin_size()))
+        }
+    }
+}
+
+/// a writable tree stacked over read-only base tree files: reads consult the top first,
+/// then each base in order, and keys removed through the overlay stay hidden in every base
+pub struct Overlay<K, V> {
+    top: BTree<K, Patch<V>>,
+    bases: Vec<BTree<K, V>>,
+}
+
+impl<K, V> Overlay<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    /// `bases` are listed top-down, an earlier base shadows a later one
+    pub fn new<P: AsRef<Path>, B: AsRef<Path>>(top: P, bases: &[B]) -> Result<Self, BTreeError> {
+        Ok(Overlay {
+            top: BTree::open_or_create(top)?,
+            bases: bases.iter().map(BTree::open_read_only).collect::<Result<_, BTreeError>>()?,
+        })
+    }
+
+    pub fn get(&self, key: &K) -> Option<V> {
+        match self.top.get(key) {
+            Some(Patch::Put(v)) => return Some(v),
+            Some(Patch::Tombstone) => return None,
+            None => {}
+        }
+        self.bases.iter().find_map(|base| base.get(key))
+    }
+
+    pub fn set(&mut self, key: &K, value: &V) -> Result<(), BTreeError> {
+        self.top.set(key, &Patch::Put(value.clone()))
+    }
+
+    /// masks the key in every layer below
+    pub fn remove(&mut self, key: &K) -> Result<(), BTreeError> {
+        self.top.set(key, &Patch::Tombstone)
+    }
+
+    pub fn iter(&self) -> OverlayIter<'_, K, V> {
+        self.range(..)
+    }
+
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> OverlayIter<'_, K, V> {
+        let bounds = (range.start_bound().cloned(), range.end_bound().cloned());
+        let mut layers: Vec<Layer<'_, K, V>> = Vec::with_capacity(self.bases.len() + 1);
+        layers.push((Box::new(self.top.range(bounds.clone())) as Box<dyn Iterator<Item = _>>).peekable());
+        for base in self.bases.iter() {
+            layers.push((Box::new(base.range(bounds.clone()).map(|(k, v)| (k, Patch::Put(v)))) as Box<dyn Iterator<Item = _>>).peekable());
+        }
+        OverlayIter { layers }
+    }
+}
+
+type Layer<'a, K, V> = Peekable<Box<dyn Iterator<Item = (K, Patch<V>)> + 'a>>;
+
+/// merges the layers of an overlay in key order, upper layers winning on equal keys
+pub struct OverlayIter<'a, K, V> {
+    layers: Vec<Layer<'a, K, V>>,
+}
+
+impl<'a, K, V> Iterator for OverlayIter<'a, K, V>
+    where
+        K: PartialOrd
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // the first layer holding the smallest head key is the one that wins
+            let heads: Vec<Option<&K>> = self.layers.iter_mut().map(|l| l.peek().map(|(k, _)| k)).collect();
+            let mut winner: Option<usize> = None;
+            for (i, head) in heads.iter().enumerate() {
+                if let Some(k) = head {
+                    if winner.is_none_or(|w| *k < heads[w].unwrap()) {
+                        winner = Some(i);
+                    }
+                }
+            }
+            let (k, patch) = self.layers[winner?].next().unwrap();
+            for layer in self.layers.iter_mut() {
+                while layer.peek().is_some_and(|(other, _)| *other == k) {
+                    layer.next();
+                }
+            }
+            if let Patch::Put(v) = patch {
+                return Some((k, v));
+            }
+        }
+    }
+}
@@ -1,45 +1,240 @@
-use std::fs::File;
 use anyhow::{Result, anyhow};
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::borrow::{BorrowMut, Borrow};
+use std::borrow::{BorrowMut, Borrow, Cow};
+use std::cmp::Ordering;
 use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::pager::{Pager, page_crc};
 use std::marker::PhantomData;
 use thiserror::Error;
 use std::fmt::{Debug, Formatter};
-use std::cell::RefCell;
-use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 
+/// the page size of trees created without picking one
 pub const PAGE_SIZE: usize = 4096;
+pub const MIN_PAGE_SIZE: usize = 512;
+pub const MAX_PAGE_SIZE: usize = 65536;
+// where the meta page records the page size, right after the stats
+pub(crate) const PAGE_SIZE_OFFSET: usize = 64;
+// where the meta page records the key and value sizes the file was built with
+const LAYOUT_OFFSET: usize = 68;
+// where the meta page keeps the first page of the free list and how many pages are on it
+const FREE_HEAD_OFFSET: usize = 76;
+const FREE_COUNT_OFFSET: usize = 80;
+// where the meta page lists spare pages: nothing points at them and whatever they hold is
+// garbage, left out of the digest, so a write can go to one without touching anything the
+// file's tree still uses
+const SPARE_COUNT_OFFSET: usize = 84;
+const SPARES_OFFSET: usize = 128;
+// where the meta page records that every page of the file is written with a checksum
+const CHECKSUMS_OFFSET: usize = 88;
+// where the meta page records the codec pages get compressed with as they are written, 0 for none
+#[cfg(feature = "lz4")]
+const COMPRESSION_OFFSET: usize = 92;
+// where the meta page records the magic number telling tree files from anything else, and
+// the version of the format they are written in. files from before have zeros there
+const MAGIC_OFFSET: usize = 96;
+pub(crate) const VERSION_OFFSET: usize = 104;
+const MAGIC: [u8; 8] = *b"BTREEDB\0";
+// where the meta page records the percentage of a full page's entries a split keeps on the
+// left, 0 for an even split
+const FILL_FACTOR_OFFSET: usize = 108;
+// where the meta page records a crc of the name of the order keys sort in, 0 for the
+// natural order
+const KEY_ORDER_OFFSET: usize = 120;
+// where the meta page records the page listing the named trees of the file, 0 without any
+const CATALOG_OFFSET: usize = 124;
+// a catalog entry: the name padded with zeros, the root of the named tree and its entry count
+pub(crate) const MAX_TREE_NAME: usize = 32;
+const CATALOG_ENTRY: usize = MAX_TREE_NAME + 12;
+// where the meta page records how many pages the file has room for, grown ahead of the pages
+// in use an extent at a time. 0 in files never grown so
+const HIGH_WATER_OFFSET: usize = SPARES_OFFSET + MAX_SPARES * 4;
+// where the meta page records how many times it was written, in files keeping a second meta
+// page in page 1 and writing the two in turn, so the newer one is whichever counts more.
+// 0 in files with just the one
+pub(crate) const EPOCH_OFFSET: usize = HIGH_WATER_OFFSET + 4;
+// where the meta page records that the file's internal pages keep counts, see `COUNTS_FLAG`.
+// 0 in files from before and in those with keys too big to leave room for them
+const COUNTS_OFFSET: usize = EPOCH_OFFSET + 8;
+/// the page holding the second meta page of files keeping two
+pub(crate) const SECOND_META: u32 = 1;
+/// the newest file format this build reads, and the one it writes. since version 2 the meta
+/// page keeps count of the entries, since version 3 it may point at a catalog of named trees,
+/// since version 4 internal pages may keep the prefix their keys share once, since version 5
+/// the file may hold pages of zeros past the ones in use, since version 6 files get created
+/// with a second meta page, since version 7 internal pages may keep count of the entries
+/// under each child
+pub const FORMAT_VERSION: u32 = 7;
+pub(crate) const MAX_SPARES: usize = 64;
+/// the largest key a tree takes, smaller page sizes taking no more than fit two to an
+/// internal page
 pub const MAX_KEY_SIZE: usize = 128;
+/// the largest value a tree takes, read and written whole. on pages too small for a leaf to
+/// hold two of them, values go to overflow pages
 pub const MAX_VALUE_SIZE: usize = 1024;
 const PTR_SIZE: usize = 4;
+// an overflow page holds the index of the next page of its chain, 0 on the last, then data
+pub(crate) const CHAIN_HEADER: usize = 8;
+// set in the type tag of a page carrying a checksum of its image in the three header bytes
+// after the tag, the most the header has spare without moving every entry of older files
+const CHECKSUM_FLAG: u8 = 0x10;
+// set on the tag of an internal page keeping the prefix its keys share once, its length after
+// the item count and the prefix itself after that, the keys losing it from their slots
+const PREFIX_FLAG: u8 = 0x80;
+const PREFIX_POS: usize = 10;
+// set on the tag of an internal page keeping the count of the entries under each child right
+// after the pointer to it, so the position of a key among all of them is found on the way down
+const COUNTS_FLAG: u8 = 0x40;
+const COUNT_SIZE: usize = 8;
 
 #[derive(Error, Debug)]
 pub enum PageError {
     #[error("page is full, need split")]
-    Full
+    Full,
+    #[error("quota of {max_pages} pages exceeded")]
+    QuotaExceeded { max_pages: u32 },
+    #[error("the file holds {stored_key} byte keys and {stored_value} byte values, the tree types have {key} and {value}")]
+    LayoutMismatch { stored_key: usize, stored_value: usize, key: usize, value: usize },
+    #[error("keys under page {index} are out of order, the key type no longer sorts the way the file was built")]
+    OrderMismatch { index: u32 },
+    #[error("the file's keys sort in another order than {order}")]
+    KeyOrderMismatch { order: String },
+    #[error("page {index} is corrupted: {reason}")]
+    Corrupted { index: u32, reason: String },
+    #[error("{} is already open for writing in this process", path.display())]
+    AlreadyOpen { path: std::path::PathBuf },
+    #[error("not a btree file, the first page does not start a tree")]
+    NotATree,
+    #[error("file format version {version} is newer than {supported}, the newest this build reads")]
+    UnsupportedVersion { version: u32, supported: u32 },
+}
+
+pub(crate) fn key_order_crc<K: BinSizer>() -> u32 {
+    match K::key_order().as_ref() {
+        "" => 0,
+        name => crc32fast::hash(name.as_bytes())
+    }
+}
+
+pub(crate) fn corrupted(index: u32, reason: String) -> anyhow::Error {
+    PageError::Corrupted { index, reason }.into()
+}
+
+/// fails for the start of a meta page written by something other than a tree, or by a newer
+/// format than this build reads. `header` runs at least through the format version
+pub(crate) fn check_format(header: &[u8]) -> Result<()> {
+    let magic = &header[MAGIC_OFFSET..VERSION_OFFSET];
+    if magic == [0; 8] {
+        return Ok(());
+    }
+    if magic != MAGIC || header[0] & !CHECKSUM_FLAG != 0x01 {
+        return Err(PageError::NotATree.into());
+    }
+    let version = u32::decode(&header[VERSION_OFFSET..])?.0;
+    if version > FORMAT_VERSION {
+        return Err(PageError::UnsupportedVersion { version, supported: FORMAT_VERSION }.into());
+    }
+    Ok(())
+}
+
+/// the epoch of an image of the meta page read from either of its places, None unless the
+/// image is whole: a meta page carrying a checksum that matches it. a torn write fails that
+pub(crate) fn meta_epoch(buf: &[u8]) -> Option<u64> {
+    if buf[0] != 0x01 | CHECKSUM_FLAG || check_checksum(0, buf, true).is_err() {
+        return None;
+    }
+    Some(u64::decode(&buf[EPOCH_OFFSET..]).ok()?.0)
+}
+
+/// stamps an image of the meta page about to be written with `epoch`, sealing it again
+pub(crate) fn stamp_epoch(buf: &mut [u8], epoch: u64) {
+    epoch.encode(&mut buf[EPOCH_OFFSET..]).unwrap();
+    seal(0, buf);
+}
+
+// what a child takes up in an internal page, `counted` if it keeps counts
+fn ptr_slot(counted: bool) -> usize {
+    if counted { PTR_SIZE + COUNT_SIZE } else { PTR_SIZE }
+}
+
+/// the largest key pages of `page_size` take, internal pages having to hold two of them at
+/// least once they leave the counts out
+pub(crate) fn max_key_size(page_size: usize) -> usize {
+    let ptr_size = ptr_slot(false);
+    MAX_KEY_SIZE.min((page_size - 8 - ptr_size) / 2 - ptr_size)
 }
 
 pub(crate) struct Page<K, V>
 {
     pub index: u32,
-    buf: [u8; PAGE_SIZE],
+    // drawn from and handed back to the pager's pool, empty until the page is set up
+    buf: Box<[u8]>,
     pub page_type: PageType,
     keys_pos: usize,
+    // what a key takes up in its slot, less the prefix an internal page keeps for all of them
+    key_size: usize,
     values_pos: usize,
     ptrs_pos: usize,
+    // what a child takes up in an internal page, the pointer to it and the count kept for it
+    ptr_size: usize,
     max_item_count: usize,
+    // what a value takes up in a leaf, the value itself or a pointer to where it spilled
+    value_size: usize,
     dirty: bool,
-    fd: Option<Rc<RefCell<File>>>,
+    // crc of the image this page was loaded from, what the file digest currently accounts for
+    disk_crc: u32,
+    fd: Option<Arc<Mutex<Pager>>>,
+    // a copy never written back, the pager only reading the overflow pages it points at
+    detached: bool,
     _k: PhantomData<K>,
     _v: PhantomData<V>,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, PartialOrd, PartialEq)]
 pub(crate) enum PageType {
     META,
     INTERNAL,
     LEAF,
+    // unused, waiting on the free list to be handed out again
+    FREE,
+    // part of a value too big for a leaf
+    OVERFLOW,
+    // the names and roots of the named trees kept in the file besides its own
+    CATALOG,
+}
+
+/// what goes in the value slot of a leaf entry
+pub(crate) enum Slot<'a, V> {
+    Value(&'a V),
+    // the first page of the overflow chain a value spilled to
+    Spilled(u32),
+}
+
+/// lifetime counters kept in the meta page
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Stat {
+    Inserts,
+    Overwrites,
+    Deletes,
+    Splits,
+    LastCompaction,
+    LastModified,
+    // not a counter, how many entries the tree holds
+    Entries,
+}
+
+impl Stat {
+    fn offset(self) -> usize {
+        match self {
+            Stat::Inserts => 16,
+            Stat::Overwrites => 24,
+            Stat::Deletes => 32,
+            Stat::Splits => 40,
+            Stat::LastCompaction => 48,
+            Stat::LastModified => 56,
+            Stat::Entries => 112,
+        }
+    }
 }
 
 #[derive(Debug, PartialOrd, PartialEq)]
@@ -53,14 +248,19 @@ impl<K, V> Default for Page<K, V> {
     fn default() -> Self {
         Page::<K, V> {
             index: 0,
-            buf: [0; PAGE_SIZE],
+            buf: Box::default(),
             page_type: PageType::LEAF,
             keys_pos: 0,
+            key_size: 0,
             values_pos: 0,
             ptrs_pos: 0,
+            ptr_size: PTR_SIZE,
             max_item_count: 0,
+            value_size: 0,
             dirty: false,
+            disk_crc: 0,
             fd: None,
+            detached: false,
             _k: PhantomData,
             _v: PhantomData,
         }
@@ -68,99 +268,746 @@ impl<K, V> Default for Page<K, V> {
 }
 
 impl<K, V> Page<K, V> where
-    K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
-    V: Encodable + Decodable + BinSizer + Debug + Clone
+    K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+    V: Encodable + Decodable + BinSizer + Debug
 {
-    pub fn new(fd: Rc<RefCell<File>>, index: u32, pt: PageType) -> Result<Self> {
+    pub fn new(fd: Arc<Mutex<Pager>>, index: u32, pt: PageType) -> Result<Self> {
+        let mut page = Self::default();
+        page.buf = fd.lock().unwrap().take_buf();
+        page.index = index;
+        page.fd = Some(fd);
+        page.reset(pt);
+        Ok(page)
+    }
+
+    /// a page of type `pt` to be written over page `index`, whatever the file holds there
+    /// now, even an image that does not parse
+    pub fn reuse(fd: Arc<Mutex<Pager>>, index: u32, pt: PageType) -> Result<Self> {
         let mut page = Self::default();
-        page.page_type = pt;
+        {
+            let mut _fd = fd.lock().unwrap();
+            page.buf = _fd.take_buf();
+            // past the end of the file there is nothing for the digest to account for
+            if (index as u64) < _fd.file_pages()? {
+                _fd.read_page(index, &mut page.buf)?;
+                page.disk_crc = page_crc(index, &page.buf);
+            }
+        }
         page.index = index;
         page.fd = Some(fd);
-        match page.page_type{
+        page.reset(pt);
+        Ok(page)
+    }
+
+    /// wipes the page into an empty one of type `pt`, to be written over what the file holds
+    pub fn reset(&mut self, pt: PageType) {
+        self.buf.fill(0);
+        self.page_type = pt;
+        match self.page_type{
             PageType::META => {
-                page.buf[0] = 0x01;
-                page.set_root_index(0);
-                page.set_total_page(0);
+                self.buf[0] = 0x01;
+                self.set_root_index(0);
+                self.set_total_page(0);
+                let page_size = self.buf.len() as u32;
+                page_size.encode(&mut self.buf[PAGE_SIZE_OFFSET..]).unwrap();
+                1u32.encode(&mut self.buf[CHECKSUMS_OFFSET..]).unwrap();
+                self.record_format();
+                self.record_layout();
+            }
+            PageType::INTERNAL => {
+                self.buf[0] = 0x02;
+                self.set_item_count(0).unwrap();
+            }
+            PageType::LEAF => {
+                self.buf[0] = 0;
+                self.set_item_count(0).unwrap();
+            }
+            PageType::FREE => {
+                self.buf[0] = 0x04;
+                self.mark_dirty();
+            }
+            PageType::OVERFLOW => {
+                self.buf[0] = 0x08;
+                self.mark_dirty();
+            }
+            PageType::CATALOG => {
+                self.buf[0] = 0x20;
+                self.mark_dirty();
+            }
+        }
+        self.init_layout();
+    }
+
+    /// how many keys a page of the given type and size can hold
+    pub fn capacity(page_size: usize, pt: &PageType) -> usize {
+        match pt {
+            PageType::META | PageType::FREE | PageType::OVERFLOW | PageType::CATALOG => 0,
+            PageType::INTERNAL => Self::internal_capacity(page_size, false),
+            PageType::LEAF => (page_size - 8) / (K::bin_size() + Self::value_slot(page_size)),
+        }
+    }
+
+    /// how many keys an internal page of `page_size` holds, `counted` if it keeps counts
+    pub fn internal_capacity(page_size: usize, counted: bool) -> usize {
+        let ptr_size = ptr_slot(counted);
+        (page_size - 8 - ptr_size) / (K::bin_size() + ptr_size)
+    }
+
+    /// how many keys an internal page of `page_size` holds once its keys lose a shared prefix
+    /// of `prefix_len` bytes
+    pub fn prefixed_capacity(page_size: usize, prefix_len: usize, counted: bool) -> usize {
+        let ptr_size = ptr_slot(counted);
+        (page_size - PREFIX_POS - prefix_len - ptr_size) / (K::bin_size() - prefix_len + ptr_size)
+    }
+
+    // the length of the prefix an internal page keeps, None when it keeps none
+    fn stored_prefix_len(&self) -> Option<usize> {
+        if self.page_type == PageType::INTERNAL && self.buf[0] & PREFIX_FLAG != 0 {
+            Some(u16::decode(&self.buf[8..]).unwrap().0 as usize)
+        } else {
+            None
+        }
+    }
+
+    /// whether values are too big for a leaf to hold two of them, the leaves then keeping each
+    /// value on a chain of overflow pages of its own
+    pub fn spills(page_size: usize) -> bool {
+        (page_size - 8) / (K::bin_size() + V::bin_size()) < 2
+    }
+
+    fn value_slot(page_size: usize) -> usize {
+        if Self::spills(page_size) { PTR_SIZE } else { V::bin_size() }
+    }
+
+    /// how many overflow pages a value takes when it spills
+    pub fn chain_len(page_size: usize) -> usize {
+        V::bin_size().div_ceil(page_size - CHAIN_HEADER)
+    }
+
+    fn init_layout(&mut self) {
+        self.max_item_count = Self::capacity(self.buf.len(), &self.page_type);
+        self.value_size = Self::value_slot(self.buf.len());
+        self.keys_pos = 8;
+        self.key_size = K::bin_size();
+        match self.page_type{
+            PageType::META | PageType::FREE | PageType::OVERFLOW | PageType::CATALOG => {
             }
             PageType::INTERNAL => {
-                page.buf[0] = 0x02;
-                page.set_item_count(0).unwrap();
+                let counted = self.counted();
+                self.ptr_size = ptr_slot(counted);
+                self.max_item_count = Self::internal_capacity(self.buf.len(), counted);
+                if let Some(prefix_len) = self.stored_prefix_len() {
+                    self.max_item_count = Self::prefixed_capacity(self.buf.len(), prefix_len, counted);
+                    self.keys_pos = PREFIX_POS + prefix_len;
+                    self.key_size -= prefix_len;
+                }
+                self.ptrs_pos = self.keys_pos + self.max_item_count * self.key_size
             }
             PageType::LEAF => {
-                page.buf[0] = 0;
-                page.set_item_count(0).unwrap();
+                self.values_pos = self.keys_pos + self.max_item_count * K::bin_size();
             }
+        };
+        // at least we should have two items in one page
+        assert!(self.max_item_count >= 2 || ![PageType::INTERNAL, PageType::LEAF].contains(&self.page_type))
+    }
+
+    pub fn load(fd: Arc<Mutex<Pager>>, index: u32) -> Result<Self> {
+        let buf = {
+            let mut _fd = fd.lock().unwrap();
+            let mut buf = _fd.take_buf();
+            _fd.read_page(index, buf.borrow_mut())?;
+            buf
+        };
+        event!(trace, index; "loaded a page");
+        Self::from_buf(fd, index, buf)
+    }
+
+    /// loads a page an internal page points at, which has to be an internal page or a leaf
+    pub fn load_node(fd: Arc<Mutex<Pager>>, index: u32) -> Result<Self> {
+        let page = Self::load(fd, index)?;
+        match page.page_type {
+            PageType::INTERNAL | PageType::LEAF => Ok(page),
+            _ => Err(corrupted(index, format!("a {:?} page is linked into the tree", page.page_type)))
         }
-        page.init_layout();
+    }
+
+    /// a page around an image of page `index` that was already read from the file,
+    /// failing if the image holds anything the accessors could trip over
+    pub fn from_buf(fd: Arc<Mutex<Pager>>, index: u32, buf: Box<[u8]>) -> Result<Self> {
+        let mut page = Self::default();
+        page.index = index;
+        page.buf = buf;
+        page.disk_crc = page_crc(index, &page.buf);
+        let required = fd.lock().unwrap().checksums_required();
+        if let Err(reason) = check_checksum(index, &page.buf, required).and_then(|_| page.parse()) {
+            // nothing of a rejected image is ever written back
+            fd.lock().unwrap().recycle_buf(std::mem::take(&mut page.buf));
+            return Err(corrupted(index, reason));
+        }
+        page.fd = Some(fd);
         Ok(page)
     }
 
-    fn init_layout(&mut self) {
-        match self.page_type{
-            PageType::META => {
-            }
-            PageType::INTERNAL => {
-                self.max_item_count = (PAGE_SIZE - 8 - PTR_SIZE) / (K::bin_size() + PTR_SIZE);
-                self.keys_pos = 8;
-                self.ptrs_pos = self.keys_pos + self.max_item_count * K::bin_size()
-            }
-            PageType::LEAF => {
-                self.max_item_count = (PAGE_SIZE - 8) / (K::bin_size() + V::bin_size());
-                self.keys_pos = 8;
-                self.values_pos = self.keys_pos + self.max_item_count * K::bin_size();
-            }
-        };
-        // at least we should have two items in one page
-        assert!(self.page_type == PageType::META || self.max_item_count >= 2)
+    fn parse(&mut self) -> std::result::Result<(), String> {
+        let tags = [0x00, 0x01, 0x02, 0x04, 0x08, 0x20, 0x02 | PREFIX_FLAG, 0x02 | COUNTS_FLAG, 0x02 | PREFIX_FLAG | COUNTS_FLAG];
+        if !tags.contains(&(self.buf[0] & !CHECKSUM_FLAG)) {
+            return Err(format!("unknown page type tag {:#04x}", self.buf[0]));
+        }
+        self.page_type = self.get_page_type();
+        if self.page_type == PageType::META && self.index != 0 {
+            return Err("a meta page past the start of the file".to_owned());
+        }
+        let capacity = match self.page_type {
+            PageType::INTERNAL => Self::internal_capacity(self.buf.len(), self.counted()),
+            _ => Self::capacity(self.buf.len(), &self.page_type)
+        };
+        if (self.page_type == PageType::INTERNAL || self.page_type == PageType::LEAF) && capacity < 2 {
+            return Err(format!("{} byte pages cannot hold two entries", self.buf.len()));
+        }
+        if let Some(prefix_len) = self.stored_prefix_len() {
+            if prefix_len >= K::bin_size() || Self::prefixed_capacity(self.buf.len(), prefix_len, self.counted()) < 2 {
+                return Err(format!("keys of {} bytes sharing a prefix of {}", K::bin_size(), prefix_len));
+            }
+        }
+        self.init_layout();
+        match self.page_type {
+            PageType::META => {
+                let total_pages = self.total_pages();
+                if total_pages < 2 || !(1..total_pages).contains(&self.root_index()) {
+                    return Err(format!("root page {} outside of {} pages", self.root_index(), total_pages));
+                }
+                if self.free_head() >= total_pages || self.free_count() >= total_pages {
+                    return Err(format!("free list of {} pages at {} outside of {} pages", self.free_count(), self.free_head(), total_pages));
+                }
+                if self.spare_count() > MAX_SPARES {
+                    return Err(format!("{} spare pages, room for {}", self.spare_count(), MAX_SPARES));
+                }
+                if let Some(spare) = self.spares().into_iter().find(|i| *i == 0 || *i >= total_pages || *i == self.root_index()) {
+                    return Err(format!("spare page {} outside of {} pages or the root", spare, total_pages));
+                }
+                if self.catalog_index() >= total_pages || self.catalog_index() == self.root_index() {
+                    return Err(format!("catalog page {} outside of {} pages or the root", self.catalog_index(), total_pages));
+                }
+                if let Some(second) = self.second_meta() {
+                    let taken = [self.root_index(), self.free_head(), self.catalog_index()];
+                    if total_pages < 3 || taken.contains(&second) || self.spares().contains(&second) {
+                        return Err(format!("page {} of {} is the second meta page and in use besides", second, total_pages));
+                    }
+                }
+                check_checksum(self.index, &self.buf, self.checksummed())?;
+            }
+            PageType::FREE => {}
+            PageType::CATALOG => {
+                let count = self.catalog_len();
+                if count > Self::catalog_capacity(self.buf.len()) {
+                    return Err(format!("lists {} trees, room for {}", count, Self::catalog_capacity(self.buf.len())));
+                }
+                for i in 0..count {
+                    let entry = self.catalog_entry_buf(i);
+                    if std::str::from_utf8(&entry[..MAX_TREE_NAME]).is_err() {
+                        return Err(format!("the name of tree {} is not utf-8", i));
+                    }
+                    let root = self.catalog_root(i);
+                    if root == 0 || root == self.index {
+                        return Err(format!("tree {} rooted at page {}", i, root));
+                    }
+                }
+            }
+            PageType::OVERFLOW => {
+                if self.chain_next() == self.index {
+                    return Err("an overflow page chained to itself".to_owned());
+                }
+            }
+            PageType::INTERNAL | PageType::LEAF => {
+                let item_count = self.item_count();
+                if item_count > self.max_item_count {
+                    return Err(format!("holds {} entries, room for {}", item_count, self.max_item_count));
+                }
+                let mut scratch = Vec::new();
+                for i in 0..item_count {
+                    K::validate(self.full_key(i, &mut scratch)).map_err(|e| format!("key {} does not decode: {}", i, e))?;
+                }
+                if self.page_type == PageType::INTERNAL {
+                    if item_count == 0 {
+                        return Err("an internal page without keys".to_owned());
+                    }
+                    let ptrs = &self.buf[self.ptrs_pos..self.ptrs_pos + (item_count + 1) * self.ptr_size];
+                    for (i, ptr) in ptrs.chunks_exact(self.ptr_size).enumerate() {
+                        let ptr = u32::from_be_bytes([ptr[0], ptr[1], ptr[2], ptr[3]]);
+                        if ptr == 0 || ptr == self.index {
+                            return Err(format!("child {} points at page {}", i, ptr));
+                        }
+                    }
+                } else if Self::spills(self.buf.len()) {
+                    // the values themselves get checked once their chains are read
+                    for i in 0..item_count {
+                        let first = self.spilled_at(i).unwrap();
+                        if first == 0 || first == self.index {
+                            return Err(format!("value {} spilled to page {}", i, first));
+                        }
+                    }
+                } else {
+                    let values = &self.buf[self.values_pos..self.values_pos + item_count * V::bin_size()];
+                    for (i, value) in values.chunks_exact(V::bin_size()).enumerate() {
+                        V::validate(value).map_err(|e| format!("value {} does not decode: {}", i, e))?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// an in-memory copy of this page, detached from the file so it is never written back
+    pub fn snapshot(&self) -> Self {
+        let mut page = Self::default();
+        page.index = self.index;
+        page.buf = self.buf.clone();
+        page.fd = self.fd.clone();
+        page.detached = true;
+        page.page_type = page.get_page_type();
+        page.init_layout();
+        page
+    }
+
+    /// a copy of this page written back in its place, to change a page that was kept around
+    pub fn copy(&self) -> Self {
+        let mut page = self.snapshot();
+        page.dirty = self.dirty;
+        page.disk_crc = self.disk_crc;
+        page.detached = false;
+        page
+    }
+
+    /// the image syncing this page would write out, for copying it elsewhere as it stands.
+    /// a meta page carries `digest` in place of the one it holds
+    pub fn image(&self, digest: u32) -> Result<Vec<u8>> {
+        let mut buf = self.buf.to_vec();
+        let mut dirty = self.dirty;
+        if self.page_type == PageType::META && u32::decode(&buf[12..])?.0 != digest {
+            digest.encode(&mut buf[12..])?;
+            dirty = true;
+        }
+        if dirty {
+            seal(self.index, &mut buf);
+        }
+        Ok(buf)
+    }
+
+    fn mark_dirty(&mut self) {
+        self.dirty = true
+    }
+
+    fn get_page_type(&self) -> PageType {
+        let u = self.buf[0];
+        if u & 0x01 == 1 {
+            PageType::META
+        } else if u & 0x04 > 0 {
+            PageType::FREE
+        } else if u & 0x08 > 0 {
+            PageType::OVERFLOW
+        } else if u & 0x20 > 0 {
+            PageType::CATALOG
+        } else {
+            if u & 0x02 > 0 {
+                PageType::INTERNAL
+            } else {
+                PageType::LEAF
+            }
+        }
+    }
+
+    pub fn root_index(&self) -> u32 {
+        match self.page_type {
+            PageType::META => u32::decode(&self.buf[4..]).unwrap().0,
+            _ => panic!("not a meta page")
+        }
+    }
+
+    pub fn total_pages(&self) -> u32 {
+        match self.page_type {
+            PageType::META => u32::decode(&self.buf[8..]).unwrap().0,
+            _ => panic!("not a meta page")
+        }
+    }
+
+    /// the file digest recorded the last time this meta page was written
+    pub fn file_digest(&self) -> u32 {
+        match self.page_type {
+            PageType::META => u32::decode(&self.buf[12..]).unwrap().0,
+            _ => panic!("not a meta page")
+        }
+    }
+
+    /// whether the file was created writing a checksum into every page, so that a page
+    /// without one is corrupted. files from before checksums only get them on pages rewritten
+    pub fn checksummed(&self) -> bool {
+        match self.page_type {
+            PageType::META => u32::decode(&self.buf[CHECKSUMS_OFFSET..]).unwrap().0 == 1,
+            _ => panic!("not a meta page")
+        }
+    }
+
+    /// the codec pages of the file are compressed with as they are written, 0 for none.
+    /// pages written before it was picked stay as they are until rewritten
+    #[cfg(feature = "lz4")]
+    pub fn compression(&self) -> u8 {
+        match self.page_type {
+            PageType::META => u32::decode(&self.buf[COMPRESSION_OFFSET..]).unwrap().0 as u8,
+            _ => panic!("not a meta page")
+        }
+    }
+
+    #[cfg(feature = "lz4")]
+    pub fn set_compression(&mut self, codec: u8) {
+        match self.page_type {
+            PageType::META => {
+                (codec as u32).encode(&mut self.buf[COMPRESSION_OFFSET..]).unwrap();
+                self.mark_dirty();
+            }
+            _ => panic!("not a meta page")
+        }
+    }
+
+    /// the format version of the file, 0 for files from before it was recorded
+    pub fn format_version(&self) -> u32 {
+        match self.page_type {
+            PageType::META => u32::decode(&self.buf[VERSION_OFFSET..]).unwrap().0,
+            _ => panic!("not a meta page")
+        }
+    }
+
+    pub fn check_format(&self) -> Result<()> {
+        match self.page_type {
+            PageType::META => check_format(&self.buf),
+            _ => panic!("not a meta page")
+        }
+    }
+
+    pub fn record_format(&mut self) {
+        match self.page_type {
+            PageType::META => {
+                self.buf[MAGIC_OFFSET..VERSION_OFFSET].copy_from_slice(&MAGIC);
+                FORMAT_VERSION.encode(&mut self.buf[VERSION_OFFSET..]).unwrap();
+                self.mark_dirty();
+            }
+            _ => panic!("not a meta page")
+        }
+    }
+
+    /// the key and value sizes the file was built with, zero for files from before they were recorded
+    pub fn layout(&self) -> (usize, usize) {
+        match self.page_type {
+            PageType::META => (
+                u32::decode(&self.buf[LAYOUT_OFFSET..]).unwrap().0 as usize,
+                u32::decode(&self.buf[LAYOUT_OFFSET + 4..]).unwrap().0 as usize,
+            ),
+            _ => panic!("not a meta page")
+        }
+    }
+
+    /// the percentage of a full page's entries a split keeps on the left, even splits keeping 50
+    pub fn fill_factor(&self) -> usize {
+        match self.page_type {
+            PageType::META => match u32::decode(&self.buf[FILL_FACTOR_OFFSET..]).unwrap().0 {
+                0 => 50,
+                percent => percent as usize
+            },
+            _ => panic!("not a meta page")
+        }
+    }
+
+    pub fn set_fill_factor(&mut self, percent: usize) {
+        match self.page_type {
+            PageType::META => {
+                (percent as u32).encode(&mut self.buf[FILL_FACTOR_OFFSET..]).unwrap();
+                self.mark_dirty();
+            }
+            _ => panic!("not a meta page")
+        }
+    }
+
+    /// where the file keeps a second meta page, which it writes in turn with this one,
+    /// see `EPOCH_OFFSET`. None for files from before there were two
+    pub fn second_meta(&self) -> Option<u32> {
+        match self.page_type {
+            PageType::META => (u64::decode(&self.buf[EPOCH_OFFSET..]).unwrap().0 != 0).then_some(SECOND_META),
+            _ => panic!("not a meta page")
+        }
+    }
+
+    /// sets page 1 aside for a second meta page, for a file just created. the pager counts
+    /// the epochs from here on
+    pub fn keep_second_meta(&mut self) {
+        match self.page_type {
+            PageType::META => {
+                1u64.encode(&mut self.buf[EPOCH_OFFSET..]).unwrap();
+                self.mark_dirty();
+            }
+            _ => panic!("not a meta page")
+        }
+    }
+
+    /// how many pages the file has room for, see `HIGH_WATER_OFFSET`
+    pub fn high_water(&self) -> u32 {
+        match self.page_type {
+            PageType::META => u32::decode(&self.buf[HIGH_WATER_OFFSET..]).unwrap().0,
+            _ => panic!("not a meta page")
+        }
+    }
+
+    pub fn set_high_water(&mut self, pages: u32) {
+        match self.page_type {
+            PageType::META => {
+                pages.encode(&mut self.buf[HIGH_WATER_OFFSET..]).unwrap();
+                self.mark_dirty();
+            }
+            _ => panic!("not a meta page")
+        }
+    }
+
+    /// the crc of the name of the order the file's keys sort in, 0 for their natural order
+    pub fn key_order(&self) -> u32 {
+        match self.page_type {
+            PageType::META => u32::decode(&self.buf[KEY_ORDER_OFFSET..]).unwrap().0,
+            _ => panic!("not a meta page")
+        }
+    }
+
+    pub fn record_layout(&mut self) {
+        match self.page_type {
+            PageType::META => {
+                (K::bin_size() as u32).encode(&mut self.buf[LAYOUT_OFFSET..]).unwrap();
+                (V::bin_size() as u32).encode(&mut self.buf[LAYOUT_OFFSET + 4..]).unwrap();
+                key_order_crc::<K>().encode(&mut self.buf[KEY_ORDER_OFFSET..]).unwrap();
+                self.mark_dirty();
+            }
+            _ => panic!("not a meta page")
+        }
+    }
+
+    /// the first page of the free list, 0 when it is empty
+    pub fn free_head(&self) -> u32 {
+        match self.page_type {
+            PageType::META => u32::decode(&self.buf[FREE_HEAD_OFFSET..]).unwrap().0,
+            _ => panic!("not a meta page")
+        }
+    }
+
+    pub fn free_count(&self) -> u32 {
+        match self.page_type {
+            PageType::META => u32::decode(&self.buf[FREE_COUNT_OFFSET..]).unwrap().0,
+            _ => panic!("not a meta page")
+        }
+    }
+
+    pub fn set_free_list(&mut self, head: u32, count: u32) {
+        match self.page_type {
+            PageType::META => {
+                head.encode(&mut self.buf[FREE_HEAD_OFFSET..]).unwrap();
+                count.encode(&mut self.buf[FREE_COUNT_OFFSET..]).unwrap();
+                self.mark_dirty();
+            }
+            _ => panic!("not a meta page")
+        }
+    }
+
+    fn spare_count(&self) -> usize {
+        match self.page_type {
+            PageType::META => u32::decode(&self.buf[SPARE_COUNT_OFFSET..]).unwrap().0 as usize,
+            _ => panic!("not a meta page")
+        }
+    }
+
+    pub fn spares(&self) -> Vec<u32> {
+        let count = self.spare_count().min(MAX_SPARES);
+        self.buf[SPARES_OFFSET..SPARES_OFFSET + count * 4].chunks_exact(4)
+            .map(|i| u32::from_be_bytes([i[0], i[1], i[2], i[3]]))
+            .collect()
+    }
+
+    pub fn pop_spare(&mut self) -> Option<u32> {
+        let count = self.spare_count();
+        if count == 0 {
+            return None;
+        }
+        let spare = u32::decode(&self.buf[SPARES_OFFSET + (count - 1) * 4..]).unwrap().0;
+        ((count - 1) as u32).encode(&mut self.buf[SPARE_COUNT_OFFSET..]).unwrap();
+        self.mark_dirty();
+        Some(spare)
+    }
+
+    /// false, leaving the list alone, when it is full
+    pub fn push_spare(&mut self, index: u32) -> bool {
+        let count = self.spare_count();
+        if count >= MAX_SPARES {
+            return false;
+        }
+        index.encode(&mut self.buf[SPARES_OFFSET + count * 4..]).unwrap();
+        ((count + 1) as u32).encode(&mut self.buf[SPARE_COUNT_OFFSET..]).unwrap();
+        self.mark_dirty();
+        true
+    }
+
+    /// the free page after this one, 0 at the end of the list
+    pub fn next_free(&self) -> u32 {
+        match self.page_type {
+            PageType::FREE => u32::decode(&self.buf[4..]).unwrap().0,
+            _ => panic!("not a free page")
+        }
+    }
+
+    pub fn set_next_free(&mut self, next: u32) {
+        match self.page_type {
+            PageType::FREE => {
+                next.encode(&mut self.buf[4..]).unwrap();
+                self.mark_dirty();
+            }
+            _ => panic!("not a free page")
+        }
+    }
+
+    /// the page listing the file's named trees, 0 when it has none
+    pub fn catalog_index(&self) -> u32 {
+        match self.page_type {
+            PageType::META => u32::decode(&self.buf[CATALOG_OFFSET..]).unwrap().0,
+            _ => panic!("not a meta page")
+        }
+    }
+
+    pub fn set_catalog_index(&mut self, index: u32) {
+        match self.page_type {
+            PageType::META => {
+                index.encode(&mut self.buf[CATALOG_OFFSET..]).unwrap();
+                self.mark_dirty();
+            }
+            _ => panic!("not a meta page")
+        }
+    }
+
+    /// how many named trees a catalog page of the given size can list
+    pub fn catalog_capacity(page_size: usize) -> usize {
+        (page_size - 8) / CATALOG_ENTRY
+    }
+
+    pub fn catalog_len(&self) -> usize {
+        match self.page_type {
+            PageType::CATALOG => u32::decode(&self.buf[4..]).unwrap().0 as usize,
+            _ => panic!("not a catalog page")
+        }
+    }
+
+    fn catalog_entry_buf(&self, i: usize) -> &[u8] {
+        let start = 8 + i * CATALOG_ENTRY;
+        &self.buf[start..start + CATALOG_ENTRY]
+    }
+
+    fn catalog_entry_buf_mut(&mut self, i: usize) -> &mut [u8] {
+        self.mark_dirty();
+        let start = 8 + i * CATALOG_ENTRY;
+        &mut self.buf[start..start + CATALOG_ENTRY]
+    }
+
+    pub fn catalog_name(&self, i: usize) -> &str {
+        let name = &self.catalog_entry_buf(i)[..MAX_TREE_NAME];
+        let end = name.iter().position(|b| *b == 0).unwrap_or(MAX_TREE_NAME);
+        std::str::from_utf8(&name[..end]).unwrap()
+    }
+
+    pub fn catalog_root(&self, i: usize) -> u32 {
+        u32::decode(&self.catalog_entry_buf(i)[MAX_TREE_NAME..]).unwrap().0
+    }
+
+    pub fn catalog_entries(&self, i: usize) -> u64 {
+        u64::decode(&self.catalog_entry_buf(i)[MAX_TREE_NAME + 4..]).unwrap().0
+    }
+
+    /// the slot of the tree called `name`
+    pub fn catalog_find(&self, name: &str) -> Option<usize> {
+        (0..self.catalog_len()).find(|i| self.catalog_name(*i) == name)
+    }
+
+    /// lists a new tree, empty and rooted at page `root`, returning its slot. None when the
+    /// catalog is full
+    pub fn push_catalog_entry(&mut self, name: &str, root: u32) -> Option<usize> {
+        let i = self.catalog_len();
+        if i >= Self::catalog_capacity(self.buf.len()) {
+            return None;
+        }
+        ((i + 1) as u32).encode(&mut self.buf[4..]).unwrap();
+        let entry = self.catalog_entry_buf_mut(i);
+        entry.fill(0);
+        entry[..name.len()].copy_from_slice(name.as_bytes());
+        root.encode(&mut entry[MAX_TREE_NAME..]).unwrap();
+        Some(i)
+    }
+
+    pub fn set_catalog_root(&mut self, i: usize, root: u32) {
+        root.encode(&mut self.catalog_entry_buf_mut(i)[MAX_TREE_NAME..]).unwrap();
+    }
+
+    pub fn set_catalog_entries(&mut self, i: usize, entries: u64) {
+        entries.encode(&mut self.catalog_entry_buf_mut(i)[MAX_TREE_NAME + 4..]).unwrap();
     }
 
-    pub fn load(fd: Rc<RefCell<File>>, index: u32) -> Result<Self> {
-        let mut page = Self::default();
+    pub fn remove_catalog_entry(&mut self, i: usize) {
+        let count = self.catalog_len();
+        let start = 8 + i * CATALOG_ENTRY;
+        self.buf.copy_within(start + CATALOG_ENTRY..8 + count * CATALOG_ENTRY, start);
+        ((count - 1) as u32).encode(&mut self.buf[4..]).unwrap();
+        self.mark_dirty();
+    }
 
-        {
-            let mut _fd = fd.as_ref().borrow_mut();
-            page.index = index;
-            _fd.seek(SeekFrom::Start((index as usize * PAGE_SIZE) as u64))?;
-            _fd.read_exact(page.buf.borrow_mut())?;
+    pub fn chain_next(&self) -> u32 {
+        match self.page_type {
+            PageType::OVERFLOW => u32::decode(&self.buf[4..]).unwrap().0,
+            _ => panic!("not an overflow page")
         }
-
-        page.page_type = page.get_page_type();
-        page.fd = Some(fd);
-        page.init_layout();
-        Ok(page)
     }
 
-    fn mark_dirty(&mut self) {
-        self.dirty = true
+    /// points an overflow page at the page its chain goes on to
+    pub fn set_chain_next(&mut self, next: u32) {
+        match self.page_type {
+            PageType::OVERFLOW => {
+                next.encode(&mut self.buf[4..]).unwrap();
+                self.mark_dirty();
+            }
+            _ => panic!("not an overflow page")
+        }
     }
 
-    fn get_page_type(&self) -> PageType {
-        let u = self.buf[0];
-        if u & 0x01 == 1 {
-            PageType::META
-        } else {
-            if u & 0x02 > 0 {
-                PageType::INTERNAL
-            } else {
-                PageType::LEAF
+    /// fills an overflow page with `data`, followed on page `next`
+    pub fn set_chain(&mut self, next: u32, data: &[u8]) {
+        match self.page_type {
+            PageType::OVERFLOW => {
+                next.encode(&mut self.buf[4..]).unwrap();
+                self.buf[CHAIN_HEADER..CHAIN_HEADER + data.len()].copy_from_slice(data);
+                self.mark_dirty();
             }
+            _ => panic!("not an overflow page")
         }
     }
 
-    pub fn root_index(&self) -> u32 {
+    pub fn stat(&self, stat: Stat) -> u64 {
         match self.page_type {
-            PageType::META => u32::decode(&self.buf[4..]).unwrap().0,
+            PageType::META => u64::decode(&self.buf[stat.offset()..]).unwrap().0,
             _ => panic!("not a meta page")
         }
     }
 
-    pub fn total_pages(&self) -> u32 {
+    pub fn set_stat(&mut self, stat: Stat, value: u64) {
         match self.page_type {
-            PageType::META => u32::decode(&self.buf[8..]).unwrap().0,
+            PageType::META => {
+                value.encode(&mut self.buf[stat.offset()..]).unwrap();
+                self.mark_dirty();
+            }
             _ => panic!("not a meta page")
         }
     }
 
+    pub fn bump_stat(&mut self, stat: Stat) {
+        self.set_stat(stat, self.stat(stat) + 1)
+    }
+
     pub fn set_root_index(&mut self, root_index: u32) {
         match self.page_type {
             PageType::META => {
@@ -214,20 +1061,145 @@ impl<K, V> Page<K, V> where
                 if i >= self.item_count() {
                     None
                 } else {
-                    K::decode(&self.buf[(self.keys_pos + i * K::bin_size())..]).map(|t| t.0).ok()
+                    K::decode(&self.raw_key_at(i)).map(|t| t.0).ok()
                 }
             }
             _ => panic!("not a internal / leaf page")
         }
     }
 
+    /// decodes the value at `i` of a leaf into `out`, reusing its storage
+    pub fn value_into(&self, i: usize, out: &mut V) -> Result<()> {
+        if i >= self.item_count() {
+            return Err(anyhow!("over size"))
+        }
+        out.decode_into(self.value_bytes(i, &mut Vec::new())?)?;
+        Ok(())
+    }
+
+    /// the encoded value at `i` of a leaf, read into `scratch` when it spilled
+    pub fn value_bytes<'a>(&'a self, i: usize, scratch: &'a mut Vec<u8>) -> Result<&'a [u8]> {
+        match self.spilled_at(i) {
+            Some(first) => {
+                let fd = self.fd.as_ref().ok_or_else(|| anyhow!("page {} has no file to read spilled values from", self.index))?;
+                read_chain::<V>(fd, first, scratch)?;
+                Ok(scratch)
+            }
+            None => Ok(self.raw_value_at(i))
+        }
+    }
+
+    /// the first overflow page of the value at `i` of a leaf, None when values are kept in the leaf
+    pub fn spilled_at(&self, i: usize) -> Option<u32> {
+        if !Self::spills(self.buf.len()) {
+            return None;
+        }
+        u32::decode(self.raw_value_at(i)).ok().map(|t| t.0)
+    }
+
+    /// the encoded key at `i`, which must be in range, put back together when the page keeps
+    /// a prefix for its keys
+    pub fn raw_key_at(&self, i: usize) -> Cow<'_, [u8]> {
+        if self.key_size == K::bin_size() {
+            Cow::Borrowed(&self.buf[self.key_range(i)])
+        } else {
+            let mut key = Vec::with_capacity(K::bin_size());
+            self.full_key(i, &mut key);
+            Cow::Owned(key)
+        }
+    }
+
+    /// the encoded key at `i` of a leaf, which must be in range
+    pub fn leaf_key_at(&self, i: usize) -> &[u8] {
+        assert_eq!(self.page_type, PageType::LEAF);
+        &self.buf[self.key_range(i)]
+    }
+
+    // `raw_key_at` putting the key together in `scratch`
+    fn full_key<'a>(&'a self, i: usize, scratch: &'a mut Vec<u8>) -> &'a [u8] {
+        let slot = &self.buf[self.key_range(i)];
+        if self.key_size == K::bin_size() {
+            return slot;
+        }
+        scratch.clear();
+        scratch.extend_from_slice(self.prefix());
+        scratch.extend_from_slice(slot);
+        scratch
+    }
+
+    fn key_range(&self, i: usize) -> std::ops::Range<usize> {
+        assert!(i < self.item_count());
+        let start = self.keys_pos + i * self.key_size;
+        start..start + self.key_size
+    }
+
+    /// the leading bytes every key of an internal page starts with, kept once rather than in
+    /// each slot. empty for the pages that keep none
+    pub fn prefix(&self) -> &[u8] {
+        &self.buf[PREFIX_POS..PREFIX_POS + (K::bin_size() - self.key_size)]
+    }
+
+    /// lays an internal page out anew for its keys to share `prefix`, which they must all
+    /// start with. fails with `PageError::Full` when the keys outnumber the slots of the new
+    /// layout. a prefix too short to make room for more keys is not kept
+    pub fn set_prefix(&mut self, prefix: &[u8]) -> Result<()> {
+        assert_eq!(self.page_type, PageType::INTERNAL);
+        let page_size = self.buf.len();
+        let counted = self.counted();
+        let prefix = if prefix.len() < K::bin_size()
+            && Self::prefixed_capacity(page_size, prefix.len(), counted) > Self::internal_capacity(page_size, counted) {
+            prefix
+        } else {
+            &[]
+        };
+        if prefix == self.prefix() {
+            return Ok(());
+        }
+        let item_count = self.item_count();
+        let keys: Vec<Vec<u8>> = (0..item_count).map(|i| self.raw_key_at(i).into_owned()).collect();
+        if let Some(i) = keys.iter().position(|key| !key.starts_with(prefix)) {
+            return Err(corrupted(self.index, format!("key {} does not start with the prefix of its page", i)));
+        }
+        // the pointers move over whole, the counts kept for them along
+        let ptrs = self.buf[self.ptrs_pos..self.ptrs_pos + (item_count + 1) * self.ptr_size].to_vec();
+        let capacity = if prefix.is_empty() {
+            Self::internal_capacity(page_size, counted)
+        } else {
+            Self::prefixed_capacity(page_size, prefix.len(), counted)
+        };
+        if item_count > capacity {
+            return Err(PageError::Full.into());
+        }
+        self.buf[0] &= !PREFIX_FLAG;
+        if !prefix.is_empty() {
+            self.buf[0] |= PREFIX_FLAG;
+            (prefix.len() as u16).encode(&mut self.buf[8..])?;
+            self.buf[PREFIX_POS..PREFIX_POS + prefix.len()].copy_from_slice(prefix);
+        }
+        self.init_layout();
+        for (i, key) in keys.iter().enumerate() {
+            self.set_raw_key_at(i, key)?;
+        }
+        self.buf[self.ptrs_pos..self.ptrs_pos + ptrs.len()].copy_from_slice(&ptrs);
+        self.mark_dirty();
+        Ok(())
+    }
+
+    /// the value slot at `i` of a leaf, which must be in range: the encoded value, or the
+    /// first page of its overflow chain when values spill
+    pub fn raw_value_at(&self, i: usize) -> &[u8] {
+        assert!(self.page_type == PageType::LEAF && i < self.item_count());
+        let start = self.values_pos + i * self.value_size;
+        &self.buf[start..start + self.value_size]
+    }
+
     pub fn value_at(&self, i: usize) -> Option<V> {
         match self.page_type {
             PageType::LEAF => {
                 if i >= self.item_count() {
                     None
                 } else {
-                    V::decode(&self.buf[(self.values_pos + i * V::bin_size())..]).map(|t| t.0).ok()
+                    V::decode(self.value_bytes(i, &mut Vec::new()).ok()?).map(|t| t.0).ok()
                 }
             }
             _ => panic!("not a leaf page")
@@ -237,23 +1209,175 @@ impl<K, V> Page<K, V> where
     pub fn ptr_at(&self, i: usize) -> Option<u32> {
         match self.page_type {
             PageType::INTERNAL=> {
-                if i >= self.item_count() + 1 {
+                if i > self.item_count() {
+                    None
+                } else {
+                    u32::decode(&self.buf[(self.ptrs_pos + i * self.ptr_size)..]).map(|t| t.0).ok()
+                }
+            }
+            _ => panic!("not a internal page")
+        }
+    }
+
+    /// overwrites `bytes.len()` bytes of the encoded value at `i`, starting `offset` bytes into it
+    pub fn patch_value_at(&mut self, i: usize, offset: usize, bytes: &[u8]) -> Result<()> {
+        match self.page_type {
+            PageType::LEAF => {
+                if i >= self.item_count() {
+                    return Err(anyhow!("over size"))
+                }
+                if offset + bytes.len() > V::bin_size() {
+                    return Err(anyhow!("patch of {} bytes at {} overruns a {} byte value", bytes.len(), offset, V::bin_size()))
+                }
+                if Self::spills(self.buf.len()) {
+                    return Err(anyhow!("values spilled to overflow pages are not patched in place"))
+                }
+                let start = self.values_pos + i * V::bin_size() + offset;
+                self.buf[start..start + bytes.len()].copy_from_slice(bytes);
+                self.mark_dirty();
+                Ok(())
+            }
+            _ => panic!("not a leaf page")
+        }
+    }
+
+    /// the page index of the child whose subtree may hold `k`
+    pub fn child_for(&self, k: &K) -> u32 {
+        self.ptr_at(self.child_slot(k)).unwrap()
+    }
+
+    /// the slot of the pointer to the child whose subtree may hold `k`
+    pub fn child_slot(&self, k: &K) -> usize {
+        match self.find(k) {
+            Some((i, Pos::Left)) => i,
+            Some((i, _)) => i + 1,
+            None => panic!("impossible for an empty internal page")
+        }
+    }
+
+    /// whether internal pages keep the count of the entries under each child, see
+    /// `COUNTS_FLAG`: for an internal page whether it does, for the meta page whether the ones
+    /// of the file get created doing so
+    pub fn counted(&self) -> bool {
+        match self.page_type {
+            PageType::META => u32::decode(&self.buf[COUNTS_OFFSET..]).unwrap().0 == 1,
+            PageType::INTERNAL => self.buf[0] & COUNTS_FLAG != 0,
+            _ => false
+        }
+    }
+
+    /// for the meta page of a file just created, makes the internal pages of the file keep
+    /// counts from here on. for an internal page without keys yet, makes it keep them
+    pub fn keep_counts(&mut self) {
+        match self.page_type {
+            PageType::META => {
+                1u32.encode(&mut self.buf[COUNTS_OFFSET..]).unwrap();
+            }
+            PageType::INTERNAL => {
+                assert_eq!(self.item_count(), 0);
+                self.buf[0] |= COUNTS_FLAG;
+                self.init_layout();
+            }
+            _ => panic!("not a meta / internal page")
+        }
+        self.mark_dirty();
+    }
+
+    /// how many entries are under child `i` of an internal page, None on one keeping no counts
+    pub fn count_at(&self, i: usize) -> Option<u64> {
+        match self.page_type {
+            PageType::INTERNAL => {
+                if !self.counted() || i > self.item_count() {
                     None
                 } else {
-                    u32::decode(&self.buf[(self.ptrs_pos + i * PTR_SIZE)..]).map(|t| t.0).ok()
+                    u64::decode(&self.buf[(self.ptrs_pos + i * self.ptr_size + PTR_SIZE)..]).map(|t| t.0).ok()
+                }
+            }
+            _ => panic!("not a internal page")
+        }
+    }
+
+    /// records `count` entries under child `i`, on pages keeping counts
+    pub fn set_count_at(&mut self, i: usize, count: u64) -> Result<()> {
+        match self.page_type {
+            PageType::INTERNAL => {
+                if i > self.item_count() {
+                    return Err(anyhow!("over size"))
+                }
+                if self.counted() {
+                    count.encode(&mut self.buf[(self.ptrs_pos + i * self.ptr_size + PTR_SIZE)..])?;
+                    self.mark_dirty();
                 }
+                Ok(())
             }
             _ => panic!("not a internal page")
         }
     }
 
+    /// counts `delta` more entries under child `i`, on pages keeping counts
+    pub fn add_count_at(&mut self, i: usize, delta: i64) -> Result<()> {
+        match self.count_at(i) {
+            Some(count) => {
+                let count = count.checked_add_signed(delta)
+                    .ok_or_else(|| corrupted(self.index, format!("child {} counts {} entries, {} off", i, count, delta)))?;
+                self.set_count_at(i, count)
+            }
+            None => Ok(())
+        }
+    }
+
+    /// how many entries a leaf holds, or an internal page keeping counts has under it
+    pub fn total(&self) -> u64 {
+        match self.page_type {
+            PageType::LEAF => self.item_count() as u64,
+            PageType::INTERNAL => (0..=self.item_count()).filter_map(|i| self.count_at(i)).sum(),
+            _ => panic!("not a internal / leaf page")
+        }
+    }
+
+    /// copies an already encoded key into slot `i`
+    pub fn set_raw_key_at(&mut self, i: usize, key: &[u8]) -> Result<()> {
+        if i >= self.item_count() || key.len() != K::bin_size() {
+            return Err(anyhow!("over size"))
+        }
+        let (prefix, suffix) = key.split_at(K::bin_size() - self.key_size);
+        if prefix != self.prefix() {
+            return Err(corrupted(self.index, "a key without the prefix of its page".to_owned()));
+        }
+        let range = self.key_range(i);
+        self.buf[range].copy_from_slice(suffix);
+        self.mark_dirty();
+        Ok(())
+    }
+
+    /// copies a value slot as `raw_value_at` hands it out into slot `i` of a leaf
+    pub fn set_raw_value_at(&mut self, i: usize, value: &[u8]) -> Result<()> {
+        match self.page_type {
+            PageType::LEAF => {
+                if i >= self.item_count() || value.len() != self.value_size {
+                    return Err(anyhow!("over size"))
+                }
+                let start = self.values_pos + i * self.value_size;
+                self.buf[start..start + self.value_size].copy_from_slice(value);
+                self.mark_dirty();
+                Ok(())
+            }
+            _ => panic!("not a leaf page")
+        }
+    }
+
     pub fn set_key_at(&mut self, i: usize, key: &K) -> Result<()> {
         match self.page_type {
             PageType::INTERNAL | PageType::LEAF => {
                 if i >= self.item_count() {
                     return Err(anyhow!("over size"))
                 }
-                key.encode(&mut self.buf[(self.keys_pos + i * K::bin_size())..])?;
+                if self.key_size != K::bin_size() {
+                    let mut buf = vec![0u8; K::bin_size()];
+                    key.encode(&mut buf)?;
+                    return self.set_raw_key_at(i, &buf);
+                }
+                key.encode(&mut self.buf[self.keys_pos + i * K::bin_size()..])?;
                 self.mark_dirty();
                 Ok(())
             }
@@ -261,13 +1385,19 @@ impl<K, V> Page<K, V> where
         }
     }
 
-    pub fn set_value_at(&mut self, i: usize, value: &V) -> Result<()> {
+    pub fn set_value_at(&mut self, i: usize, value: &Slot<V>) -> Result<()> {
         match self.page_type {
             PageType::LEAF => {
                 if i >= self.item_count() {
                     return Err(anyhow!("over size"))
                 }
-                value.encode(&mut self.buf[(self.values_pos + i * V::bin_size())..])?;
+                let spills = Self::spills(self.buf.len());
+                let slot = &mut self.buf[(self.values_pos + i * self.value_size)..];
+                match value {
+                    Slot::Value(value) if !spills => value.encode(slot)?,
+                    Slot::Spilled(first) if spills => first.encode(slot)?,
+                    _ => return Err(anyhow!("a value goes to overflow pages exactly when it is too big for a leaf"))
+                };
                 self.mark_dirty();
                 Ok(())
             }
@@ -278,10 +1408,10 @@ impl<K, V> Page<K, V> where
     pub fn set_ptr_at(&mut self, i: usize, ptr: u32) -> Result<()> {
         match self.page_type {
             PageType::INTERNAL => {
-                if i >= self.item_count() + 1 {
+                if i > self.item_count() {
                     return Err(anyhow!("over size"))
                 }
-                ptr.encode(&mut self.buf[(self.ptrs_pos + i * PTR_SIZE)..])?;
+                ptr.encode(&mut self.buf[(self.ptrs_pos + i * self.ptr_size)..])?;
                 self.mark_dirty();
                 Ok(())
             }
@@ -289,7 +1419,78 @@ impl<K, V> Page<K, V> where
         }
     }
 
+    /// takes entry `i` out of a leaf, closing the gap
+    pub fn remove_entry(&mut self, i: usize) -> Result<()> {
+        assert_eq!(self.page_type, PageType::LEAF);
+        let item_count = self.item_count();
+        if i >= item_count {
+            return Err(anyhow!("over size"))
+        }
+        let (ks, vs) = (K::bin_size(), self.value_size);
+        self.buf.copy_within(self.keys_pos + (i + 1) * ks..self.keys_pos + item_count * ks, self.keys_pos + i * ks);
+        self.buf.copy_within(self.values_pos + (i + 1) * vs..self.values_pos + item_count * vs, self.values_pos + i * vs);
+        self.set_item_count(item_count - 1)
+    }
+
+    /// puts an already encoded key in at slot `key_i` and a pointer in at slot `ptr_i`
+    /// of an internal page, with `count` entries under it, shifting the ones from there on up
+    pub fn insert_separator(&mut self, key_i: usize, key: &[u8], ptr_i: usize, ptr: u32, count: u64) -> Result<()> {
+        assert_eq!(self.page_type, PageType::INTERNAL);
+        let item_count = self.item_count();
+        if key_i > item_count || ptr_i > item_count + 1 {
+            return Err(anyhow!("over size"))
+        }
+        self.set_item_count(item_count + 1)?;
+        let (ks, ps) = (self.key_size, self.ptr_size);
+        self.buf.copy_within(self.keys_pos + key_i * ks..self.keys_pos + item_count * ks, self.keys_pos + (key_i + 1) * ks);
+        self.buf.copy_within(self.ptrs_pos + ptr_i * ps..self.ptrs_pos + (item_count + 1) * ps, self.ptrs_pos + (ptr_i + 1) * ps);
+        self.set_raw_key_at(key_i, key)?;
+        self.set_ptr_at(ptr_i, ptr)?;
+        self.set_count_at(ptr_i, count)
+    }
+
+    /// takes the key at slot `key_i` and the pointer at slot `ptr_i` out of an internal page,
+    /// which may be left without keys
+    pub fn remove_separator(&mut self, key_i: usize, ptr_i: usize) -> Result<()> {
+        assert_eq!(self.page_type, PageType::INTERNAL);
+        let item_count = self.item_count();
+        if key_i >= item_count || ptr_i > item_count {
+            return Err(anyhow!("over size"))
+        }
+        let (ks, ps) = (self.key_size, self.ptr_size);
+        self.buf.copy_within(self.keys_pos + (key_i + 1) * ks..self.keys_pos + item_count * ks, self.keys_pos + key_i * ks);
+        self.buf.copy_within(self.ptrs_pos + (ptr_i + 1) * ps..self.ptrs_pos + (item_count + 1) * ps, self.ptrs_pos + ptr_i * ps);
+        self.set_item_count(item_count - 1)
+    }
+
+    /// points the slot holding `old` at `new` instead
+    pub fn replace_ptr(&mut self, old: u32, new: u32) -> Result<()> {
+        assert_eq!(self.page_type, PageType::INTERNAL);
+        match (0..=self.item_count()).find(|i| self.ptr_at(*i) == Some(old)) {
+            Some(i) => self.set_ptr_at(i, new),
+            None => Err(corrupted(self.index, format!("no pointer to page {} to replace", old)))
+        }
+    }
+
+    /// turns this page into a copy of `other`, to be written out at its own index
+    pub fn copy_from(&mut self, other: &Page<K, V>) {
+        self.buf.copy_from_slice(&other.buf);
+        self.page_type = self.get_page_type();
+        self.init_layout();
+        self.mark_dirty();
+    }
+
+    /// drops the page without writing back whatever changed in it
+    pub fn discard(mut self) {
+        self.dirty = false;
+    }
+
     pub fn find(&self, k: &K) -> Option<(usize, Pos)> {
+        if K::ordered_encoding() {
+            if let Some(found) = self.find_encoded(k) {
+                return found;
+            }
+        }
         let item_count = self.item_count();
         if item_count == 0 {
             return None;
@@ -318,128 +1519,121 @@ impl<K, V> Page<K, V> where
         None
     }
 
-    pub fn insert(&mut self, k: &K, v: &V) -> Result<()> {
-        assert_eq!(self.page_type, PageType::LEAF);
-        let old_item_count = self.item_count();
-        match self.find(k) {
-            None => {
-                // empty node
-                self.set_item_count(1)?;
-                self.set_key_at(0, k)?;
-                self.set_value_at(0, v)?;
-            },
-            Some((i, pos)) => {
-                match pos {
-                    Pos::Current => {
-                        self.set_key_at(i, k)?;
-                        self.set_value_at(i, v)?;
-                    }
-                    Pos::Left => {
-                        self.set_item_count(old_item_count + 1)?;
-                        unsafe {
-                            let buf_ptr = self.buf.as_mut_ptr();
-                            let key_ptr = buf_ptr.add(self.keys_pos);
-                            let value_ptr = buf_ptr.add(self.values_pos);
-                            std::ptr::copy(key_ptr.add(i * K::bin_size()), key_ptr.add((i + 1) * K::bin_size()), (old_item_count - i) * K::bin_size());
-                            std::ptr::copy(value_ptr.add(i * V::bin_size()), value_ptr.add((i + 1) * V::bin_size()), (old_item_count - i) * V::bin_size());
-                        }
-                        // for j in (i..old_item_count).rev() {
-                        //     self.set_key_at(j + 1, &self.key_at(j).unwrap())?;
-                        //     self.set_value_at(j + 1, &self.value_at(j).unwrap())?;
-                        // }
-                        self.set_key_at(i, k)?;
-                        self.set_value_at(i, v)?;
+    // `find` comparing the stored bytes of the keys to those of `k`. None when `k` does not
+    // encode, such as a string too long for its type, which is left to comparing decoded keys
+    fn find_encoded(&self, k: &K) -> Option<Option<(usize, Pos)>> {
+        let item_count = self.item_count();
+        if item_count == 0 {
+            return Some(None);
+        }
+        // short keys are encoded on the stack
+        let mut short = [0u8; 32];
+        let mut long = Vec::new();
+        let probe = if K::bin_size() <= short.len() {
+            &mut short[..K::bin_size()]
+        } else {
+            long.resize(K::bin_size(), 0);
+            &mut long[..]
+        };
+        k.encode(probe).ok()?;
+        let mut scratch = Vec::new();
+        let mut min = 0;
+        let mut max = item_count - 1;
+        while min <= max {
+            let mid = (min + max) / 2;
+            match K::compare_encoded(self.full_key(mid, &mut scratch), probe) {
+                Ordering::Equal => return Some(Some((mid, Pos::Current))),
+                Ordering::Less => {
+                    if mid == item_count - 1 || K::compare_encoded(self.full_key(mid + 1, &mut scratch), probe) == Ordering::Greater {
+                        return Some(Some((mid, Pos::Right)));
                     }
-                    Pos::Right => {
-                        self.set_item_count(old_item_count + 1)?;
-                        unsafe {
-                            let buf_ptr = self.buf.as_mut_ptr();
-                            let key_ptr = buf_ptr.add(self.keys_pos);
-                            let value_ptr = buf_ptr.add(self.values_pos);
-                            std::ptr::copy(key_ptr.add((i + 1) * K::bin_size()), key_ptr.add((i + 2) * K::bin_size()), (old_item_count - i - 1) * K::bin_size());
-                            std::ptr::copy(value_ptr.add((i + 1) * V::bin_size()), value_ptr.add((i + 2) * V::bin_size()), (old_item_count - i - 1) * V::bin_size());
-                        }
-                        // for j in ((i + 1)..old_item_count).rev() {
-                        //     self.set_key_at(j + 1, &self.key_at(j).unwrap())?;
-                        //     self.set_value_at(j + 1, &self.value_at(j).unwrap())?;
-                        // }
-                        self.set_key_at(i + 1, k)?;
-                        self.set_value_at(i + 1, v)?;
+                    min = mid + 1
+                }
+                Ordering::Greater => {
+                    if mid == 0 {
+                        return Some(Some((mid, Pos::Left)));
                     }
+                    max = mid - 1
                 }
             }
         }
+        Some(None)
+    }
+
+    /// returns whether `k` is a new key rather than an overwrite
+    pub fn insert(&mut self, k: &K, v: &Slot<V>) -> Result<bool> {
+        assert_eq!(self.page_type, PageType::LEAF);
+        let item_count = self.item_count();
+        let (i, inserted) = match self.find(k) {
+            None => (0, true),
+            Some((i, Pos::Current)) => (i, false),
+            Some((i, Pos::Left)) => (i, true),
+            Some((i, Pos::Right)) => (i + 1, true)
+        };
+        if inserted {
+            // the entries from slot i on move up one as they are, raw
+            self.set_item_count(item_count + 1)?;
+            let (ks, vs) = (K::bin_size(), self.value_size);
+            self.buf.copy_within(self.keys_pos + i * ks..self.keys_pos + item_count * ks, self.keys_pos + (i + 1) * ks);
+            self.buf.copy_within(self.values_pos + i * vs..self.values_pos + item_count * vs, self.values_pos + (i + 1) * vs);
+        }
+        self.set_key_at(i, k)?;
+        self.set_value_at(i, v)?;
         self.mark_dirty();
-        Ok(())
+        Ok(inserted)
     }
 
-    pub fn insert_ptr(&mut self, k: &K, ptr: u32) -> Result<()> {
+    /// puts `k` in with the pointer right of it to a child with `count` entries under it
+    pub fn insert_ptr(&mut self, k: &K, ptr: u32, count: u64) -> Result<()> {
         assert_eq!(self.page_type, PageType::INTERNAL);
-        let old_item_count = self.item_count();
-        match self.find(k) {
+        let item_count = self.item_count();
+        let (i, inserted) = match self.find(k) {
             None => {
                 // empty node
                 // must first set ptrs[0] !!!
                 assert!(self.ptr_at(0).unwrap() > 0);
-                self.set_item_count(1)?;
-                self.set_key_at(0, k)?;
-                self.set_ptr_at(1, ptr)?;
-            },
-            Some((i, pos)) => {
-                match pos {
-                    Pos::Current => {
-                        self.set_key_at(i, k)?;
-                        self.set_ptr_at(i + 1, ptr)?;
-                    }
-                    Pos::Left => {
-                        self.set_item_count(old_item_count + 1)?;
-                        unsafe {
-                            let buf_ptr = self.buf.as_mut_ptr();
-                            let key_ptr = buf_ptr.add(self.keys_pos);
-                            let ptr_ptr = buf_ptr.add(self.ptrs_pos);
-                            std::ptr::copy(key_ptr.add(i * K::bin_size()), key_ptr.add((i + 1) * K::bin_size()), (old_item_count - i) * K::bin_size());
-                            std::ptr::copy(ptr_ptr.add((i + 1) * PTR_SIZE), ptr_ptr.add((i + 2) * PTR_SIZE), (old_item_count - i) * PTR_SIZE);
-                        }
-                        // for j in (i..old_item_count).rev() {
-                        //     self.set_key_at(j + 1, &self.key_at(j).unwrap())?;
-                        //     self.set_ptr_at(j + 2, self.ptr_at(j + 1).unwrap())?;
-                        // }
-                        self.set_key_at(i, k)?;
-                        self.set_ptr_at(i + 1, ptr)?;
-                    }
-                    Pos::Right => {
-                        self.set_item_count(old_item_count + 1)?;
-                        unsafe {
-                            let buf_ptr = self.buf.as_mut_ptr();
-                            let key_ptr = buf_ptr.add(self.keys_pos);
-                            let ptr_ptr = buf_ptr.add(self.ptrs_pos);
-                            std::ptr::copy(key_ptr.add((i + 1) * K::bin_size()), key_ptr.add((i + 2) * K::bin_size()), (old_item_count - i -1) * K::bin_size());
-                            std::ptr::copy(ptr_ptr.add((i + 2) * PTR_SIZE), ptr_ptr.add((i + 3) * PTR_SIZE), (old_item_count - i - 1) * PTR_SIZE);
-                        }
-                        // for j in ((i + 1)..old_item_count).rev() {
-                        //     self.set_key_at(j + 1, &self.key_at(j).unwrap())?;
-                        //     self.set_ptr_at(j + 2, self.ptr_at(j + 1).unwrap())?;
-                        // }
-                        self.set_key_at(i + 1, k)?;
-                        self.set_ptr_at(i + 2, ptr)?;
-                    }
-                }
+                (0, true)
             }
+            Some((i, Pos::Current)) => (i, false),
+            Some((i, Pos::Left)) => (i, true),
+            Some((i, Pos::Right)) => (i + 1, true)
+        };
+        if inserted {
+            // the keys from slot i and the pointers right of them move up one
+            self.set_item_count(item_count + 1)?;
+            let (ks, ps) = (self.key_size, self.ptr_size);
+            self.buf.copy_within(self.keys_pos + i * ks..self.keys_pos + item_count * ks, self.keys_pos + (i + 1) * ks);
+            self.buf.copy_within(self.ptrs_pos + (i + 1) * ps..self.ptrs_pos + (item_count + 1) * ps, self.ptrs_pos + (i + 2) * ps);
         }
+        self.set_key_at(i, k)?;
+        self.set_ptr_at(i + 1, ptr)?;
+        self.set_count_at(i + 1, count)?;
         self.mark_dirty();
         Ok(())
     }
 }
 
 impl<K,V> Debug for Page<K, V> where
-    K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
-    V: Encodable + Decodable + BinSizer + Debug + Clone
+    K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+    V: Encodable + Decodable + BinSizer + Debug
 {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self.page_type {
             PageType::META => {
                 f.write_fmt(format_args!("{:?}; root index:{}; total pages: {}", self.page_type, self.root_index(), self.total_pages()))?;
             }
+            PageType::FREE => {
+                f.write_fmt(format_args!("{:?}; next free: {}", self.page_type, self.next_free()))?;
+            }
+            PageType::OVERFLOW => {
+                f.write_fmt(format_args!("{:?}; next: {}", self.page_type, self.chain_next()))?;
+            }
+            PageType::CATALOG => {
+                f.write_fmt(format_args!("{:?}; trees: {};\n", self.page_type, self.catalog_len()))?;
+                for i in 0..self.catalog_len() {
+                    f.write_fmt(format_args!("#{} {:?}: root {}\n", i, self.catalog_name(i), self.catalog_root(i)))?;
+                }
+            }
             PageType::LEAF => {
                 f.write_fmt(format_args!("{:?}; item count:{};\n", self.page_type, self.item_count()))?;
                 for i in 0..self.item_count() {
@@ -459,12 +1653,32 @@ impl<K,V> Debug for Page<K, V> where
 }
 
 impl<K, V> Page<K, V> {
+    /// cuts the page off from the file, whatever it holds is never written back
+    pub fn detach(&mut self) {
+        self.detached = true
+    }
+
     pub fn sync(&mut self) -> Result<()> {
+        let fd = match self.fd.as_ref() {
+            Some(fd) if !self.detached => fd,
+            _ => return Ok(())
+        };
+        let mut fd = fd.lock().unwrap();
+        if self.page_type == PageType::META && u32::decode(&self.buf[12..])?.0 != fd.digest() {
+            // other pages were written since, the meta page has to carry the new digest
+            fd.digest().encode(&mut self.buf[12..])?;
+            self.dirty = true;
+        }
         if self.dirty {
-            let mut fd = self.fd.as_ref().unwrap().as_ref().borrow_mut();
-            fd.seek(SeekFrom::Start((self.index as usize * PAGE_SIZE) as u64))?;
-            fd.write_all(self.buf.borrow())?;
+            seal(self.index, &mut self.buf);
+            fd.write_page(self.index, self.buf.borrow())?;
+            let crc = page_crc(self.index, &self.buf);
+            fd.roll_digest(self.disk_crc, crc);
+            self.disk_crc = crc;
             self.dirty = false;
+            if matches!(self.page_type, PageType::INTERNAL | PageType::FREE) {
+                fd.bump_generation();
+            }
         }
         Ok(())
     }
@@ -473,5 +1687,67 @@ impl<K, V> Page<K, V> {
 impl<K, V> Drop for Page<K, V> {
     fn drop(&mut self) {
         self.sync().unwrap();
+        if let Some(fd) = self.fd.as_ref() {
+            fd.lock().unwrap().recycle_buf(std::mem::take(&mut self.buf));
+        }
+    }
+}
+/// reads the value spilled to the overflow chain starting at page `first` into `out`
+pub(crate) fn read_chain<V: Decodable + BinSizer>(fd: &Arc<Mutex<Pager>>, first: u32, out: &mut Vec<u8>) -> Result<()> {
+    out.clear();
+    let mut fd = fd.lock().unwrap();
+    let mut buf = fd.take_buf();
+    let mut index = first;
+    let required = fd.checksums_required();
+    let read = (|| {
+        while out.len() < V::bin_size() {
+            if index == 0 {
+                return Err(corrupted(first, format!("overflow chain ends {} bytes into a {} byte value", out.len(), V::bin_size())));
+            }
+            fd.read_page(index, &mut buf)?;
+            check_checksum(index, &buf, required).map_err(|reason| corrupted(index, reason))?;
+            if buf[0] & !CHECKSUM_FLAG != 0x08 {
+                return Err(corrupted(index, format!("a page with tag {:#04x} in the overflow chain from page {}", buf[0], first)));
+            }
+            let take = (V::bin_size() - out.len()).min(buf.len() - CHAIN_HEADER);
+            out.extend_from_slice(&buf[CHAIN_HEADER..CHAIN_HEADER + take]);
+            index = u32::decode(&buf[4..])?.0;
+        }
+        V::validate(out).map_err(|e| corrupted(first, format!("spilled value does not decode: {}", e)))
+    })();
+    fd.recycle_buf(buf);
+    read
+}
+
+/// crc of the image of page `index`, leaving out the header bytes it gets stored in
+fn checksum(index: u32, buf: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&index.to_be_bytes());
+    hasher.update(&buf[..1]);
+    hasher.update(&buf[4..]);
+    hasher.finalize() & 0x00ff_ffff
+}
+
+/// writes the checksum of the image about to go out to page `index` into its header
+fn seal(index: u32, buf: &mut [u8]) {
+    buf[0] |= CHECKSUM_FLAG;
+    let crc = checksum(index, buf).to_be_bytes();
+    buf[1..4].copy_from_slice(&crc[1..]);
+}
+
+/// fails for an image of page `index` that does not match its checksum, or carries none
+/// though `required`. a torn write or a page written to the wrong place shows up here
+fn check_checksum(index: u32, buf: &[u8], required: bool) -> std::result::Result<(), String> {
+    if buf[0] & CHECKSUM_FLAG == 0 {
+        return match required {
+            true => Err("no checksum on a page of a checksummed file".to_owned()),
+            false => Ok(())
+        };
     }
-}
\ No newline at end of file
+    let stored = u32::from_be_bytes([0, buf[1], buf[2], buf[3]]);
+    let actual = checksum(index, buf);
+    if stored != actual {
+        return Err(format!("checksum {:06x} does not match the page, which sums to {:06x}", stored, actual));
+    }
+    Ok(())
+}
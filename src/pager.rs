@@ -0,0 +1,841 @@
+use crate::page::{PageError, PAGE_SIZE, PAGE_SIZE_OFFSET, VERSION_OFFSET, MIN_PAGE_SIZE, MAX_PAGE_SIZE, SECOND_META,
+    EPOCH_OFFSET, check_format, meta_epoch, stamp_epoch};
+use crate::byte::Decodable;
+use crate::journal;
+use crate::compress;
+use crate::metrics::Metrics;
+use crate::doublewrite::DoubleWrite;
+#[cfg(feature = "direct-io")]
+use crate::direct::DirectFile;
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs::File;
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// page buffers kept around for reuse, past this dropped pages just free theirs
+const POOL_LIMIT: usize = 32;
+/// how much the file grows by at a time, ahead of the pages written
+pub(crate) const EXTENT_SIZE: usize = 1 << 20;
+// the most bytes of adjacent dirty pages written out in one go
+const COALESCE_LIMIT: usize = 1 << 20;
+
+/// owns the tree file and the state shared by every page reading from or writing to it
+pub(crate) struct Pager {
+    file: File,
+    // xor of the crc of every page except the meta page, rolled forward as pages get written
+    digest: u32,
+    pool: Vec<Box<[u8]>>,
+    page_size: usize,
+    cache: Cache,
+    // the digest from before a transaction started writing, while it is being applied
+    deferred: Option<u32>,
+    // whether a page read without a checksum is corrupted, as it is in files created with them
+    checksums: bool,
+    // whether pages but the meta page get written compressed
+    #[cfg(feature = "lz4")]
+    compress: bool,
+    // compressed images on their way to or from the file
+    packed: Vec<u8>,
+    // adjacent dirty pages on their way to the file in one write
+    coalesced: Vec<u8>,
+    // the handle pages are read and written through when they bypass the os page cache
+    #[cfg(feature = "direct-io")]
+    direct: Option<DirectFile>,
+    // bumped whenever an internal page may read back otherwise than before, telling the
+    // internal pages a tree keeps pinned apart from stale ones
+    generation: u64,
+    // where page images go before they are written in place, when torn pages are guarded against
+    double_write: Option<DoubleWrite>,
+    // the epoch of the meta page last read or written, whose parity tells which of its two
+    // places holds it. 0 while the file keeps just the one, at page 0
+    meta_epoch: u64,
+    // what the pager did since the tree was opened
+    metrics: Metrics,
+}
+
+/// images of recently used pages, the least recently used one going first once it is full.
+/// written pages stay dirty in here until they are evicted or flushed
+struct Cache {
+    capacity: usize,
+    pages: HashMap<u32, Cached>,
+    // indexes of the cached pages written since they last reached the file, in file order
+    dirty: BTreeSet<u32>,
+    // last use of every cached page, oldest first
+    uses: BTreeMap<u64, u32>,
+    clock: u64,
+}
+
+struct Cached {
+    buf: Box<[u8]>,
+    used: u64,
+}
+
+impl Pager {
+    /// a pager without a page cache, see `set_cache_capacity`
+    pub fn new(file: File, page_size: usize) -> Self {
+        Pager {
+            file,
+            digest: 0,
+            pool: Vec::new(),
+            page_size,
+            cache: Cache {
+                capacity: 0,
+                pages: HashMap::new(),
+                dirty: BTreeSet::new(),
+                uses: BTreeMap::new(),
+                clock: 0,
+            },
+            deferred: None,
+            checksums: false,
+            #[cfg(feature = "lz4")]
+            compress: false,
+            packed: Vec::new(),
+            coalesced: Vec::new(),
+            #[cfg(feature = "direct-io")]
+            direct: None,
+            generation: 0,
+            double_write: None,
+            meta_epoch: 0,
+            metrics: Metrics::default(),
+        }
+    }
+
+    #[cfg(feature = "lz4")]
+    pub fn set_compression(&mut self, on: bool) {
+        self.compress = on;
+    }
+
+    /// reads and writes pages through a handle on the file at `path` that bypasses the os page
+    /// cache. the journal and everything else but pages still goes through the cache
+    #[cfg(feature = "direct-io")]
+    pub fn open_direct(&mut self, path: &Path, writable: bool) -> Result<()> {
+        self.direct = Some(DirectFile::open(path, writable, self.page_size)?);
+        Ok(())
+    }
+
+    /// writes every page image twice, first to the double-write buffer of the tree file at
+    /// `path`, synced, and only then in place, syncing the file after
+    pub fn open_double_write(&mut self, path: &Path) -> Result<()> {
+        self.flush_double_write()?;
+        self.double_write = Some(DoubleWrite::open(path)?);
+        Ok(())
+    }
+
+    pub fn require_checksums(&mut self, on: bool) {
+        self.checksums = on
+    }
+
+    pub fn checksums_required(&self) -> bool {
+        self.checksums
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// invalidates the internal pages pinned so far, for when one was written or taken out of the tree
+    pub fn bump_generation(&mut self) {
+        self.generation += 1;
+    }
+
+    /// keeps up to `capacity` page images in memory, 0 reading and writing every page
+    /// straight through to the file
+    pub fn set_cache_capacity(&mut self, capacity: usize) -> Result<()> {
+        self.cache.capacity = capacity;
+        while self.cache.pages.len() > capacity {
+            self.evict()?;
+        }
+        Ok(())
+    }
+
+    /// the page size recorded in the meta page of an existing tree file,
+    /// files from before page sizes were configurable have none and use the default.
+    /// fails for files that are not trees or have a newer format
+    pub fn stored_page_size(mut file: &File) -> Result<usize> {
+        let mut header = [0u8; VERSION_OFFSET + 4];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut header).map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => PageError::NotATree.into(),
+            _ => anyhow::Error::from(e)
+        })?;
+        if let Err(e) = check_format(&header) {
+            return second_meta_page_size(file).ok_or(e);
+        }
+        match u32::decode(&header[PAGE_SIZE_OFFSET..])?.0 as usize {
+            0 => Ok(PAGE_SIZE),
+            size => {
+                check_page_size(size)?;
+                Ok(size)
+            }
+        }
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// how many whole pages the file holds
+    pub fn file_pages(&self) -> Result<u64> {
+        Ok(self.file.metadata()?.len() / self.page_size as u64)
+    }
+
+    /// picks the newer whole one of the two meta pages of a file keeping two, to be read as
+    /// page 0 from here on. a file keeping one, or whose meta pages are both torn, reads page 0
+    pub fn find_meta(&mut self) -> Result<()> {
+        let mut buf = vec![0u8; self.page_size];
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_exact(&mut buf)?;
+        let first = meta_epoch(&buf);
+        if first == Some(0) || self.file_pages()? <= SECOND_META as u64 {
+            return Ok(());
+        }
+        self.file.seek(SeekFrom::Start(SECOND_META as u64 * self.page_size as u64))?;
+        self.file.read_exact(&mut buf)?;
+        let second = meta_epoch(&buf).filter(|e| *e != 0);
+        self.meta_epoch = match (first, second) {
+            (Some(first), Some(second)) => first.max(second),
+            (Some(epoch), None) | (None, Some(epoch)) => epoch,
+            (None, None) => 0
+        };
+        if first.is_none() && second.is_some() {
+            event!(warn, epoch = self.meta_epoch; "the meta page is torn, falling back on the second one");
+        }
+        Ok(())
+    }
+
+    // where page `index` is in the file. the meta page takes turns between its two places
+    // in files keeping two
+    fn offset(&self, index: u32) -> u64 {
+        let index = match index {
+            0 if self.meta_epoch % 2 == 1 => SECOND_META,
+            _ => index
+        };
+        index as u64 * self.page_size as u64
+    }
+
+    // the epoch the next write of the meta page image `buf` goes out with, 0 while the
+    // file keeps just the one
+    fn next_meta_epoch(&self, buf: &[u8]) -> u64 {
+        match u64::decode(&buf[EPOCH_OFFSET..]) {
+            Ok((0, _)) | Err(_) => 0,
+            Ok((epoch, _)) => epoch.max(self.meta_epoch) + 1
+        }
+    }
+
+    pub fn read_page(&mut self, index: u32, buf: &mut [u8]) -> Result<()> {
+        if let Some(cached) = self.cache.pages.get(&index) {
+            buf.copy_from_slice(&cached.buf);
+            self.touch(index);
+            self.metrics.cache_hits += 1;
+            return Ok(());
+        }
+        self.metrics.cache_misses += 1;
+        self.metrics.page_reads += 1;
+        let offset = self.offset(index);
+        #[cfg(feature = "direct-io")]
+        if let Some(direct) = self.direct.as_mut() {
+            direct.read(offset, buf)?;
+            compress::unpack(buf, &mut self.packed)?;
+            return self.cache_page(index, buf, false);
+        }
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(buf)?;
+        compress::unpack(buf, &mut self.packed)?;
+        self.cache_page(index, buf, false)
+    }
+
+    pub fn write_page(&mut self, index: u32, buf: &[u8]) -> Result<()> {
+        if self.cache.capacity > 0 || self.deferred.is_some() {
+            return self.cache_page(index, buf, true);
+        }
+        self.write_through(index, buf)?;
+        self.flush_double_write()
+    }
+
+    fn write_through(&mut self, index: u32, buf: &[u8]) -> Result<()> {
+        if index == 0 {
+            return self.write_meta(buf);
+        }
+        let offset = (index as usize * self.page_size) as u64;
+        #[cfg(feature = "lz4")]
+        if self.compress && index != 0 {
+            if let Some(len) = compress::pack(buf, &mut self.packed) {
+                self.write_image(offset, Some(len), buf)?;
+                let end = offset + self.page_size as u64;
+                if self.file.metadata()?.len() < end {
+                    // the file grows by a hole
+                    self.file.set_len(end)?;
+                } else {
+                    compress::punch_hole(&self.file, offset + len as u64, (self.page_size - len) as u64);
+                }
+                return Ok(());
+            }
+        }
+        self.write_image(offset, None, buf)
+    }
+
+    // a file keeping two meta pages writes the one holding the older epoch, so a write torn
+    // halfway leaves the other to fall back on
+    fn write_meta(&mut self, buf: &[u8]) -> Result<()> {
+        let epoch = self.next_meta_epoch(buf);
+        if epoch == 0 {
+            return self.write_image(0, None, buf);
+        }
+        let mut image = self.take_buf();
+        image.copy_from_slice(buf);
+        stamp_epoch(&mut image, epoch);
+        let slot = if epoch % 2 == 1 { SECOND_META } else { 0 };
+        let written = self.write_image(slot as u64 * self.page_size as u64, None, &image);
+        self.recycle_buf(image);
+        written?;
+        self.meta_epoch = epoch;
+        Ok(())
+    }
+
+    // writes `buf` at `offset`, or the first `packed` bytes of its compressed image. a direct
+    // write takes the whole page, zeros after the compressed image making up the rest
+    fn write_image(&mut self, offset: u64, packed: Option<usize>, buf: &[u8]) -> Result<()> {
+        self.metrics.page_writes += 1;
+        let compressed = std::mem::take(&mut self.packed);
+        let image = match packed {
+            Some(len) => &compressed[..len],
+            None => buf
+        };
+        let written = match self.double_write.as_mut() {
+            Some(double_write) => {
+                double_write.stage(offset, image);
+                Ok(())
+            }
+            None => self.write_at(offset, image)
+        };
+        self.packed = compressed;
+        written
+    }
+
+    // writes `image` in place at `offset`, past the double-write buffer
+    fn write_at(&mut self, offset: u64, image: &[u8]) -> Result<()> {
+        #[cfg(feature = "direct-io")]
+        if let Some(direct) = self.direct.as_mut() {
+            return direct.write(offset, image);
+        }
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(image)?;
+        Ok(())
+    }
+
+    // writes the images staged in the double-write buffer in place, once the buffer is on disk
+    fn flush_double_write(&mut self) -> Result<()> {
+        let mut double_write = match self.double_write.take() {
+            Some(double_write) if !double_write.is_empty() => double_write,
+            double_write => {
+                self.double_write = double_write;
+                return Ok(());
+            }
+        };
+        let flushed = double_write.flush(|offset, image| self.write_at(offset, image))
+            .and_then(|_| Ok(self.file.sync_data()?));
+        self.double_write = Some(double_write);
+        flushed?;
+        self.metrics.fsyncs += 2;
+        Ok(())
+    }
+
+    /// writes every dirty cached page out to the file, nothing while writes are deferred
+    pub fn flush(&mut self) -> Result<()> {
+        if self.deferred.is_some() {
+            return Ok(());
+        }
+        self.write_dirty()
+    }
+
+    fn write_dirty(&mut self) -> Result<()> {
+        let dirty = self.dirty_pages();
+        self.write_cached_pages(&dirty)?;
+        if !dirty.is_empty() {
+            event!(debug, pages = dirty.len(); "wrote the dirty cached pages out");
+        }
+        Ok(())
+    }
+
+    // writes the cached pages of `indexes`, in ascending order, out to the file. runs of
+    // adjacent pages go out as one write, unless pages get compressed or bypass the os page
+    // cache, which write them one at a time
+    fn write_cached_pages(&mut self, indexes: &[u32]) -> Result<()> {
+        if !self.coalesces() {
+            for index in indexes {
+                self.write_cached(*index)?;
+            }
+            return self.flush_double_write();
+        }
+        let run_pages = (COALESCE_LIMIT / self.page_size).max(1);
+        let mut start = 0;
+        while start < indexes.len() {
+            let mut end = start + 1;
+            while end < indexes.len() && end - start < run_pages && indexes[end] == indexes[end - 1] + 1 {
+                end += 1;
+            }
+            self.write_run(&indexes[start..end])?;
+            start = end;
+        }
+        Ok(())
+    }
+
+    // pages written one after another at the same offsets as written together. pages going
+    // through the double-write buffer already go out to it in one write
+    fn coalesces(&self) -> bool {
+        if self.double_write.is_some() {
+            return false;
+        }
+        #[cfg(feature = "lz4")]
+        if self.compress {
+            return false;
+        }
+        #[cfg(feature = "direct-io")]
+        if self.direct.is_some() {
+            return false;
+        }
+        true
+    }
+
+    // writes the cached pages of `run`, adjacent in the file, with a single seek and write
+    fn write_run(&mut self, run: &[u32]) -> Result<()> {
+        if run.len() == 1 {
+            return self.write_cached(run[0]);
+        }
+        self.coalesced.clear();
+        for index in run {
+            self.coalesced.extend_from_slice(&self.cache.pages[index].buf);
+        }
+        self.file.seek(SeekFrom::Start(run[0] as u64 * self.page_size as u64))?;
+        self.file.write_all(&self.coalesced)?;
+        self.metrics.page_writes += run.len() as u64;
+        for index in run {
+            self.cache.dirty.remove(index);
+        }
+        Ok(())
+    }
+
+    fn write_cached(&mut self, index: u32) -> Result<()> {
+        let buf = std::mem::take(&mut self.cache.pages.get_mut(&index).unwrap().buf);
+        let written = self.write_through(index, &buf);
+        self.cache.pages.get_mut(&index).unwrap().buf = buf;
+        written?;
+        self.cache.dirty.remove(&index);
+        Ok(())
+    }
+
+    /// waits for everything written to the file to reach the disk
+    pub fn sync_file(&mut self) -> Result<()> {
+        self.file.sync_data()?;
+        self.metrics.fsyncs += 1;
+        Ok(())
+    }
+
+    /// keeps every page written from here on in memory, however many there are, until
+    /// `commit_deferred` or `discard_deferred`
+    pub fn defer(&mut self) -> Result<()> {
+        self.write_dirty()?;
+        self.deferred = Some(self.digest);
+        Ok(())
+    }
+
+    /// writes the deferred pages out as one: their old images go to `journal` first, so a
+    /// crash on the way leaves the file to be rolled back on the next open. fails still deferring
+    pub fn commit_deferred(&mut self, journal: &Path) -> Result<()> {
+        let mut dirty = self.dirty_pages();
+        // the meta page goes out to the place holding the older of its two images
+        if let Some(0) = dirty.first() {
+            if self.next_meta_epoch(&self.cache.pages[&0].buf) % 2 == 1 {
+                dirty[0] = SECOND_META;
+            }
+        }
+        journal::write(journal, &mut self.file, self.page_size, &dirty)?;
+        self.metrics.fsyncs += 1;
+        self.write_dirty()?;
+        self.file.sync_all()?;
+        self.metrics.fsyncs += 1;
+        journal::remove(journal)?;
+        self.deferred = None;
+        while self.cache.pages.len() > self.cache.capacity {
+            self.evict()?;
+        }
+        Ok(())
+    }
+
+    /// forgets every cached page, the deferred ones included, along with the digest they rolled
+    /// forward. whatever of them reached the file is left to `roll_back`
+    pub fn discard_deferred(&mut self) {
+        self.bump_generation();
+        if let Some(digest) = self.deferred.take() {
+            self.digest = digest;
+        }
+        self.cache.uses.clear();
+        self.cache.dirty.clear();
+        let pages: Vec<Cached> = self.cache.pages.drain().map(|(_, c)| c).collect();
+        for cached in pages {
+            self.recycle_buf(cached.buf);
+        }
+    }
+
+    /// indexes of the pages written since they last reached the file
+    pub fn dirty_pages(&self) -> Vec<u32> {
+        self.cache.dirty.iter().copied().collect()
+    }
+
+    /// drops whatever was written to page `index` and not yet written out, the file keeping
+    /// the image it holds
+    pub fn forget_page(&mut self, index: u32) -> Result<()> {
+        let cached = match self.cache.pages.remove(&index) {
+            Some(cached) => cached,
+            None => return Ok(())
+        };
+        self.bump_generation();
+        self.cache.uses.remove(&cached.used);
+        if self.cache.dirty.remove(&index) {
+            let disk_crc = self.file_crc(index)?;
+            self.roll_digest(page_crc(index, &cached.buf), disk_crc);
+        }
+        self.recycle_buf(cached.buf);
+        Ok(())
+    }
+
+    /// crc of the latest image of page `index`, cached or in the file
+    pub fn image_crc(&mut self, index: u32) -> Result<u32> {
+        match self.cache.pages.get(&index) {
+            Some(cached) => Ok(page_crc(index, &cached.buf)),
+            None => self.file_crc(index)
+        }
+    }
+
+    // past the end of the file there is nothing for the digest to account for
+    fn file_crc(&mut self, index: u32) -> Result<u32> {
+        let offset = index as u64 * self.page_size as u64;
+        if offset >= self.file.metadata()?.len() {
+            return Ok(0);
+        }
+        let mut on_disk = self.take_buf();
+        self.metrics.page_reads += 1;
+        self.file.seek(SeekFrom::Start(offset))?;
+        let read = self.file.read_exact(&mut on_disk).map_err(anyhow::Error::from)
+            .and_then(|_| compress::unpack(&mut on_disk, &mut self.packed));
+        let crc = page_crc(index, &on_disk);
+        self.recycle_buf(on_disk);
+        read?;
+        Ok(crc)
+    }
+
+    /// writes every deferred page out ahead of the meta page, syncing the file after each,
+    /// so the meta page only ever points at pages already on disk. later writes stay deferred
+    pub fn write_ordered(&mut self) -> Result<()> {
+        let dirty = self.dirty_pages();
+        let (meta, pages) = match dirty.split_first() {
+            Some((0, pages)) => (true, pages),
+            _ => (false, &dirty[..])
+        };
+        self.write_cached_pages(pages)?;
+        self.sync_file()?;
+        if meta {
+            self.write_cached(0)?;
+            self.flush_double_write()?;
+            self.sync_file()?;
+        }
+        Ok(())
+    }
+
+    /// writes the deferred pages out and goes back to writing pages as the cache evicts them
+    pub fn stop_deferring(&mut self) -> Result<()> {
+        self.write_ordered()?;
+        self.deferred = None;
+        while self.cache.pages.len() > self.cache.capacity {
+            self.evict()?;
+        }
+        Ok(())
+    }
+
+    /// grows the file to room for `pages` pages of zeros, setting its blocks aside in one go
+    /// where the file system can. a file already as long is left alone
+    pub fn preallocate(&mut self, pages: u32) -> Result<()> {
+        let len = pages as u64 * self.page_size as u64;
+        let file_len = self.file.metadata()?.len();
+        if file_len >= len {
+            return Ok(());
+        }
+        #[cfg(all(target_os = "linux", any(feature = "lz4", feature = "direct-io")))]
+        {
+            use std::os::unix::io::AsRawFd;
+            let done = unsafe {
+                libc::fallocate(self.file.as_raw_fd(), 0, file_len as libc::off_t, (len - file_len) as libc::off_t)
+            };
+            if done == 0 {
+                return Ok(());
+            }
+        }
+        self.file.set_len(len)?;
+        Ok(())
+    }
+
+    /// cuts the file down to its first `pages` pages, and the cache along with it
+    pub fn truncate(&mut self, pages: u32) -> Result<()> {
+        let cut: Vec<u32> = self.cache.pages.keys().copied().filter(|i| *i >= pages).collect();
+        for index in cut {
+            self.forget_page(index)?;
+        }
+        self.bump_generation();
+        self.file.set_len(pages as u64 * self.page_size as u64)?;
+        self.file.sync_all()?;
+        self.metrics.fsyncs += 1;
+        Ok(())
+    }
+
+    /// puts back the pages a journal left by a commit cut short recorded
+    pub fn roll_back(&mut self, journal: &Path) -> Result<()> {
+        self.bump_generation();
+        journal::recover(journal, &mut self.file).map(|_| ())
+    }
+
+    fn cache_page(&mut self, index: u32, buf: &[u8], dirty: bool) -> Result<()> {
+        if self.cache.capacity == 0 && self.deferred.is_none() {
+            return Ok(());
+        }
+        if let Some(cached) = self.cache.pages.get_mut(&index) {
+            cached.buf.copy_from_slice(buf);
+            if dirty {
+                self.cache.dirty.insert(index);
+            }
+            self.touch(index);
+            return Ok(());
+        }
+        if self.cache.pages.len() >= self.cache.capacity {
+            self.evict()?;
+        }
+        let mut image = self.take_buf();
+        image.copy_from_slice(buf);
+        self.cache.clock += 1;
+        self.cache.uses.insert(self.cache.clock, index);
+        self.cache.pages.insert(index, Cached { buf: image, used: self.cache.clock });
+        if dirty {
+            self.cache.dirty.insert(index);
+        }
+        Ok(())
+    }
+
+    fn touch(&mut self, index: u32) {
+        let cached = self.cache.pages.get_mut(&index).unwrap();
+        self.cache.uses.remove(&cached.used);
+        self.cache.clock += 1;
+        cached.used = self.cache.clock;
+        self.cache.uses.insert(cached.used, index);
+    }
+
+    /// drops the least recently used page, writing it out first if it is dirty. deferred writes
+    /// stay put, the cache growing past its capacity when it holds nothing else
+    fn evict(&mut self) -> Result<()> {
+        let oldest = if self.deferred.is_some() {
+            let dirty = &self.cache.dirty;
+            self.cache.uses.iter().find(|(_, i)| !dirty.contains(*i)).map(|(used, i)| (*used, *i))
+        } else {
+            self.cache.uses.first_key_value().map(|(used, i)| (*used, *i))
+        };
+        let index = match oldest {
+            Some((used, index)) => {
+                self.cache.uses.remove(&used);
+                index
+            }
+            None => return Ok(())
+        };
+        let cached = self.cache.pages.remove(&index).unwrap();
+        let dirty = self.cache.dirty.remove(&index);
+        let written = match dirty {
+            true => self.write_through(index, &cached.buf).and_then(|_| self.flush_double_write()),
+            false => Ok(())
+        };
+        if written.is_err() {
+            // still the only copy of the page, keep it around
+            self.cache.uses.insert(cached.used, index);
+            self.cache.pages.insert(index, cached);
+            self.cache.dirty.insert(index);
+            return written;
+        }
+        self.recycle_buf(cached.buf);
+        Ok(())
+    }
+
+    /// a page sized buffer, holding whatever the page that used it last left behind
+    pub fn take_buf(&mut self) -> Box<[u8]> {
+        self.pool.pop().unwrap_or_else(|| vec![0; self.page_size].into_boxed_slice())
+    }
+
+    pub fn recycle_buf(&mut self, buf: Box<[u8]>) {
+        if buf.len() == self.page_size && self.pool.len() < POOL_LIMIT {
+            self.pool.push(buf);
+        }
+    }
+
+    pub fn metrics(&self) -> Metrics {
+        self.metrics
+    }
+
+    pub fn reset_metrics(&mut self) {
+        self.metrics = Metrics::default()
+    }
+
+    /// page splits happen over in the tree, which counts them here with the rest
+    pub fn count_split(&mut self) {
+        self.metrics.splits += 1
+    }
+
+    pub fn digest(&self) -> u32 {
+        self.digest
+    }
+
+    pub fn set_digest(&mut self, digest: u32) {
+        self.digest = digest
+    }
+
+    /// swaps the contribution of a page's previous on-disk image for its new one
+    pub fn roll_digest(&mut self, old_crc: u32, new_crc: u32) {
+        self.digest ^= old_crc ^ new_crc
+    }
+
+    /// rereads pages `1..total_pages` but the spare ones from disk and folds them into a fresh digest
+    pub fn compute_digest(&mut self, total_pages: u32, spares: &[u32]) -> Result<u32> {
+        let mut buf = vec![0u8; self.page_size];
+        let mut digest = 0;
+        for index in (1..total_pages).filter(|i| !spares.contains(i)) {
+            self.read_page(index, &mut buf)?;
+            digest ^= page_crc(index, &buf);
+        }
+        Ok(digest)
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        self.flush().unwrap();
+    }
+}
+
+pub(crate) fn check_page_size(page_size: usize) -> Result<()> {
+    if page_size.is_power_of_two() && (MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size) {
+        Ok(())
+    } else {
+        Err(anyhow!("page size {} is not a power of two between {} and {}", page_size, MIN_PAGE_SIZE, MAX_PAGE_SIZE))
+    }
+}
+
+/// the meta page stores the digest itself, so it never takes part in it. nor does a page of
+/// nothing but zeros, which the file grew by ahead of writing it, like a page past the end
+pub(crate) fn page_crc(index: u32, buf: &[u8]) -> u32 {
+    if index == 0 || buf.iter().all(|b| *b == 0) {
+        return 0;
+    }
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&index.to_be_bytes());
+    hasher.update(buf);
+    hasher.finalize()
+}
+
+/// the page size of a file whose page 0 no longer tells it, read from the second meta page if
+/// the file keeps one, the page size a whole meta page records matching where it was found
+fn second_meta_page_size(mut file: &File) -> Option<usize> {
+    let sizes = (MIN_PAGE_SIZE.trailing_zeros()..=MAX_PAGE_SIZE.trailing_zeros()).map(|shift| 1usize << shift);
+    for size in sizes {
+        let mut buf = vec![0u8; size];
+        file.seek(SeekFrom::Start(SECOND_META as u64 * size as u64)).ok()?;
+        if file.read_exact(&mut buf).is_err() {
+            return None;
+        }
+        let recorded = u32::decode(&buf[PAGE_SIZE_OFFSET..]).ok()?.0 as usize;
+        if recorded == size && meta_epoch(&buf).is_some_and(|e| e != 0) && check_format(&buf).is_ok() {
+            return Some(size);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pager;
+    use crate::page::{Page, PageType, Slot, Stat, PAGE_SIZE, SECOND_META, meta_epoch};
+    use crate::BTree;
+    use std::fs::{self, OpenOptions};
+    use std::sync::{Arc, Mutex};
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("btree-pager-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn a_torn_newer_meta_page_falls_back_on_the_older_epoch() {
+        let path = temp_path("torn-meta");
+        let fill_factor;
+        {
+            let mut tree = BTree::<u32, u64>::open_or_create(&path).unwrap();
+            for i in 0..1000u32 {
+                tree.set(&i, &(i as u64)).unwrap();
+            }
+            tree.flush().unwrap();
+            fill_factor = tree.fill_factor();
+            // a write of nothing but the meta page, the older one keeping the rest as it is
+            tree.set_fill_factor(90).unwrap();
+        }
+        let mut file = fs::read(&path).unwrap();
+        let first = meta_epoch(&file[..PAGE_SIZE]).unwrap();
+        let second = meta_epoch(&file[PAGE_SIZE..2 * PAGE_SIZE]).unwrap();
+        let newer = if first > second { 0 } else { SECOND_META as usize };
+        file[newer * PAGE_SIZE + PAGE_SIZE / 2] ^= 0xff;
+        fs::write(&path, &file).unwrap();
+
+        let mut tree = BTree::<u32, u64>::open(&path).unwrap();
+        assert_eq!(tree.fill_factor(), fill_factor);
+        assert_eq!(tree.len(), 1000);
+        assert!(tree.verify().unwrap().is_empty());
+        // the torn place is the one the next write goes to
+        tree.set_fill_factor(80).unwrap();
+        drop(tree);
+        let tree = BTree::<u32, u64>::open(&path).unwrap();
+        assert_eq!(tree.fill_factor(), 80);
+        assert_eq!(tree.iter().count(), 1000);
+        drop(tree);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_file_keeping_one_meta_page_opens_and_keeps_page_1() {
+        let path = temp_path("one-meta");
+        // laid out the way files from before the second meta page were: the root right after it
+        {
+            let file = OpenOptions::new().create_new(true).read(true).write(true).open(&path).unwrap();
+            let fd = Arc::new(Mutex::new(Pager::new(file, PAGE_SIZE)));
+            let mut meta_page = Page::<u32, u64>::new(fd.clone(), 0, PageType::META).unwrap();
+            meta_page.set_total_page(2);
+            meta_page.set_root_index(1);
+            meta_page.set_stat(Stat::Entries, 10);
+            let mut root = Page::<u32, u64>::new(fd.clone(), 1, PageType::LEAF).unwrap();
+            root.set_item_count(10).unwrap();
+            for i in 0..10usize {
+                root.set_key_at(i, &(i as u32)).unwrap();
+                root.set_value_at(i, &Slot::Value(&(i as u64 * 3))).unwrap();
+            }
+        }
+        {
+            let mut tree = BTree::<u32, u64>::open(&path).unwrap();
+            assert_eq!(tree.iter().collect::<Vec<(u32, u64)>>(), (0..10).map(|i| (i, i as u64 * 3)).collect::<Vec<_>>());
+            // enough writes to go through the meta page many times and split the root
+            for i in 10..2000u32 {
+                tree.set(&i, &(i as u64 * 3)).unwrap();
+            }
+            assert_eq!(tree.meta_page.as_ref().unwrap().second_meta(), None);
+        }
+        let tree = BTree::<u32, u64>::open(&path).unwrap();
+        assert_eq!(tree.len(), 2000);
+        assert_eq!(tree.get(&5), Some(15));
+        assert_eq!(tree.iter().count(), 2000);
+        assert!(tree.verify().unwrap().is_empty());
+        drop(tree);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::compress;
+use crate::page::Page;
+use crate::pager::Pager;
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+/// reads pages a scan is about to visit on a helper thread, through a file handle of its own
+pub(crate) struct Prefetcher {
+    depth: usize,
+    requests: Sender<u32>,
+    pages: Receiver<(u32, io::Result<Box<[u8]>>)>,
+    // asked for and not handed out yet, the image being None until it arrives
+    pending: HashMap<u32, Option<Box<[u8]>>>,
+}
+
+impl Prefetcher {
+    pub fn spawn(path: &Path, page_size: usize, depth: usize) -> Result<Self> {
+        let mut file = File::open(path)?;
+        let (requests, rx) = mpsc::channel::<u32>();
+        let (tx, pages) = mpsc::channel();
+        thread::spawn(move || {
+            let mut packed = Vec::new();
+            for index in rx {
+                let mut buf = vec![0; page_size].into_boxed_slice();
+                let read = file.seek(SeekFrom::Start(index as u64 * page_size as u64))
+                    .and_then(|_| file.read_exact(&mut buf))
+                    .and_then(|_| compress::unpack(&mut buf, &mut packed).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string())))
+                    .map(|_| buf);
+                // the scan is gone
+                if tx.send((index, read)).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Prefetcher {
+            depth,
+            requests,
+            pages,
+            pending: HashMap::new(),
+        })
+    }
+
+    /// how many pages to keep in flight ahead of the scan
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn request(&mut self, indexes: impl Iterator<Item = u32>) {
+        for index in indexes {
+            if !self.pending.contains_key(&index) && self.requests.send(index).is_ok() {
+                self.pending.insert(index, None);
+            }
+        }
+    }
+
+    /// hands out a page the helper was asked for, waiting on it if need be,
+    /// and reads any other page right away
+    pub fn load<K, V>(&mut self, fd: &Arc<Mutex<Pager>>, index: u32) -> Result<Page<K, V>>
+        where
+            K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+            V: Encodable + Decodable + BinSizer + Debug
+    {
+        loop {
+            match self.pending.get(&index) {
+                Some(Some(_)) => {
+                    let buf = self.pending.remove(&index).unwrap().unwrap();
+                    return Page::from_buf(fd.clone(), index, buf);
+                }
+                Some(None) => {}
+                None => break
+            }
+            match self.pages.recv() {
+                Ok((i, Ok(buf))) => {
+                    if let Some(slot) = self.pending.get_mut(&i) {
+                        *slot = Some(buf);
+                    }
+                }
+                // whoever wants this page reads it directly
+                Ok((i, Err(_))) => {
+                    self.pending.remove(&i);
+                }
+                Err(_) => {
+                    self.pending.clear();
+                }
+            }
+        }
+        Page::load(fd.clone(), index)
+    }
+}
@@ -0,0 +1,152 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::page::{Page, PageType, Pos, corrupted};
+use crate::{BTree, MAX_DEPTH};
+use anyhow::Result;
+use std::fmt::Debug;
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    /// how many keys of the tree sort before `key`, whether or not `key` is in it. 0 as well
+    /// when a page on the way is corrupted, which `try_rank` tells apart
+    pub fn rank(&self, key: &K) -> usize {
+        self.try_rank(key).unwrap_or(0)
+    }
+
+    /// found on the way down to `key`'s leaf from the counts internal pages keep of the
+    /// entries under each child. files from before they kept any count the leaves left of it
+    pub fn try_rank(&self, key: &K) -> Result<usize, BTreeError> {
+        let root = self.root_page.as_ref().unwrap();
+        let mut rank = 0;
+        let mut next = None;
+        for _ in 0..=MAX_DEPTH {
+            let p = next.as_ref().unwrap_or(root);
+            if p.page_type == PageType::LEAF {
+                let at = match p.find(key) {
+                    None => 0,
+                    Some((i, Pos::Right)) => i + 1,
+                    Some((i, _)) => i
+                };
+                return Ok(rank as usize + at);
+            }
+            let slot = p.child_slot(key);
+            for i in 0..slot {
+                rank += self.entries_under(p, i)?;
+            }
+            let child = Page::<K, V>::load_node(self.fd.clone(), p.ptr_at(slot).unwrap())?;
+            next = Some(child);
+        }
+        Err(corrupted(root.index, "the tree loops back on itself".to_owned()).into())
+    }
+
+    /// the entry `n` others sort before, the first at 0, None past the last. None as well
+    /// when a page on the way is corrupted, which `try_select` tells apart
+    pub fn select(&self, n: usize) -> Option<(K, V)> {
+        self.try_select(n).ok().flatten()
+    }
+
+    /// goes down to the entry by the counts internal pages keep, like `try_rank`
+    pub fn try_select(&self, n: usize) -> Result<Option<(K, V)>, BTreeError> {
+        let root = self.root_page.as_ref().unwrap();
+        let mut n = n as u64;
+        let mut next = None;
+        for _ in 0..=MAX_DEPTH {
+            let p = next.as_ref().unwrap_or(root);
+            if p.page_type == PageType::LEAF {
+                if n >= p.item_count() as u64 {
+                    return Ok(None);
+                }
+                let i = n as usize;
+                let key = p.key_at(i).ok_or_else(|| corrupted(p.index, format!("key {} does not decode", i)))?;
+                let value = V::decode(p.value_bytes(i, &mut Vec::new())?)?.0;
+                return Ok(Some((key, value)));
+            }
+            // past the last entry the descent keeps right, down to a leaf too short for it
+            let mut slot = 0;
+            while slot < p.item_count() {
+                let under = self.entries_under(p, slot)?;
+                if n < under {
+                    break;
+                }
+                n -= under;
+                slot += 1;
+            }
+            let child = Page::<K, V>::load_node(self.fd.clone(), p.ptr_at(slot).unwrap())?;
+            next = Some(child);
+        }
+        Err(corrupted(root.index, "the tree loops back on itself".to_owned()).into())
+    }
+
+    // how many entries are under child `i` of internal page `p`, counted leaf by leaf on
+    // pages keeping no count of them
+    fn entries_under(&self, p: &Page<K, V>, i: usize) -> Result<u64> {
+        match p.count_at(i) {
+            Some(count) => Ok(count),
+            None => self.count_entries(&Page::<K, V>::load_node(self.fd.clone(), p.ptr_at(i).unwrap())?)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BTree;
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    // every rank and selection the tree gives agrees with the map, at the keys and between them
+    fn check_against(tree: &BTree<u32, u64>, model: &BTreeMap<u32, u64>) {
+        assert_eq!(tree.len(), model.len() as u64);
+        for (n, (k, v)) in model.iter().enumerate() {
+            assert_eq!(tree.try_select(n).unwrap(), Some((*k, *v)), "select {}", n);
+            assert_eq!(tree.try_rank(k).unwrap(), n, "rank of {}", k);
+            assert_eq!(tree.try_rank(&(k + 1)).unwrap(), n + 1, "rank of {}", k + 1);
+        }
+        assert_eq!(tree.try_select(model.len()).unwrap(), None);
+        assert_eq!(tree.rank(&0), 0);
+    }
+
+    #[test]
+    fn rank_and_select_agree_with_a_model_through_sets_deletes_and_reopening() {
+        let path = std::env::temp_dir().join(format!("btree-rank-test-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut model = BTreeMap::new();
+        let mut seed = 7u64;
+        let mut next = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) as u32
+        };
+        {
+            let mut tree = BTree::<u32, u64>::open_or_create(&path).unwrap();
+            for round in 0..4 {
+                for _ in 0..3000 {
+                    let k = next() % 20000;
+                    tree.set(&k, &(k as u64 + round)).unwrap();
+                    model.insert(k, k as u64 + round);
+                }
+                // deleting most of a stretch of keys empties and merges pages
+                for _ in 0..2000 {
+                    let k = next() % 20000;
+                    let low = (round as u32) * 5000;
+                    let k = if k % 2 == 0 { low + k % 5000 } else { k };
+                    assert_eq!(tree.remove(&k).unwrap(), model.remove(&k));
+                }
+                check_against(&tree, &model);
+            }
+            assert!(tree.verify().unwrap().is_empty());
+        }
+        let mut tree = BTree::<u32, u64>::open(&path).unwrap();
+        check_against(&tree, &model);
+        tree.compact().unwrap();
+        check_against(&tree, &model);
+        drop(tree);
+        fs::remove_file(&path).unwrap();
+
+        let (tree, _) = BTree::bulk_load(&path, model.iter().map(|(k, v)| (*k, *v))).unwrap();
+        check_against(&tree, &model);
+        drop(tree);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,125 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::page::{Page, PageType, corrupted};
+use crate::BTree;
+use anyhow::Result;
+use std::fmt::Debug;
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    /// finds the pages neither the tree nor the free list reaches, such as the ones a split
+    /// left behind when it was cut short, and puts them on the free list.
+    /// returns how many pages were reclaimed, each `page_size()` bytes
+    pub fn reclaim_orphans(&mut self) -> Result<u32, BTreeError> {
+        if self.read_only {
+            return Err(BTreeError::ReadOnly { path: self.path.clone() });
+        }
+        let orphans = self.find_orphans()?;
+        for index in orphans.iter() {
+            self.free_page(*index)?;
+        }
+        self.sync()?;
+        self.finish_write()?;
+        Ok(orphans.len() as u32)
+    }
+
+    /// the same as `reclaim_orphans`, under the name garbage collectors go by
+    pub fn gc(&mut self) -> Result<u32, BTreeError> {
+        self.reclaim_orphans()
+    }
+
+    fn find_orphans(&self) -> Result<Vec<u32>> {
+        let meta_page = self.meta_page.as_ref().unwrap();
+        let mut reached = self.live_pages()?;
+        // spares are kept out of the free list on purpose
+        let overflow = self.cow.iter().flat_map(|cow| cow.overflow.iter());
+        for index in meta_page.spares().iter().chain(overflow) {
+            if let Some(reached) = reached.get_mut(*index as usize) {
+                *reached = true;
+            }
+        }
+
+        let mut index = meta_page.free_head();
+        // a looping free list stops at the first page seen twice
+        while index != 0 && !reached.get(index as usize).copied().unwrap_or(true) {
+            reached[index as usize] = true;
+            let page = Page::<K, V>::load(self.fd.clone(), index)?;
+            if page.page_type != PageType::FREE {
+                return Err(corrupted(index, format!("a {:?} page is on the free list", page.page_type)));
+            }
+            index = page.next_free();
+        }
+
+        Ok((0..reached.len() as u32).filter(|i| !reached[*i as usize]).collect())
+    }
+
+    /// which of the file's pages hold something: the meta pages, the pages of the file's own
+    /// tree and of the named trees along with their overflow chains, and the catalog
+    pub(crate) fn live_pages(&self) -> Result<Vec<bool>> {
+        let meta_page = self.meta_page.as_ref().unwrap();
+        let mut reached = vec![false; meta_page.total_pages() as usize];
+        reached[0] = true;
+        if let Some(second) = meta_page.second_meta() {
+            reached[second as usize] = true;
+        }
+
+        // the root may hold changes not synced yet, so its pointers are taken from memory
+        let root = self.root_page.as_ref().unwrap();
+        reached[root.index as usize] = true;
+        let mut todo = child_ptrs(root);
+        let mut chains = chain_ptrs(root);
+        // the named trees are walked from the roots the catalog lists
+        let catalog = meta_page.catalog_index();
+        if catalog != 0 {
+            reached[catalog as usize] = true;
+            let p = Page::<K, V>::load(self.fd.clone(), catalog)?;
+            if p.page_type != PageType::CATALOG {
+                return Err(corrupted(catalog, format!("a {:?} page in place of the catalog", p.page_type)));
+            }
+            todo.extend((0..p.catalog_len()).map(|i| p.catalog_root(i)));
+        }
+        while let Some(index) = todo.pop() {
+            if reached.get(index as usize).copied().unwrap_or(true) {
+                continue;
+            }
+            reached[index as usize] = true;
+            let p = Page::<K, V>::load_node(self.fd.clone(), index)?;
+            todo.extend(child_ptrs(&p));
+            chains.extend(chain_ptrs(&p));
+        }
+        for first in chains {
+            for index in self.chain_pages(first)? {
+                if let Some(reached) = reached.get_mut(index as usize) {
+                    *reached = true;
+                }
+            }
+        }
+        Ok(reached)
+    }
+}
+
+pub(crate) fn child_ptrs<K, V>(p: &Page<K, V>) -> Vec<u32>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    match p.page_type {
+        PageType::INTERNAL => (0..=p.item_count()).map(|i| p.ptr_at(i).unwrap()).collect(),
+        _ => Vec::new()
+    }
+}
+
+/// the first pages of the overflow chains a leaf's values were spilled to
+pub(crate) fn chain_ptrs<K, V>(p: &Page<K, V>) -> Vec<u32>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    match p.page_type {
+        PageType::LEAF => (0..p.item_count()).filter_map(|i| p.spilled_at(i)).collect(),
+        _ => Vec::new()
+    }
+}
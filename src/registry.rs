@@ -0,0 +1,30 @@
+use crate::page::PageError;
+use anyhow::Result;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// canonical paths of the tree files this process has open for writing
+static OPEN: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// a tree file held open for writing, released when dropped. two writable trees over one file
+/// would each cache their own root and meta page and write over each other's changes
+pub(crate) struct Registration(PathBuf);
+
+impl Registration {
+    pub fn acquire(path: &Path) -> Result<Self> {
+        let path = path.canonicalize()?;
+        // a panic elsewhere while holding the lock leaves the set itself intact
+        let mut open = OPEN.lock().unwrap_or_else(|e| e.into_inner());
+        if !open.insert(path.clone()) {
+            return Err(PageError::AlreadyOpen { path }.into());
+        }
+        Ok(Registration(path))
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        OPEN.lock().unwrap_or_else(|e| e.into_inner()).remove(&self.0);
+    }
+}
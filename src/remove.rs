@@ -0,0 +1,162 @@
+use crate::byte::{Encodable, Decodable, BinSizer, common_len};
+use crate::error::BTreeError;
+use crate::page::{Page, PageError, PageType, Pos, Stat, corrupted};
+use crate::{BTree, MAX_DEPTH};
+use anyhow::Result;
+use std::fmt::Debug;
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    /// takes `key` out of the tree and returns the value it had. a leaf left empty goes on the
+    /// free list, so deleted pages get handed out again instead of the file growing
+    pub fn remove(&mut self, key: &K) -> Result<Option<V>, BTreeError> {
+        if self.read_only {
+            return Err(BTreeError::ReadOnly { path: self.path.clone() });
+        }
+        let value = self.delete(key)?;
+        if value.is_some() {
+            self.sync()?;
+            self.finish_write()?;
+        }
+        Ok(value)
+    }
+
+    /// `remove` short of writing the pages out
+    pub(crate) fn delete(&mut self, key: &K) -> Result<Option<V>> {
+        // every page under the root on the way to the leaf, with its slot in the parent's pointers
+        let mut path: Vec<(Page<K, V>, usize)> = Vec::new();
+        loop {
+            let p = path.last().map(|(p, _)| p).unwrap_or_else(|| self.root_page.as_ref().unwrap());
+            if p.page_type != PageType::INTERNAL {
+                break;
+            }
+            let slot = match p.find(key) {
+                Some((i, Pos::Left)) => i,
+                Some((i, _)) => i + 1,
+                None => panic!("impossible for an empty internal page")
+            };
+            let index = p.ptr_at(slot).unwrap();
+            if path.len() == MAX_DEPTH {
+                return Err(corrupted(index, "the tree loops back on itself".to_owned()));
+            }
+            path.push((Page::<K, V>::load_node(self.fd.clone(), index)?, slot));
+        }
+
+        let (value, spilled, emptied) = {
+            let leaf = match path.last_mut() {
+                Some((p, _)) => p,
+                None => self.root_page.as_mut().unwrap()
+            };
+            let i = match leaf.find(key) {
+                Some((i, Pos::Current)) => i,
+                _ => return Ok(None)
+            };
+            let value = V::decode(leaf.value_bytes(i, &mut Vec::new())?)?.0;
+            let spilled = leaf.spilled_at(i);
+            leaf.remove_entry(i)?;
+            (value, spilled, leaf.item_count() == 0)
+        };
+        self.count_along(key, path.iter_mut().map(|(p, _)| p), -1)?;
+        // an empty root leaf is just an empty tree
+        if emptied && !path.is_empty() {
+            self.unlink(path)?;
+        } else {
+            // the pages write themselves back as they drop, ahead of finishing the write
+            drop(path);
+        }
+        if let Some(first) = spilled {
+            self.free_chain(first)?;
+        }
+        self.bump_stat(Stat::Deletes);
+        self.touch(Stat::LastModified);
+        Ok(Some(value))
+    }
+
+    /// frees the empty leaf on top of `path` and drops its pointer from the parent. an internal
+    /// page left without keys borrows one from a sibling, or merges into it when the sibling
+    /// has room, and so on up; a root without keys hands over to its only child
+    fn unlink(&mut self, mut path: Vec<(Page<K, V>, usize)>) -> Result<()> {
+        let (leaf, slot) = path.pop().unwrap();
+        self.release(leaf)?;
+        parent(&mut path, &mut self.root_page).remove_separator(slot.saturating_sub(1), slot)?;
+
+        loop {
+            if parent(&mut path, &mut self.root_page).item_count() > 0 {
+                return Ok(());
+            }
+            let (mut p, slot) = match path.pop() {
+                Some(top) => top,
+                None => return self.shrink_root()
+            };
+            let (only_child, count) = (p.ptr_at(0).unwrap(), p.count_at(0).unwrap_or_default());
+            let g = parent(&mut path, &mut self.root_page);
+            // the left sibling when there is one, the separator sitting between the two
+            let (sibling_slot, sep_i) = if slot > 0 { (slot - 1, slot - 1) } else { (slot + 1, slot) };
+            let mut sibling = Page::<K, V>::load_node(self.fd.clone(), g.ptr_at(sibling_slot).unwrap())?;
+            if sibling.page_type != PageType::INTERNAL {
+                return Err(corrupted(sibling.index, "leaves at different depths".to_owned()));
+            }
+            let sep = g.raw_key_at(sep_i).to_vec();
+            let n = sibling.item_count();
+            // the keys of both pages start with what their prefixes have in common, and that
+            // shorter prefix may leave the sibling no room for another key
+            let shared = sibling.prefix()[..common_len(sibling.prefix(), p.prefix())].to_vec();
+            let fits = match sibling.set_prefix(&shared) {
+                Ok(()) => !sibling.is_full(),
+                Err(e) if matches!(e.downcast_ref::<PageError>(), Some(PageError::Full)) => false,
+                Err(e) => return Err(e)
+            };
+            if fits {
+                // the separator comes down between the sibling's pointers and the only child
+                if slot > 0 {
+                    sibling.insert_separator(n, &sep, n + 1, only_child, count)?;
+                } else {
+                    sibling.insert_separator(0, &sep, 0, only_child, count)?;
+                }
+                g.add_count_at(sibling_slot, count as i64)?;
+                g.remove_separator(sep_i, slot)?;
+                self.release(p)?;
+                continue;
+            }
+            // the sibling's nearest pointer moves over, its key going up in place of the separator
+            p.set_prefix(&shared)?;
+            let moved = if slot > 0 {
+                let up = sibling.raw_key_at(n - 1).to_vec();
+                let moved = sibling.count_at(n).unwrap_or_default();
+                p.insert_separator(0, &sep, 0, sibling.ptr_at(n).unwrap(), moved)?;
+                g.set_raw_key_at(sep_i, &up)?;
+                sibling.remove_separator(n - 1, n)?;
+                moved
+            } else {
+                let up = sibling.raw_key_at(0).to_vec();
+                let moved = sibling.count_at(0).unwrap_or_default();
+                p.insert_separator(0, &sep, 1, sibling.ptr_at(0).unwrap(), moved)?;
+                g.set_raw_key_at(sep_i, &up)?;
+                sibling.remove_separator(0, 0)?;
+                moved
+            };
+            g.add_count_at(slot, moved as i64)?;
+            g.add_count_at(sibling_slot, -(moved as i64))?;
+            return Ok(());
+        }
+    }
+
+    fn shrink_root(&mut self) -> Result<()> {
+        let child = self.root_page.as_ref().unwrap().ptr_at(0).unwrap();
+        let child = Page::<K, V>::load_node(self.fd.clone(), child)?;
+        self.set_root_index(child.index);
+        let old_root = self.root_page.replace(child).unwrap();
+        self.release(old_root)
+    }
+}
+
+/// the page on top of `path`, or the root when the path is empty
+fn parent<'a, K, V>(path: &'a mut [(Page<K, V>, usize)], root: &'a mut Option<Page<K, V>>) -> &'a mut Page<K, V> {
+    match path.last_mut() {
+        Some((p, _)) => p,
+        None => root.as_mut().unwrap()
+    }
+}
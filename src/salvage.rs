@@ -0,0 +1,88 @@
+use crate::build::Builder;
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::page::{Page, PageType, PAGE_SIZE};
+use crate::pager::Pager;
+use crate::BTree;
+use anyhow::Result;
+use std::cmp::Ordering;
+use std::ffi::OsString;
+use std::fmt::Debug;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    /// opens a damaged tree file by ignoring its structure altogether: every page that still
+    /// reads as a sane leaf gives up its entries, which are bulk built into a fresh tree at `path`.
+    /// the damaged file is kept next to it with a `.corrupt` suffix.
+    /// returns the new tree and how many entries were recovered
+    pub fn open_salvage<P: AsRef<Path>>(path: P) -> Result<(Self, usize), BTreeError> {
+        let path = path.as_ref();
+        let (mut entries, page_size) = Self::scan_leaves(path)?;
+        // a key found in several leaves keeps the copy from the highest page index
+        entries.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        entries.dedup_by(|later, earlier| {
+            if later.0 == earlier.0 {
+                std::mem::swap(later, earlier);
+                true
+            } else {
+                false
+            }
+        });
+
+        let rebuilt = with_suffix(path, ".salvage");
+        let _ = fs::remove_file(&rebuilt);
+        let mut builder = Builder::<K, V>::create(&rebuilt, page_size)?;
+        for (k, v) in entries.iter() {
+            builder.push(k, v)?;
+        }
+        let recovered = builder.finish()?;
+
+        fs::rename(path, with_suffix(path, ".corrupt"))?;
+        fs::rename(&rebuilt, path)?;
+        Ok((BTree::open(path)?, recovered))
+    }
+
+    // entries in page index order, so later copies of a key come last, and the page size used
+    fn scan_leaves(path: &Path) -> Result<(Vec<(K, V)>, usize)> {
+        let file = OpenOptions::new().read(true).open(path)?;
+        // a damaged meta page may not even tell the page size any more
+        let page_size = Pager::stored_page_size(&file).unwrap_or(PAGE_SIZE);
+        let total_pages = (file.metadata()?.len() / page_size as u64) as u32;
+        let fd = Arc::new(Mutex::new(Pager::new(file, page_size)));
+        let capacity = Page::<K, V>::capacity(page_size, &PageType::LEAF);
+        let mut entries = Vec::new();
+        for index in 1..total_pages {
+            let page = match Page::<K, V>::load(fd.clone(), index) {
+                Ok(page) => page,
+                Err(_) => continue
+            };
+            if page.page_type != PageType::LEAF || page.item_count() > capacity {
+                continue;
+            }
+            let mut leaf = Vec::with_capacity(page.item_count());
+            for i in 0..page.item_count() {
+                match (page.key_at(i), page.value_at(i)) {
+                    (Some(k), Some(v)) => leaf.push((k, v)),
+                    _ => break
+                }
+            }
+            // keys out of order mean the page is garbage that happens to look like a leaf
+            if leaf.len() == page.item_count() && leaf.windows(2).all(|w| w[0].0 < w[1].0) {
+                entries.append(&mut leaf);
+            }
+        }
+        Ok((entries, page_size))
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
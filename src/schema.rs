@@ -0,0 +1,81 @@
+use crate::byte::{Encodable, Decodable, BinSizer, check_len};
+use crate::BTree;
+use crate::error::BTreeError;
+use anyhow::Result;
+use std::fmt::Debug;
+
+/// a value type whose encoding evolves over time. every version has to fit in
+/// `bin_size()` bytes, so leave room for growth when picking the first layout
+pub trait Schema: Encodable + Decodable + BinSizer {
+    /// the version written by `encode`
+    const VERSION: u8;
+
+    /// rebuilds a value from the encoding an older `version` wrote into `buf`
+    fn upgrade(version: u8, buf: &[u8]) -> Result<Self>;
+}
+
+/// stores a value behind a version byte and upgrades records of older versions as they are read
+#[derive(Debug, Clone, PartialEq)]
+pub struct Versioned<T>(pub T);
+
+impl<T: Schema> Versioned<T> {
+    /// the version a stored record was written with
+    pub fn stored_version(buf: &[u8]) -> u8 {
+        buf[0]
+    }
+}
+
+impl<T: Schema> BinSizer for Versioned<T> {
+    #[inline]
+    fn bin_size() -> usize {
+        1 + T::bin_size()
+    }
+}
+
+impl<T: Schema> Encodable for Versioned<T> {
+    fn encode(&self, buf: &mut [u8]) -> Result<usize> {
+        check_len(buf, Self::bin_size())?;
+        buf[0] = T::VERSION;
+        self.0.encode(&mut buf[1..])?;
+        Ok(Self::bin_size())
+    }
+}
+
+impl<T: Schema> Decodable for Versioned<T> {
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        check_len(buf, Self::bin_size())?;
+        let val = if buf[0] == T::VERSION {
+            T::decode(&buf[1..])?.0
+        } else {
+            T::upgrade(buf[0], &buf[1..Self::bin_size()])?
+        };
+        Ok((Versioned(val), Self::bin_size()))
+    }
+}
+
+impl<K, T> BTree<K, Versioned<T>>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        T: Schema + Debug + Clone
+{
+    /// rewrites every record stored with an older schema version in the current one,
+    /// returning how many were upgraded
+    pub fn upgrade_values(&mut self) -> Result<usize, BTreeError> {
+        let mut outdated = Vec::new();
+        {
+            let mut iter = self.streaming_iter(..);
+            while let Some((k, v)) = iter.next() {
+                if Versioned::<T>::stored_version(v) != T::VERSION {
+                    outdated.push(K::decode(k)?.0);
+                }
+            }
+        }
+        for k in outdated.iter() {
+            // decoding upgrades, setting writes it back in the current version
+            if let Some(v) = self.get(k) {
+                self.set(k, &v)?;
+            }
+        }
+        Ok(outdated.len())
+    }
+}
@@ -0,0 +1,79 @@
+use crate::byte::{Encodable, Decodable, BinSizer, check_len};
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::ops::{Deref, DerefMut};
+
+const LEN_SIZE: usize = 4;
+
+/// stores any serde type as its bincode encoding, up to `MAX` bytes of it. each one takes
+/// `MAX` bytes plus a 4 byte length on the page whatever its own length, and encoding one
+/// that serializes longer fails. as a key it orders by `T`
+#[derive(Debug, Clone, Default, PartialEq, PartialOrd)]
+pub struct SerdeCodec<T, const MAX: usize>(pub T);
+
+impl<T, const MAX: usize> SerdeCodec<T, MAX> {
+    pub fn new(value: T) -> Self {
+        SerdeCodec(value)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T, const MAX: usize> From<T> for SerdeCodec<T, MAX> {
+    fn from(value: T) -> Self {
+        SerdeCodec(value)
+    }
+}
+
+impl<T, const MAX: usize> Deref for SerdeCodec<T, MAX> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T, const MAX: usize> DerefMut for SerdeCodec<T, MAX> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T, const MAX: usize> BinSizer for SerdeCodec<T, MAX> {
+    #[inline]
+    fn bin_size() -> usize {
+        LEN_SIZE + MAX
+    }
+}
+
+impl<T: Serialize, const MAX: usize> Encodable for SerdeCodec<T, MAX> {
+    fn encode(&self, buf: &mut [u8]) -> Result<usize> {
+        check_len(buf, Self::bin_size())?;
+        let len = bincode::serialized_size(&self.0)? as usize;
+        if len > MAX {
+            return Err(anyhow!("{} serialized bytes do not fit in {}", len, MAX));
+        }
+        (len as u32).encode(buf)?;
+        bincode::serialize_into(&mut buf[LEN_SIZE..LEN_SIZE + len], &self.0)?;
+        // the unused tail is zeroed so equal values always store the same bytes
+        for b in buf[LEN_SIZE + len..Self::bin_size()].iter_mut() {
+            *b = 0;
+        }
+        Ok(Self::bin_size())
+    }
+}
+
+impl<T: DeserializeOwned, const MAX: usize> Decodable for SerdeCodec<T, MAX> {
+    fn decode(buf: &[u8]) -> Result<(Self, usize)> {
+        check_len(buf, Self::bin_size())?;
+        let len = u32::decode(buf)?.0 as usize;
+        if len > MAX {
+            return Err(anyhow!("a serialized length of {} over the {} byte maximum", len, MAX));
+        }
+        let value = bincode::deserialize(&buf[LEN_SIZE..LEN_SIZE + len])?;
+        Ok((SerdeCodec(value), Self::bin_size()))
+    }
+}
@@ -0,0 +1,108 @@
+use crate::build::Builder;
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::BTree;
+use crate::error::BTreeError;
+use anyhow::{anyhow, Result};
+use std::fmt::Debug;
+use std::fs;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    /// bulk builds one new tree file per key range, `ranges[i]` going to `paths[i]`,
+    /// and returns the number of entries in each shard. the ranges have to be in ascending
+    /// order without overlapping, and a shard failing takes the ones built before it away
+    pub fn split_into<R, P>(&self, ranges: &[R], paths: &[P]) -> Result<Vec<usize>, BTreeError>
+        where
+            R: RangeBounds<K>,
+            P: AsRef<Path>
+    {
+        if ranges.len() != paths.len() {
+            return Err(anyhow!("{} ranges given for {} shard files", ranges.len(), paths.len()).into());
+        }
+        for (i, pair) in ranges.windows(2).enumerate() {
+            if !sorts_before(pair[0].end_bound(), pair[1].start_bound()) {
+                return Err(anyhow!("shard range {} overlaps range {} or comes after it", i + 1, i).into());
+            }
+        }
+        let mut counts = Vec::with_capacity(ranges.len());
+        for (range, path) in ranges.iter().zip(paths) {
+            match self.export_range((range.start_bound().cloned(), range.end_bound().cloned()), path) {
+                Ok(count) => counts.push(count),
+                Err(e) => {
+                    for built in &paths[..counts.len()] {
+                        let _ = fs::remove_file(built);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(counts)
+    }
+
+    /// bulk builds a standalone, densely packed tree file holding only the entries in `range`,
+    /// and returns how many entries it got
+    pub fn export_range<R, P>(&self, range: R, path: P) -> Result<usize, BTreeError>
+        where
+            R: RangeBounds<K>,
+            P: AsRef<Path>
+    {
+        let mut builder = Builder::<K, V>::create(path, self.page_size())?;
+        for (k, v) in self.range(range) {
+            builder.push(&k, &v)?;
+        }
+        Ok(builder.finish()?)
+    }
+}
+
+/// whether every key up to `end` sorts before every key from `start` on
+fn sorts_before<K: PartialOrd>(end: Bound<&K>, start: Bound<&K>) -> bool {
+    match (end, start) {
+        (Bound::Unbounded, _) | (_, Bound::Unbounded) => false,
+        (Bound::Included(end), Bound::Included(start)) => end < start,
+        (Bound::Included(end), Bound::Excluded(start))
+        | (Bound::Excluded(end), Bound::Included(start))
+        | (Bound::Excluded(end), Bound::Excluded(start)) => end <= start,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BTree;
+    use std::fs;
+
+    #[test]
+    fn overlapping_ranges_or_a_shard_failing_leave_no_shards() {
+        let path = std::env::temp_dir().join(format!("btree-shard-test-{}", std::process::id()));
+        let shards: Vec<_> = (0..3)
+            .map(|i| std::env::temp_dir().join(format!("btree-shard-test-{}-{}", std::process::id(), i)))
+            .collect();
+        let _ = fs::remove_file(&path);
+        for shard in &shards {
+            let _ = fs::remove_file(shard);
+        }
+        let mut tree = BTree::<u32, u64>::open_or_create(&path).unwrap();
+        for i in 0..3000u32 {
+            tree.set(&i, &(i as u64)).unwrap();
+        }
+        assert!(tree.split_into(&[0..1000, 500..2000, 2000..3000], &shards).is_err());
+        assert!(tree.split_into(&[1000..2000, 0..1000, 2000..3000], &shards).is_err());
+        assert!(shards.iter().all(|shard| !shard.exists()));
+        // the second shard's path is taken, the first one built goes away again
+        fs::write(&shards[1], b"taken").unwrap();
+        assert!(tree.split_into(&[0..1000, 1000..2000, 2000..3000], &shards).is_err());
+        assert!(!shards[0].exists() && !shards[2].exists());
+        assert_eq!(fs::read(&shards[1]).unwrap(), b"taken");
+        fs::remove_file(&shards[1]).unwrap();
+        assert_eq!(tree.split_into(&[0..1000, 1000..2000, 2000..3000], &shards).unwrap(), vec![1000, 1000, 1000]);
+        drop(tree);
+        fs::remove_file(&path).unwrap();
+        for shard in &shards {
+            fs::remove_file(shard).unwrap();
+        }
+    }
+}
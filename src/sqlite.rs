@@ -0,0 +1,50 @@
+use crate::build::Builder;
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::page::PAGE_SIZE;
+use crate::BTree;
+use anyhow::Result;
+use rusqlite::types::{FromSql, ToSql};
+use rusqlite::{params, Connection};
+use std::fmt::Debug;
+use std::path::Path;
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone + ToSql + FromSql,
+        V: Encodable + Decodable + BinSizer + Debug + Clone + ToSql + FromSql
+{
+    /// bulk builds a new tree file at `path` from a sqlite `table` whose first column holds
+    /// unique keys and whose second column holds the values, returning the tree and its entry count
+    pub fn import_sqlite<P: AsRef<Path>>(path: P, conn: &Connection, table: &str) -> Result<(Self, usize), BTreeError> {
+        let mut stmt = conn.prepare(&format!("SELECT * FROM {} ORDER BY 1", quote(table)))?;
+        let mut rows = stmt.query([])?;
+        let mut builder = Builder::<K, V>::create(path.as_ref(), PAGE_SIZE)?;
+        while let Some(row) = rows.next()? {
+            builder.push(&row.get(0)?, &row.get(1)?)?;
+        }
+        let count = builder.finish()?;
+        Ok((BTree::open(path)?, count))
+    }
+
+    /// writes every entry into a new sqlite `table` with a `key` primary key and a `value`
+    /// column, in one transaction, returning how many rows were inserted
+    pub fn export_sqlite(&self, conn: &mut Connection, table: &str) -> Result<usize, BTreeError> {
+        let tx = conn.transaction()?;
+        tx.execute(&format!("CREATE TABLE {} (key PRIMARY KEY, value)", quote(table)), [])?;
+        let mut count = 0;
+        {
+            let mut stmt = tx.prepare(&format!("INSERT INTO {} (key, value) VALUES (?1, ?2)", quote(table)))?;
+            for (k, v) in self.iter() {
+                stmt.execute(params![k, v])?;
+                count += 1;
+            }
+        }
+        tx.commit()?;
+        Ok(count)
+    }
+}
+
+fn quote(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
@@ -0,0 +1,99 @@
+use crate::page::{Page, PageType, Stat, corrupted};
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::{BTree, MAX_DEPTH};
+use anyhow::Result;
+use std::fmt::Debug;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// lifetime operation counters of a tree file, persisted in its meta page
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    /// `set` calls that added a new key
+    pub inserts: u64,
+    /// `set` calls that replaced the value of an existing key
+    pub overwrites: u64,
+    pub deletes: u64,
+    /// leaf and internal page splits
+    pub splits: u64,
+    pub last_compaction: Option<SystemTime>,
+}
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    pub fn stats(&self) -> Stats {
+        let meta_page = self.meta_page.as_ref().unwrap();
+        Stats {
+            inserts: meta_page.stat(Stat::Inserts),
+            overwrites: meta_page.stat(Stat::Overwrites),
+            deletes: meta_page.stat(Stat::Deletes),
+            splits: meta_page.stat(Stat::Splits),
+            last_compaction: self.stat_time(Stat::LastCompaction),
+        }
+    }
+
+    /// when the last write to the tree happened, as recorded in the meta page,
+    /// which unlike the file mtime is not disturbed by page writes from reads
+    pub fn last_modified(&self) -> Option<SystemTime> {
+        self.stat_time(Stat::LastModified)
+    }
+
+    // timestamps are kept as milliseconds since the unix epoch, 0 meaning never
+    fn stat_time(&self, stat: Stat) -> Option<SystemTime> {
+        match self.meta_page.as_ref().unwrap().stat(stat) {
+            0 => None,
+            millis => Some(UNIX_EPOCH + Duration::from_millis(millis))
+        }
+    }
+
+    pub(crate) fn touch(&mut self, stat: Stat) {
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64);
+        self.meta_page.as_mut().unwrap().set_stat(stat, millis)
+    }
+
+    pub(crate) fn bump_stat(&mut self, stat: Stat) {
+        self.meta_page.as_mut().unwrap().bump_stat(stat);
+        // inserts and deletes are what moves the entry count
+        match stat {
+            Stat::Inserts => self.set_entries(self.entries() + 1),
+            Stat::Deletes => self.set_entries(self.entries().saturating_sub(1)),
+            Stat::Splits => self.fd.lock().unwrap().count_split(),
+            _ => {}
+        }
+    }
+
+    /// how many entries the tree holds, as counted in the meta page. a file from before the
+    /// count was kept gets counted when it is opened for writing; opened read only instead,
+    /// every call walks its leaves, which stops short at a corrupted page
+    pub fn len(&self) -> u64 {
+        if self.meta_page.as_ref().unwrap().format_version() >= 2 {
+            return self.entries();
+        }
+        self.count_entries(self.root_page.as_ref().unwrap()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// counts the entries under `root` leaf by leaf
+    pub(crate) fn count_entries(&self, root: &Page<K, V>) -> Result<u64> {
+        let mut entries = 0;
+        let mut todo = vec![(root.snapshot(), 0)];
+        while let Some((p, depth)) = todo.pop() {
+            if p.page_type == PageType::LEAF {
+                entries += p.item_count() as u64;
+                continue;
+            }
+            if depth == MAX_DEPTH {
+                return Err(corrupted(p.index, "the tree loops back on itself".to_owned()));
+            }
+            for i in 0..=p.item_count() {
+                todo.push((Page::<K, V>::load_node(self.fd.clone(), p.ptr_at(i).unwrap())?, depth + 1));
+            }
+        }
+        Ok(entries)
+    }
+}
@@ -0,0 +1,125 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::iter::Iter;
+use crate::BTree;
+use anyhow::Result;
+use std::fmt::Debug;
+use std::ops::RangeBounds;
+use std::path::Path;
+
+/// a record stored whole as the value of a tree, under a key taken from one of its fields.
+/// `define_row!` writes the struct, its codec and this impl in one go
+pub trait Row: Encodable + Decodable + BinSizer + Debug + Clone {
+    type Key: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone;
+
+    fn key(&self) -> Self::Key;
+}
+
+/// a tree file holding rows of one type, keyed by their key field
+pub struct Table<R: Row> {
+    tree: BTree<R::Key, R>,
+}
+
+impl<R: Row> Table<R> {
+    /// opens the table at `path`, creating it when missing, failing as `BTree::open_or_create` does
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, BTreeError> {
+        Ok(Table {
+            tree: BTree::open_or_create(path)?,
+        })
+    }
+
+    /// stores `row`, replacing any row with the same key
+    pub fn insert(&mut self, row: &R) -> Result<(), BTreeError> {
+        self.tree.set(&row.key(), row)
+    }
+
+    pub fn get(&self, key: &R::Key) -> Option<R> {
+        self.tree.get(key)
+    }
+
+    pub fn iter(&self) -> Rows<'_, R> {
+        self.range(..)
+    }
+
+    /// rows whose key falls in `range`, in key order
+    pub fn range<B: RangeBounds<R::Key>>(&self, range: B) -> Rows<'_, R> {
+        Rows {
+            inner: self.tree.range(range),
+        }
+    }
+
+    pub fn tree(&mut self) -> &mut BTree<R::Key, R> {
+        &mut self.tree
+    }
+}
+
+pub struct Rows<'a, R: Row> {
+    inner: Iter<'a, R::Key, R>,
+}
+
+impl<'a, R: Row> Iterator for Rows<'a, R> {
+    type Item = R;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, row)| row)
+    }
+}
+
+/// declares a row struct whose fields are encoded back to back, the key field first:
+///
+/// ```ignore
+/// define_row! {
+///     pub struct User key id: u32 {
+///         age: u8,
+///         name: Name,
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! define_row {
+    ($vis: vis struct $name: ident key $key: ident: $key_ty: ty { $($field: ident: $ty: ty),* $(,)? }) => {
+        #[derive(Debug, Clone, PartialEq)]
+        $vis struct $name {
+            pub $key: $key_ty,
+            $(pub $field: $ty),*
+        }
+
+        impl $crate::BinSizer for $name {
+            #[inline]
+            fn bin_size() -> usize {
+                <$key_ty as $crate::BinSizer>::bin_size() $(+ <$ty as $crate::BinSizer>::bin_size())*
+            }
+        }
+
+        impl $crate::Encodable for $name {
+            #[allow(unused_mut)]
+            fn encode(&self, buf: &mut [u8]) -> anyhow::Result<usize> {
+                $crate::check_len(buf, <Self as $crate::BinSizer>::bin_size())?;
+                let mut at = $crate::Encodable::encode(&self.$key, buf)?;
+                $(at += $crate::Encodable::encode(&self.$field, &mut buf[at..])?;)*
+                Ok(at)
+            }
+        }
+
+        impl $crate::Decodable for $name {
+            #[allow(unused_mut)]
+            fn decode(buf: &[u8]) -> anyhow::Result<(Self, usize)> {
+                $crate::check_len(buf, <Self as $crate::BinSizer>::bin_size())?;
+                let ($key, mut at) = <$key_ty as $crate::Decodable>::decode(buf)?;
+                $(
+                    let ($field, size) = <$ty as $crate::Decodable>::decode(&buf[at..])?;
+                    at += size;
+                )*
+                Ok((Self { $key, $($field),* }, at))
+            }
+        }
+
+        impl $crate::Row for $name {
+            type Key = $key_ty;
+
+            fn key(&self) -> Self::Key {
+                self.$key.clone()
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::journal::journal_path;
+use crate::BTree;
+use anyhow::Result;
+use std::fmt::Debug;
+
+/// writes to a tree held back until `commit`, which makes all of them durable or none.
+/// dropping a transaction without committing it rolls it back
+pub struct Txn<'a, K, V> {
+    tree: &'a mut BTree<K, V>,
+    // every write in the order it was made, None deleting the key
+    writes: Vec<(K, Option<V>)>,
+}
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    pub fn begin(&mut self) -> Txn<'_, K, V> {
+        Txn { tree: self, writes: Vec::new() }
+    }
+
+    /// applies `writes` with every page they touch kept in memory, then hands those to the
+    /// pager to write out as one. on failure the tree goes back to what the file held before
+    pub(crate) fn apply_atomically(&mut self, writes: &[(K, Option<V>)]) -> Result<()> {
+        if self.read_only {
+            return Err(BTreeError::ReadOnly { path: self.path.clone() }.into());
+        }
+        self.sync()?;
+        self.fd.lock().unwrap().defer()?;
+        // the journal makes the whole commit atomic, copy-on-write sits it out
+        let cow = self.cow.take();
+        let applied = writes.iter()
+            .try_for_each(|(key, value)| match value {
+                Some(value) => self.set(key, value),
+                None => self.remove(key).map(|_| ())
+            })
+            .map_err(anyhow::Error::from)
+            .and_then(|_| self.sync())
+            .and_then(|_| self.fd.lock().unwrap().commit_deferred(&journal_path(&self.path)));
+        let applied = match applied {
+            Ok(()) => Ok(()),
+            Err(e) => self.abandon().and(Err(e))
+        };
+        if cow.is_some() {
+            self.fd.lock().unwrap().defer()?;
+            self.cow = cow;
+        }
+        applied
+    }
+}
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    /// throws away the pages a deferred write kept in memory and puts back whatever of them
+    /// reached the file, then loads the tree again from what the file holds
+    pub(crate) fn abandon(&mut self) -> Result<()> {
+        // the pager still defers, what these write on the way out is discarded with the rest
+        self.root_page = None;
+        self.meta_page = None;
+        let mut fd = self.fd.lock().unwrap();
+        fd.discard_deferred();
+        fd.roll_back(&journal_path(&self.path))?;
+        drop(fd);
+        self.init_load()
+    }
+}
+
+impl<'a, K, V> Txn<'a, K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug + Clone,
+        V: Encodable + Decodable + BinSizer + Debug + Clone
+{
+    pub fn set(&mut self, key: &K, value: &V) {
+        self.writes.push((key.clone(), Some(value.clone())));
+    }
+
+    pub fn delete(&mut self, key: &K) {
+        self.writes.push((key.clone(), None));
+    }
+
+    /// the value under `key` with the writes made so far in this transaction applied
+    pub fn get(&self, key: &K) -> Option<V> {
+        match self.writes.iter().rev().find(|(k, _)| k == key) {
+            Some((_, value)) => value.clone(),
+            None => self.tree.get(key)
+        }
+    }
+
+    /// makes every write durable at once. a crash before this returns leaves the file as it
+    /// was before the transaction, rolled back the next time it is opened for writing; so does
+    /// a failure, which leaves the tree as it was too. every page the writes touch stays in
+    /// memory until it is written out
+    pub fn commit(self) -> Result<(), BTreeError> {
+        if self.writes.is_empty() {
+            return Ok(());
+        }
+        Ok(self.tree.apply_atomically(&self.writes)?)
+    }
+
+    pub fn rollback(self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::journal::{self, journal_path};
+    use crate::{BTree, PAGE_SIZE};
+    use std::fs::{self, OpenOptions};
+
+    #[test]
+    fn reopening_rolls_back_a_commit_cut_short() {
+        let path = std::env::temp_dir().join(format!("btree-txn-test-{}", std::process::id()));
+        let saved = std::env::temp_dir().join(format!("btree-txn-test-{}-saved", std::process::id()));
+        let _ = fs::remove_file(&path);
+        {
+            let mut tree = BTree::<u32, u64>::open_or_create(&path).unwrap();
+            for i in 0..2000u32 {
+                tree.set(&i, &(i as u64)).unwrap();
+            }
+        }
+        let before: Vec<(u32, u64)> = BTree::open_read_only(&path).unwrap().iter().collect();
+        // the old image of every page, a journal of whatever the commit may overwrite
+        let mut file = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+        let pages = (file.metadata().unwrap().len() / PAGE_SIZE as u64) as u32;
+        journal::write(&saved, &mut file, PAGE_SIZE, &(0..pages).collect::<Vec<u32>>()).unwrap();
+        drop(file);
+        {
+            let mut tree = BTree::<u32, u64>::open(&path).unwrap();
+            let mut txn = tree.begin();
+            for i in 1000..5000u32 {
+                txn.set(&i, &0);
+            }
+            for i in 0..500u32 {
+                txn.delete(&i);
+            }
+            txn.commit().unwrap();
+        }
+        // the commit got its pages out and never removed its journal
+        fs::rename(&saved, journal_path(&path)).unwrap();
+
+        let tree = BTree::<u32, u64>::open(&path).unwrap();
+        assert!(!journal_path(&path).exists());
+        assert_eq!(tree.len(), before.len() as u64);
+        assert_eq!(tree.iter().collect::<Vec<(u32, u64)>>(), before);
+        assert!(tree.verify().unwrap().is_empty());
+        drop(tree);
+        fs::remove_file(&path).unwrap();
+    }
+}
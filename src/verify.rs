@@ -0,0 +1,244 @@
+use crate::byte::{Encodable, Decodable, BinSizer};
+use crate::error::BTreeError;
+use crate::page::{Page, PageType, Stat};
+use crate::{BTree, MAX_DEPTH};
+use anyhow::Result;
+use std::fmt::Debug;
+use std::io;
+use thiserror::Error;
+
+/// something wrong with a tree file that `BTree::verify` came across, with the page it is on
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum Problem {
+    #[error("page {page} does not load: {reason}")]
+    Unreadable { page: u32, reason: String },
+    #[error("page {from} points at page {page}, outside of {total_pages} pages")]
+    BadPointer { from: u32, page: u32, total_pages: u32 },
+    #[error("page {page} is reached a second time, from page {from}")]
+    Shared { from: u32, page: u32 },
+    #[error("page {from} points at a {found} page {page}, not a {expected} page")]
+    WrongType { from: u32, page: u32, found: String, expected: &'static str },
+    #[error("page {page} holds no entries")]
+    Empty { page: u32 },
+    #[error("key {at} of page {page} does not sort after the key before it")]
+    OutOfOrder { page: u32, at: usize },
+    #[error("key {at} of page {page} is the first to sort outside the separators above it")]
+    OutOfRange { page: u32, at: usize },
+    #[error("leaf {page} sits {depth} pages below the root, other leaves {expected}")]
+    UnevenDepth { page: u32, depth: usize, expected: usize },
+    #[error("the tree under page {page} goes more than {} pages deep", MAX_DEPTH)]
+    TooDeep { page: u32 },
+    #[error("the overflow chain from page {page} has {pages} pages, values take {expected}")]
+    ShortChain { page: u32, pages: usize, expected: usize },
+    #[error("{} holds {found} entries, {recorded} are recorded", .tree.as_deref().map_or("the tree".to_owned(), |n| format!("tree {:?}", n)))]
+    EntryCount { tree: Option<String>, recorded: u64, found: u64 },
+    #[error("page {from} counts {recorded} entries under page {page}, which has {found}")]
+    SubtreeCount { from: u32, page: u32, recorded: u64, found: u64 },
+    #[error("the free list holds {found} pages, {recorded} are recorded")]
+    FreeCount { recorded: u32, found: u32 },
+    #[error("page {page} is reached from neither a tree, the free list nor the spares")]
+    Unreachable { page: u32 },
+}
+
+impl<K, V> BTree<K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    /// walks every page of the file: the file's own tree and the named trees with their
+    /// overflow chains, the catalog, the free list and the spares, checking pages are of the
+    /// type their place calls for, point inside the file and are reached once, that keys sort
+    /// within and across pages, leaves all sit as deep and the recorded counts add up, those
+    /// internal pages keep of the entries under each child included, and
+    /// that no page is left out. returns every problem found, none for a sound file; fails
+    /// only when reading the file does
+    pub fn verify(&self) -> Result<Vec<Problem>, BTreeError> {
+        let meta_page = self.meta_page.as_ref().unwrap();
+        let mut walk = Walk {
+            tree: self,
+            total_pages: meta_page.total_pages(),
+            reached: vec![false; meta_page.total_pages() as usize],
+            problems: Vec::new(),
+        };
+        walk.reached[0] = true;
+        if let Some(second) = meta_page.second_meta() {
+            walk.reached[second as usize] = true;
+        }
+        let overflow = self.cow.iter().flat_map(|cow| cow.overflow.iter());
+        for index in meta_page.spares().iter().chain(overflow) {
+            if let Some(reached) = walk.reached.get_mut(*index as usize) {
+                *reached = true;
+            }
+        }
+
+        // files from before the entry count was kept have none recorded
+        let recorded = (meta_page.format_version() >= 2).then(|| meta_page.stat(Stat::Entries));
+        walk.tree(0, meta_page.root_index(), None, recorded)?;
+        let catalog = meta_page.catalog_index();
+        if catalog != 0 {
+            if let Some(p) = walk.load(0, catalog, &[PageType::CATALOG], "catalog")? {
+                for i in 0..p.catalog_len() {
+                    walk.tree(catalog, p.catalog_root(i), Some(p.catalog_name(i)), Some(p.catalog_entries(i)))?;
+                }
+            }
+        }
+
+        let mut found = 0;
+        let (mut from, mut index) = (0, meta_page.free_head());
+        while index != 0 {
+            match walk.load(from, index, &[PageType::FREE], "free")? {
+                Some(p) => {
+                    found += 1;
+                    from = index;
+                    index = p.next_free();
+                }
+                None => break
+            }
+        }
+        if found != meta_page.free_count() {
+            walk.problems.push(Problem::FreeCount { recorded: meta_page.free_count(), found });
+        }
+
+        let unreached = (0..walk.total_pages).filter(|i| !walk.reached[*i as usize]);
+        let unreached: Vec<Problem> = unreached.map(|page| Problem::Unreachable { page }).collect();
+        walk.problems.extend(unreached);
+        Ok(walk.problems)
+    }
+}
+
+struct Walk<'a, K, V> {
+    tree: &'a BTree<K, V>,
+    total_pages: u32,
+    reached: Vec<bool>,
+    problems: Vec<Problem>,
+}
+
+impl<'a, K, V> Walk<'a, K, V>
+    where
+        K: Encodable + Decodable + BinSizer + PartialEq + PartialOrd + Debug,
+        V: Encodable + Decodable + BinSizer + Debug
+{
+    // the tree rooted at page `root` that page `from` lists, named `name` unless it is the file's own
+    fn tree(&mut self, from: u32, root: u32, name: Option<&str>, recorded: Option<u64>) -> Result<()> {
+        // every page still to look at, with how deep it sits, the encoded keys it has to lie
+        // between and the count of entries its parent keeps for it
+        let mut todo = vec![(from, root, 0, None::<Vec<u8>>, None::<Vec<u8>>, None)];
+        let mut leaf_depth = None;
+        let mut entries = 0;
+        while let Some((from, index, depth, low, high, count)) = todo.pop() {
+            let p = match self.load(from, index, &[PageType::INTERNAL, PageType::LEAF], "tree")? {
+                Some(p) => p,
+                None => continue
+            };
+            // each page checks its own against its parent's, so those of a whole subtree add up
+            let found = (p.page_type == PageType::LEAF || p.counted()).then(|| p.total());
+            if let (Some(recorded), Some(found)) = (count, found) {
+                if recorded != found {
+                    self.problems.push(Problem::SubtreeCount { from, page: index, recorded, found });
+                }
+            }
+            let keys: Vec<K> = (0..p.item_count()).filter_map(|i| p.key_at(i)).collect();
+            let low_key = low.as_ref().and_then(|low| K::decode(low).ok()).map(|(k, _)| k);
+            let high_key = high.as_ref().and_then(|high| K::decode(high).ok()).map(|(k, _)| k);
+            for at in 1..keys.len() {
+                if keys[at] <= keys[at - 1] {
+                    self.problems.push(Problem::OutOfOrder { page: index, at });
+                }
+            }
+            // a split copies the first key of the right page up, so the lower bound is inclusive.
+            // a page hung under the wrong separator only counts once
+            let outside = |key: &K| low_key.as_ref().is_some_and(|low| key < low) || high_key.as_ref().is_some_and(|high| key >= high);
+            if let Some(at) = keys.iter().position(outside) {
+                self.problems.push(Problem::OutOfRange { page: index, at });
+            }
+            if p.page_type == PageType::LEAF {
+                entries += p.item_count() as u64;
+                if keys.is_empty() && depth > 0 {
+                    self.problems.push(Problem::Empty { page: index });
+                }
+                match leaf_depth {
+                    Some(expected) if expected != depth => self.problems.push(Problem::UnevenDepth { page: index, depth, expected }),
+                    Some(_) => {}
+                    None => leaf_depth = Some(depth)
+                }
+                for i in 0..p.item_count() {
+                    if let Some(first) = p.spilled_at(i) {
+                        self.chain(index, first)?;
+                    }
+                }
+                continue;
+            }
+            if keys.is_empty() {
+                self.problems.push(Problem::Empty { page: index });
+                continue;
+            }
+            if depth == MAX_DEPTH {
+                self.problems.push(Problem::TooDeep { page: root });
+                continue;
+            }
+            for i in (0..=keys.len()).rev() {
+                let low = if i == 0 { low.clone() } else { Some(p.raw_key_at(i - 1).to_vec()) };
+                let high = if i == keys.len() { high.clone() } else { Some(p.raw_key_at(i).to_vec()) };
+                todo.push((index, p.ptr_at(i).unwrap(), depth + 1, low, high, p.count_at(i)));
+            }
+        }
+        if let Some(recorded) = recorded.filter(|recorded| *recorded != entries) {
+            let tree = name.map(|name| name.to_owned());
+            self.problems.push(Problem::EntryCount { tree, recorded, found: entries });
+        }
+        Ok(())
+    }
+
+    fn chain(&mut self, from: u32, first: u32) -> Result<()> {
+        let expected = Page::<K, V>::chain_len(self.tree.page_size());
+        let (mut from, mut index, mut pages) = (from, first, 0);
+        while pages < expected {
+            let p = match self.load(from, index, &[PageType::OVERFLOW], "overflow")? {
+                Some(p) => p,
+                None => return Ok(())
+            };
+            pages += 1;
+            from = index;
+            index = p.chain_next();
+            if index == 0 {
+                break;
+            }
+        }
+        if pages < expected {
+            self.problems.push(Problem::ShortChain { page: first, pages, expected });
+        }
+        Ok(())
+    }
+
+    /// page `index` that page `from` points at, which has to be of one of the types `pts`;
+    /// None after noting why it cannot be looked into
+    fn load(&mut self, from: u32, index: u32, pts: &[PageType], expected: &'static str) -> Result<Option<Page<K, V>>> {
+        if index == 0 || index >= self.total_pages {
+            self.problems.push(Problem::BadPointer { from, page: index, total_pages: self.total_pages });
+            return Ok(None);
+        }
+        if self.reached[index as usize] {
+            self.problems.push(Problem::Shared { from, page: index });
+            return Ok(None);
+        }
+        self.reached[index as usize] = true;
+        // the root may hold changes not synced yet
+        let root = self.tree.root_page.as_ref().filter(|root| root.index == index);
+        let p = match root.map_or_else(|| Page::<K, V>::load(self.tree.fd.clone(), index), |root| Ok(root.snapshot())) {
+            Ok(p) => p,
+            Err(e) => {
+                // a page cut off by the end of the file is as broken as one that does not parse
+                if e.downcast_ref::<io::Error>().is_some_and(|e| e.kind() != io::ErrorKind::UnexpectedEof) {
+                    return Err(e);
+                }
+                self.problems.push(Problem::Unreadable { page: index, reason: e.to_string() });
+                return Ok(None);
+            }
+        };
+        if !pts.contains(&p.page_type) {
+            self.problems.push(Problem::WrongType { from, page: index, found: format!("{:?}", p.page_type), expected });
+            return Ok(None);
+        }
+        Ok(Some(p))
+    }
+}